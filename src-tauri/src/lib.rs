@@ -1,16 +1,19 @@
 use serde::{Deserialize, Serialize};
-use tauri::Manager;
+use tauri::{Emitter, Manager};
 
 mod modules;
 use modules::ssh;
 use modules::csv_parser;
 use modules::images;
 use modules::deployment;
+use modules::medusa;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct ServerConfig {
     ip: String,
     domain: String,
+    #[serde(rename = "multiTenant", default)]
+    multi_tenant: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -22,9 +25,49 @@ struct DeployConfig {
     identity: serde_json::Value,
     design: serde_json::Value,
     payment: serde_json::Value,
+    /// Empty when the products stay in `ImportedCatalog`
+    #[serde(default)]
     products: Vec<serde_json::Value>,
     #[serde(rename = "imagesMapping")]
     images_mapping: serde_json::Value,
+    #[serde(rename = "imageOptions", default)]
+    image_options: images::OptimizeOptions,
+}
+
+/// Products of the last import. Large catalogs are kept here instead of in
+/// the wizard, which only receives a summary and pages of products.
+#[derive(Default)]
+struct ImportedCatalog(std::sync::Mutex<Vec<csv_parser::Product>>);
+
+impl ImportedCatalog {
+    /// `sent` when the wizard sent products, otherwise the imported ones.
+    fn products(&self, sent: Option<Vec<csv_parser::Product>>) -> Result<Vec<csv_parser::Product>, String> {
+        if let Some(products) = sent {
+            return Ok(products);
+        }
+        let products = self.0.lock().map_err(|e| e.to_string())?.clone();
+        if products.is_empty() {
+            return Err("No products imported, import the products file again".to_string());
+        }
+        Ok(products)
+    }
+
+    fn replace(&self, products: Vec<csv_parser::Product>) -> Result<(), String> {
+        *self.0.lock().map_err(|e| e.to_string())? = products;
+        Ok(())
+    }
+
+    fn page(&self, page: usize, page_size: usize) -> Result<csv_parser::ProductPage, String> {
+        let products = self.0.lock().map_err(|e| e.to_string())?;
+        Ok(csv_parser::page(&products, page, page_size))
+    }
+
+    fn values(&self) -> Result<Vec<serde_json::Value>, String> {
+        self.products(None)?
+            .iter()
+            .map(|p| serde_json::to_value(p).map_err(|e| e.to_string()))
+            .collect()
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -32,6 +75,8 @@ struct DeployResult {
     url: String,
     #[serde(rename = "webhookUrl")]
     webhook_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ports: Option<deployment::LocalPorts>,
 }
 
 // SSH Connection Command
@@ -41,46 +86,201 @@ async fn connect_ssh(ip: String, _domain: String) -> Result<String, String> {
         .map_err(|e| format!("Failed to connect: {}", e))
 }
 
-// Read CSV File Command
+// Preview Products File Command: headers, samples and suggested column mapping
+#[tauri::command]
+async fn preview_products_file(path: String, sheet: Option<String>) -> Result<csv_parser::HeaderPreview, String> {
+    tokio::task::spawn_blocking(move || {
+        csv_parser::preview_products_file(std::path::Path::new(&path), sheet.as_deref())
+    })
+    .await
+    .map_err(|e| format!("Failed to read products file: {}", e))?
+    .map_err(|e| format!("Failed to read products file: {}", e))
+}
+
+// Import Products File Command: streams the file, emitting "import-progress",
+// keeps the products in the backend and returns a summary with the first page
+#[tauri::command]
+async fn import_products_file(
+    path: String,
+    options: Option<csv_parser::ImportOptions>,
+    app: tauri::AppHandle,
+    catalog: tauri::State<'_, ImportedCatalog>,
+) -> Result<csv_parser::ImportSummary, String> {
+    let emitter = app.clone();
+    let mut report = tokio::task::spawn_blocking(move || {
+        csv_parser::stream_products_file(std::path::Path::new(&path), &options.unwrap_or_default(), |progress| {
+            let _ = emitter.emit("import-progress", progress);
+        })
+    })
+    .await
+    .map_err(|e| format!("Failed to parse products file: {}", e))?
+    .map_err(|e| format!("Failed to parse products file: {}", e))?;
+
+    let (summary, products) = csv_parser::ImportSummary::take(&mut report);
+    catalog.replace(products)?;
+    Ok(summary)
+}
+
+// Imported Products Page Command
+#[tauri::command]
+async fn imported_products_page(
+    page: usize,
+    page_size: usize,
+    catalog: tauri::State<'_, ImportedCatalog>,
+) -> Result<csv_parser::ProductPage, String> {
+    catalog.page(page, page_size)
+}
+
+// Write Products UUIDs Command: copy of the CSV with the generated UUIDs filled in
 #[tauri::command]
-async fn read_csv_file(path: String) -> Result<String, String> {
-    std::fs::read_to_string(&path)
-        .map_err(|e| format!("Failed to read CSV file: {}", e))
+async fn write_products_uuids(
+    path: String,
+    options: Option<csv_parser::ImportOptions>,
+    products: Option<Vec<csv_parser::Product>>,
+    catalog: tauri::State<'_, ImportedCatalog>,
+) -> Result<csv_parser::UuidWriteBack, String> {
+    let products = catalog.products(products)?;
+    tokio::task::spawn_blocking(move || {
+        csv_parser::write_back_uuids(std::path::Path::new(&path), &options.unwrap_or_default(), &products)
+    })
+    .await
+    .map_err(|e| format!("Failed to write UUIDs: {}", e))?
+    .map_err(|e| format!("Failed to write UUIDs: {}", e))
 }
 
-// Scan Images Directory Command
+// Scan Images Directory Command: match the image files to products with the
+// given rules, folder per product by default
 #[tauri::command]
 async fn scan_images_directory(
     directory: String,
-    products: Vec<serde_json::Value>,
+    matching: Option<images::MatchOptions>,
+    products: Option<Vec<serde_json::Value>>,
+    app: tauri::AppHandle,
+    catalog: tauri::State<'_, ImportedCatalog>,
 ) -> Result<serde_json::Value, String> {
-    images::scan_directory(&directory, &products)
+    let products = match products {
+        Some(products) => products,
+        None => catalog.values()?,
+    };
+    let cache_dir = images::cache_dir(&app).ok();
+    images::scan_directory(&directory, &products, cache_dir.as_deref(), &matching.unwrap_or_default())
         .await
         .map_err(|e| format!("Failed to scan directory: {}", e))
 }
 
+// Download Product Images Command: fetch the `imagens` URLs into the local
+// cache, emitting "image-download-progress"
+#[tauri::command]
+async fn download_product_images(
+    options: Option<images::DownloadOptions>,
+    products: Option<Vec<csv_parser::Product>>,
+    app: tauri::AppHandle,
+    catalog: tauri::State<'_, ImportedCatalog>,
+) -> Result<images::DownloadReport, String> {
+    let products = catalog.products(products)?;
+    let cache_dir = images::cache_dir(&app)
+        .map_err(|e| format!("Failed to download images: {}", e))?;
+    images::download_images(&products, &cache_dir, &options.unwrap_or_default(), |progress| {
+        let _ = app.emit("image-download-progress", progress);
+    })
+    .await
+    .map_err(|e| format!("Failed to download images: {}", e))
+}
+
 // Deploy Store Command
 #[tauri::command]
 async fn deploy_store(
-    config: DeployConfig,
+    mut config: DeployConfig,
     app: tauri::AppHandle,
+    catalog: tauri::State<'_, ImportedCatalog>,
 ) -> Result<DeployResult, String> {
+    if config.products.is_empty() {
+        config.products = catalog.values()?;
+    }
     deployment::deploy(config, app)
         .await
         .map_err(|e| format!("Deployment failed: {}", e))
 }
 
+// Export Deployment Bundle Command
+#[tauri::command]
+async fn export_deployment_bundle(
+    mut config: DeployConfig,
+    output_path: String,
+    app: tauri::AppHandle,
+    catalog: tauri::State<'_, ImportedCatalog>,
+) -> Result<deployment::BundleSummary, String> {
+    if config.products.is_empty() {
+        config.products = catalog.values()?;
+    }
+    deployment::export_bundle(&config, std::path::Path::new(&output_path), &app)
+        .await
+        .map_err(|e| format!("Failed to export bundle: {}", e))
+}
+
+// Remove Store From Shared Server Command
+#[tauri::command]
+async fn remove_remote_store(
+    server: ServerConfig,
+    identity: serde_json::Value,
+    app: tauri::AppHandle,
+) -> Result<(), String> {
+    deployment::remove_remote_store(server, identity, app)
+        .await
+        .map_err(|e| format!("Failed to remove store: {}", e))
+}
+
+// Sync Catalog Command: diff the products against the live store, then apply
+// the approved changes when `approved` is given
+#[tauri::command]
+async fn sync_catalog(
+    identity: serde_json::Value,
+    store: Option<medusa::StoreAccess>,
+    products: Option<Vec<csv_parser::Product>>,
+    approved: Option<Vec<String>>,
+    app: tauri::AppHandle,
+    catalog: tauri::State<'_, ImportedCatalog>,
+) -> Result<medusa::CatalogSync, String> {
+    let products = catalog.products(products)?;
+    deployment::sync_catalog(&app, &identity, store, &products, approved.as_deref())
+        .await
+        .map_err(|e| format!("Failed to sync catalog: {}", e))
+}
+
+// Export Catalog Command: write the live store's catalog to a CSV that can
+// be imported again
+#[tauri::command]
+async fn export_catalog_csv(
+    identity: serde_json::Value,
+    store: Option<medusa::StoreAccess>,
+    output_path: String,
+    app: tauri::AppHandle,
+) -> Result<deployment::CatalogExport, String> {
+    deployment::export_catalog(&app, &identity, store, std::path::Path::new(&output_path))
+        .await
+        .map_err(|e| format!("Failed to export catalog: {}", e))
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
+        .manage(ImportedCatalog::default())
         .invoke_handler(tauri::generate_handler![
             connect_ssh,
-            read_csv_file,
+            preview_products_file,
+            import_products_file,
+            imported_products_page,
+            write_products_uuids,
             scan_images_directory,
+            download_product_images,
             deploy_store,
+            remove_remote_store,
+            export_deployment_bundle,
+            sync_catalog,
+            export_catalog_csv,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
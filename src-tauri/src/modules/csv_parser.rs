@@ -1,23 +1,485 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::path::Path;
+use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize)]
+mod conflicts;
+mod encoding;
+mod logistics;
+mod mapping;
+mod money;
+mod platforms;
+mod spreadsheet;
+mod stream;
+mod summary;
+mod taxonomy;
+mod uuids;
+mod variants;
+mod writer;
+
+pub use conflicts::{Conflict, ConflictResolution};
+pub use mapping::{ColumnMapping, HeaderPreview};
+pub use money::{Currency, Money};
+pub use platforms::Platform;
+pub use stream::ImportProgress;
+pub use summary::{page, ImportSummary, ProductPage};
+pub use taxonomy::{category_handle, category_tree, collections, tags, CategoryNode};
+pub use uuids::{write_back_uuids, UuidWriteBack};
+pub use variants::{ProductOption, Variant};
+pub use writer::write_csv;
+
+use variants::{CatalogBuilder, Row, OPTION_PREFIX};
+
+pub const MAX_DESCRIPTION_LEN: usize = 5000;
+const REQUIRED_COLUMNS: [&str; 3] = ["codigo_interno", "nome", "preco"];
+
+/// Header row and numbered data rows of a table.
+type Table = (Vec<String>, Vec<(u64, Vec<String>)>);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Product {
     pub uuid: Option<String>,
     pub codigo_interno: String,
     pub nome: String,
-    pub preco: f64,
+    pub preco: Money,
     pub descricao: String,
+    #[serde(default)]
+    pub options: Vec<ProductOption>,
+    #[serde(default)]
+    pub variants: Vec<Variant>,
+    /// Category path from the root, e.g. ["Roupas", "Camisetas"]
+    #[serde(default)]
+    pub categoria: Vec<String>,
+    #[serde(default)]
+    pub colecao: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Image URLs given in the file, e.g. when migrating from another
+    /// platform. Local images are matched by `codigo_interno` instead.
+    #[serde(default)]
+    pub imagens: Vec<String>,
+}
+
+#[cfg(test)]
+impl Product {
+    /// "Camiseta" at R$ 49,90 without variants, for tests to adjust with
+    /// struct update syntax.
+    pub fn sample(codigo: &str) -> Self {
+        Product {
+            uuid: None,
+            codigo_interno: codigo.to_string(),
+            nome: "Camiseta".to_string(),
+            preco: Money::brl(4990),
+            descricao: String::new(),
+            options: Vec::new(),
+            variants: Vec::new(),
+            categoria: Vec::new(),
+            colecao: None,
+            tags: Vec::new(),
+            imagens: Vec::new(),
+        }
+    }
+}
+
+/// A problem found in one cell. `line` is the 1-based line in the file, the
+/// header being line 1.
+#[derive(Debug, Clone, Serialize)]
+pub struct RowDiagnostic {
+    pub line: u64,
+    pub column: String,
+    pub value: String,
+    pub reason: String,
+}
+
+/// Products from the rows that passed validation plus one diagnostic per
+/// problem in the rows that did not.
+#[derive(Debug, Default, Serialize)]
+pub struct ParseReport {
+    pub products: Vec<Product>,
+    pub diagnostics: Vec<RowDiagnostic>,
+    #[serde(rename = "totalRows")]
+    pub total_rows: usize,
+    #[serde(rename = "rejectedRows")]
+    pub rejected_rows: usize,
+    /// Products that had no uuid and received a new one
+    #[serde(rename = "generatedUuids")]
+    pub generated_uuids: usize,
+    /// "csv", "xlsx", "xls" or "ods"
+    pub format: String,
+    /// CSV only
+    pub delimiter: Option<char>,
+    /// CSV only
+    pub encoding: Option<String>,
+    /// Spreadsheets only: the worksheet that was read and all worksheets in
+    /// the file, so the user can pick another one
+    pub sheet: Option<String>,
+    pub sheets: Vec<String>,
+    /// Set when the file is another platform's export
+    pub platform: Option<Platform>,
+    /// Duplicate codes, SKUs, UUIDs and similar names, by line
+    pub conflicts: Vec<Conflict>,
+    pub categories: Vec<CategoryNode>,
+    pub collections: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+/// Column positions resolved from the header row.
+struct Columns {
+    uuid: Option<usize>,
+    codigo_interno: usize,
+    codigo_pai: Option<usize>,
+    nome: usize,
+    preco: usize,
+    descricao: Option<usize>,
+    sku: Option<usize>,
+    estoque: Option<usize>,
+    ean: Option<usize>,
+    ncm: Option<usize>,
+    peso_g: Option<usize>,
+    altura_cm: Option<usize>,
+    largura_cm: Option<usize>,
+    comprimento_cm: Option<usize>,
+    categoria: Option<usize>,
+    colecao: Option<usize>,
+    tags: Option<usize>,
+    imagens: Option<usize>,
+    /// `opcao_*` columns, in file order
+    options: Vec<(String, usize)>,
+}
+
+impl Columns {
+    fn from_headers(headers: &[String]) -> Result<Self> {
+        let find = |name: &str| headers.iter().position(|h| h.trim() == name);
+
+        let missing: Vec<&str> = REQUIRED_COLUMNS
+            .iter()
+            .copied()
+            .filter(|name| find(name).is_none())
+            .collect();
+        if !missing.is_empty() {
+            anyhow::bail!("Missing required columns: {}", missing.join(", "));
+        }
+
+        let options = headers
+            .iter()
+            .enumerate()
+            .map(|(i, h)| (h.trim(), i))
+            .filter(|(h, _)| h.len() > OPTION_PREFIX.len() && h.starts_with(OPTION_PREFIX))
+            .map(|(h, i)| (h.to_string(), i))
+            .collect();
+
+        Ok(Self {
+            uuid: find("uuid"),
+            codigo_interno: find("codigo_interno").unwrap_or_default(),
+            codigo_pai: find("codigo_pai"),
+            nome: find("nome").unwrap_or_default(),
+            preco: find("preco").unwrap_or_default(),
+            descricao: find("descricao"),
+            sku: find("sku"),
+            estoque: find("estoque"),
+            ean: find("ean"),
+            ncm: find("ncm"),
+            peso_g: find("peso_g"),
+            altura_cm: find("altura_cm"),
+            largura_cm: find("largura_cm"),
+            comprimento_cm: find("comprimento_cm"),
+            categoria: find("categoria"),
+            colecao: find("colecao"),
+            tags: find("tags"),
+            imagens: find("imagens"),
+            options,
+        })
+    }
+}
+
+/// How to read a product file: the worksheet of a spreadsheet and the
+/// user-confirmed column mapping. Without a mapping, exports of other
+/// platforms are recognized by their headers (or read as `platform`), and
+/// any other file must use the field names.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ImportOptions {
+    #[serde(default)]
+    pub sheet: Option<String>,
+    #[serde(default)]
+    pub mapping: Option<ColumnMapping>,
+    #[serde(default)]
+    pub platform: Option<Platform>,
+    /// How to resolve duplicate products; without it they are only reported
+    #[serde(default)]
+    pub duplicates: Option<ConflictResolution>,
+}
+
+/// Parses a product file, picking the reader from the extension: Excel and
+/// OpenDocument spreadsheets are read from `options.sheet` (the first
+/// worksheet with the required columns by default), anything else as CSV.
+/// Progress is reported while the file is read: CSVs are streamed from
+/// disk, spreadsheets are loaded whole by their reader, so they report only
+/// when done.
+pub fn stream_products_file(
+    path: &Path,
+    options: &ImportOptions,
+    mut on_progress: impl FnMut(ImportProgress),
+) -> Result<ParseReport> {
+    if !is_spreadsheet(path) {
+        return stream::parse_csv_file(path, options, on_progress);
+    }
+
+    let report = spreadsheet::parse_spreadsheet(path, options)?;
+    let total_bytes = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    on_progress(ImportProgress { bytes_read: total_bytes, total_bytes, rows: report.total_rows });
+    Ok(report)
+}
+
+/// First phase of a mapped import: headers, a few sample rows and the
+/// suggested mapping.
+pub fn preview_products_file(path: &Path, sheet: Option<&str>) -> Result<HeaderPreview> {
+    if is_spreadsheet(path) {
+        return spreadsheet::preview_spreadsheet(path, sheet);
+    }
+
+    stream::preview_csv_file(path)
+}
+
+fn is_spreadsheet(path: &Path) -> bool {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    spreadsheet::EXTENSIONS.contains(&extension.as_str())
+}
+
+/// Validates rows from any tabular source, each given with its 1-based line
+/// number. With a mapping the headers are renamed to field names first, and
+/// platform exports are translated to our columns. Rows sharing a
+/// `codigo_interno` (or a `codigo_pai`) become variants of one product, told
+/// apart by their `opcao_*` columns. Rows with problems are reported in
+/// `diagnostics` and left out, every other row is imported. Duplicates are
+/// listed in `conflicts` and resolved as `options.duplicates` says. Products
+/// without a uuid get a new v4 UUID.
+fn parse_rows(
+    headers: &[String],
+    options: &ImportOptions,
+    rows: impl IntoIterator<Item = Result<(u64, Vec<String>)>>,
+) -> Result<ParseReport> {
+    if let Some(mapping) = &options.mapping {
+        return validate_rows(&Columns::from_headers(&mapping.apply(headers))?, rows, options);
+    }
+
+    if let Some(platform) = options.platform.or_else(|| Platform::detect(headers)) {
+        let rows = rows.into_iter().collect::<Result<Vec<_>>>()?;
+        let (headers, rows) = platform.translate(headers, rows);
+        let mut report = validate_rows(&Columns::from_headers(&headers)?, rows.into_iter().map(Ok), options)?;
+        report.platform = Some(platform);
+        return Ok(report);
+    }
+
+    validate_rows(&Columns::from_headers(headers)?, rows, options)
+}
+
+fn validate_rows(
+    columns: &Columns,
+    rows: impl IntoIterator<Item = Result<(u64, Vec<String>)>>,
+    options: &ImportOptions,
+) -> Result<ParseReport> {
+    let mut report = ParseReport::default();
+    let mut catalog = CatalogBuilder::default();
+
+    for row in rows {
+        let (line, cells) = row?;
+        if cells.iter().all(|cell| cell.trim().is_empty()) {
+            continue;
+        }
+
+        report.total_rows += 1;
+
+        match validate_row(columns, &cells, line, &catalog) {
+            Ok(row) => catalog.push(row),
+            Err(diagnostics) => {
+                report.rejected_rows += 1;
+                report.diagnostics.extend(diagnostics);
+            }
+        }
+    }
+
+    let (mut products, rejected, conflicts) = catalog.finish(options.duplicates.as_ref());
+    for product in products.iter_mut().filter(|p| p.uuid.is_none()) {
+        product.uuid = Some(Uuid::new_v4().to_string());
+        report.generated_uuids += 1;
+    }
+    report.products = products;
+    report.conflicts = conflicts;
+    report.rejected_rows += rejected.len();
+    report.diagnostics.extend(rejected);
+    report.diagnostics.sort_by_key(|d| d.line);
+    report.categories = taxonomy::category_tree(&report.products);
+    report.collections = taxonomy::collections(&report.products);
+    report.tags = taxonomy::tags(&report.products);
+
+    Ok(report)
+}
+
+/// Checks the cells of one row. Name and price are required on the first
+/// row of a product; later variant rows may leave them empty. Product level
+/// fields (description, category, collection, tags) are taken from the first
+/// row.
+fn validate_row(
+    columns: &Columns,
+    cells: &[String],
+    line: u64,
+    catalog: &CatalogBuilder,
+) -> std::result::Result<Row, Vec<RowDiagnostic>> {
+    let cell = |index: usize| cells.get(index).map(String::as_str).unwrap_or("").trim();
+    let optional = |index: Option<usize>| index.map(cell).filter(|v| !v.is_empty());
+    let mut diagnostics = Vec::new();
+    let mut fail = |column: &str, value: &str, reason: &str| {
+        diagnostics.push(RowDiagnostic {
+            line,
+            column: column.to_string(),
+            value: value.to_string(),
+            reason: reason.to_string(),
+        });
+    };
+
+    let codigo_interno = cell(columns.codigo_interno);
+    if codigo_interno.is_empty() {
+        fail("codigo_interno", codigo_interno, "codigo_interno is required");
+    }
+    let parent = optional(columns.codigo_pai).unwrap_or(codigo_interno);
+    let is_variant_row = !parent.is_empty() && catalog.contains(parent);
+
+    let nome = cell(columns.nome);
+    if nome.is_empty() && !is_variant_row {
+        fail("nome", nome, "nome must not be empty");
+    }
+
+    let preco_raw = cell(columns.preco);
+    let preco = if preco_raw.is_empty() && is_variant_row {
+        None
+    } else {
+        match Money::parse(preco_raw, Currency::BRL) {
+            Ok(money) if money.centavos < 0 => {
+                fail("preco", preco_raw, "preco must not be negative");
+                None
+            }
+            Ok(money) => Some(money),
+            Err(reason) => {
+                fail("preco", preco_raw, &reason);
+                None
+            }
+        }
+    };
+
+    let descricao = columns.descricao.map(cell).unwrap_or("");
+    let descricao_len = descricao.chars().count();
+    if descricao_len > MAX_DESCRIPTION_LEN {
+        fail(
+            "descricao",
+            &descricao.chars().take(50).collect::<String>(),
+            &format!(
+                "descricao has {} characters, the limit is {}",
+                descricao_len, MAX_DESCRIPTION_LEN
+            ),
+        );
+    }
+
+    let uuid = optional(columns.uuid);
+    if let Some(value) = uuid {
+        if Uuid::parse_str(value).is_err() {
+            fail("uuid", value, "uuid is not a valid UUID");
+        }
+    }
+
+    let estoque = parse_cell(&mut fail, "estoque", optional(columns.estoque), logistics::parse_stock);
+    let peso_g = parse_cell(&mut fail, "peso_g", optional(columns.peso_g), logistics::parse_weight);
+    let altura_cm = parse_cell(&mut fail, "altura_cm", optional(columns.altura_cm), |v| {
+        logistics::parse_side("altura_cm", v)
+    });
+    let largura_cm = parse_cell(&mut fail, "largura_cm", optional(columns.largura_cm), |v| {
+        logistics::parse_side("largura_cm", v)
+    });
+    let comprimento_cm = parse_cell(&mut fail, "comprimento_cm", optional(columns.comprimento_cm), |v| {
+        logistics::parse_side("comprimento_cm", v)
+    });
+    let sku = parse_cell(&mut fail, "sku", optional(columns.sku), logistics::parse_sku);
+    let ean = parse_cell(&mut fail, "ean", optional(columns.ean), logistics::parse_ean);
+    let ncm = parse_cell(&mut fail, "ncm", optional(columns.ncm), logistics::parse_ncm);
+
+    let imagens_raw = optional(columns.imagens);
+    let imagens = imagens_raw.map(parse_image_urls).unwrap_or_default();
+    if let Some(url) = imagens.iter().find(|url| !url.starts_with("http://") && !url.starts_with("https://")) {
+        fail("imagens", url, "imagens must be http(s) URLs separated by |");
+    }
+
+    let categoria_raw = optional(columns.categoria);
+    let categoria = match categoria_raw.map(taxonomy::parse_category_path) {
+        Some(Ok(path)) => path,
+        Some(Err(reason)) => {
+            fail("categoria", categoria_raw.unwrap_or(""), &reason);
+            Vec::new()
+        }
+        None => Vec::new(),
+    };
+
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+
+    Ok(Row {
+        line,
+        parent: parent.to_string(),
+        codigo: codigo_interno.to_string(),
+        uuid: uuid.map(str::to_string),
+        nome: nome.to_string(),
+        preco,
+        descricao: descricao.to_string(),
+        options: columns
+            .options
+            .iter()
+            .map(|(column, index)| (column.clone(), cell(*index)))
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(column, value)| (column, value.to_string()))
+            .collect(),
+        sku,
+        estoque,
+        ean,
+        peso_g,
+        altura_cm,
+        largura_cm,
+        comprimento_cm,
+        ncm,
+        categoria,
+        colecao: optional(columns.colecao).map(str::to_string),
+        tags: optional(columns.tags).map(taxonomy::parse_tags).unwrap_or_default(),
+        imagens,
+        variant: true,
+    })
+}
+
+/// "https://a/1.jpg | https://a/2.jpg" -> both URLs, repeats dropped.
+fn parse_image_urls(raw: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for url in raw.split('|').map(str::trim).filter(|u| !u.is_empty()) {
+        if !urls.iter().any(|u| u == url) {
+            urls.push(url.to_string());
+        }
+    }
+    urls
 }
 
-pub fn parse_csv(content: &str) -> Result<Vec<Product>> {
-    let mut reader = csv::Reader::from_reader(content.as_bytes());
-    let mut products = Vec::new();
-    
-    for result in reader.deserialize() {
-        let product: Product = result?;
-        products.push(product);
+/// Runs `parse` on an optional cell, recording a diagnostic when it fails.
+fn parse_cell<T>(
+    fail: &mut impl FnMut(&str, &str, &str),
+    column: &str,
+    value: Option<&str>,
+    parse: impl FnOnce(&str) -> std::result::Result<T, String>,
+) -> Option<T> {
+    let value = value?;
+    match parse(value) {
+        Ok(parsed) => Some(parsed),
+        Err(reason) => {
+            fail(column, value, &reason);
+            None
+        }
     }
-    
-    Ok(products)
 }
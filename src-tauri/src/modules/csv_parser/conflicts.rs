@@ -0,0 +1,375 @@
+//! Duplicate products in an import: the same code and options on two rows,
+//! a SKU or UUID used twice, or two products whose names differ only in
+//! case, accents or punctuation. Conflicts are always reported; a chosen
+//! strategy resolves them before the products are built.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use super::variants::Row;
+use crate::modules::text::slugify;
+
+/// Group and row index of a row
+type Position = (usize, usize);
+/// Product code and sorted option values of a row
+type RowKey = (String, Vec<(String, String)>);
+/// Kind, shared value and the products or rows sharing it
+type ConflictSet<T> = (ConflictKind, String, Vec<T>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicateStrategy {
+    /// Keep the first row, filling its empty cells from the others
+    Merge,
+    KeepFirst,
+    KeepLast,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConflictResolution {
+    pub strategy: DuplicateStrategy,
+    /// Also resolve products with similar names, which are often distinct
+    /// products and are only reported by default
+    #[serde(default)]
+    pub names: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictKind {
+    Codigo,
+    Sku,
+    Uuid,
+    Nome,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    /// The shared code, SKU, UUID or normalized name
+    pub value: String,
+    /// Lines of the rows involved, for products their first row
+    pub lines: Vec<u64>,
+    pub codigos: Vec<String>,
+    /// Strategy applied, none when the conflict was only reported
+    pub resolved: Option<DuplicateStrategy>,
+}
+
+/// Finds the conflicts in `groups` (rows grouped by product) and, with a
+/// resolution, rewrites the groups so each conflict leaves a single product
+/// or row. Products sharing a UUID or name are resolved first: merging them
+/// moves the rows of the others into the first product, where repeated rows
+/// then show up as code duplicates.
+pub(super) fn resolve(groups: &mut Vec<Vec<Row>>, resolution: Option<&ConflictResolution>) -> Vec<Conflict> {
+    let mut conflicts = resolve_products(groups, resolution);
+    conflicts.extend(resolve_rows(groups, resolution));
+    conflicts.sort_by_key(|c| c.lines.first().copied().unwrap_or(0));
+    conflicts
+}
+
+fn resolve_products(groups: &mut Vec<Vec<Row>>, resolution: Option<&ConflictResolution>) -> Vec<Conflict> {
+    let mut by_uuid: HashMap<&str, Vec<usize>> = HashMap::new();
+    let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, rows) in groups.iter().enumerate() {
+        let Some(first) = rows.first() else { continue };
+        if let Some(uuid) = &first.uuid {
+            by_uuid.entry(uuid.as_str()).or_default().push(index);
+        }
+        let name = slugify(&first.nome);
+        if !name.is_empty() {
+            by_name.entry(name).or_default().push(index);
+        }
+    }
+
+    let mut sets: Vec<ConflictSet<usize>> = Vec::new();
+    let mut linked: HashSet<Vec<usize>> = HashSet::new();
+    for (uuid, indexes) in by_uuid.into_iter().filter(|(_, i)| i.len() > 1) {
+        linked.insert(indexes.clone());
+        sets.push((ConflictKind::Uuid, uuid.to_string(), indexes));
+    }
+    for (name, indexes) in by_name.into_iter().filter(|(_, i)| i.len() > 1) {
+        // Copies of one product that already share a UUID are reported once
+        if !linked.contains(&indexes) {
+            sets.push((ConflictKind::Nome, name, indexes));
+        }
+    }
+    // Sets come from hash maps, sorted so ties resolve the same way every time
+    sets.sort_by(|a, b| (a.2[0], a.0, &a.1).cmp(&(b.2[0], b.0, &b.1)));
+    let found: Vec<(Conflict, Vec<usize>)> = sets
+        .into_iter()
+        .map(|(kind, value, indexes)| {
+            let conflict = Conflict {
+                kind,
+                value,
+                lines: indexes.iter().map(|&i| groups[i][0].line).collect(),
+                codigos: indexes.iter().map(|&i| groups[i][0].parent.clone()).collect(),
+                resolved: None,
+            };
+            (conflict, indexes)
+        })
+        .collect();
+
+    let mut slots: Vec<Option<Vec<Row>>> = std::mem::take(groups).into_iter().map(Some).collect();
+    let mut conflicts = Vec::new();
+    for (mut conflict, indexes) in found {
+        let strategy = resolution
+            .filter(|r| conflict.kind != ConflictKind::Nome || r.names)
+            .map(|r| r.strategy);
+        // Products already merged into or dropped for another conflict,
+        // which leaves it resolved as well
+        let remaining: Vec<usize> = indexes.into_iter().filter(|i| slots[*i].is_some()).collect();
+        if let Some(strategy) = strategy {
+            if remaining.len() > 1 {
+                apply_to_products(&mut slots, &remaining, strategy);
+            }
+            conflict.resolved = Some(strategy);
+        }
+        conflicts.push(conflict);
+    }
+
+    *groups = slots.into_iter().flatten().collect();
+    conflicts
+}
+
+fn apply_to_products(slots: &mut [Option<Vec<Row>>], indexes: &[usize], strategy: DuplicateStrategy) {
+    let (kept, dropped) = match strategy {
+        DuplicateStrategy::KeepLast => (indexes[indexes.len() - 1], &indexes[..indexes.len() - 1]),
+        _ => (indexes[0], &indexes[1..]),
+    };
+    for &index in dropped {
+        let Some(rows) = slots[index].take() else { continue };
+        if strategy != DuplicateStrategy::Merge {
+            continue;
+        }
+        let Some(target) = slots[kept].as_mut() else { continue };
+        let parent = target[0].parent.clone();
+        target.extend(rows.into_iter().map(|mut row| {
+            row.parent = parent.clone();
+            row
+        }));
+    }
+}
+
+fn resolve_rows(groups: &mut Vec<Vec<Row>>, resolution: Option<&ConflictResolution>) -> Vec<Conflict> {
+    let mut by_key: HashMap<RowKey, Vec<Position>> = HashMap::new();
+    let mut by_sku: HashMap<&str, Vec<Position>> = HashMap::new();
+    for (g, rows) in groups.iter().enumerate() {
+        for (r, row) in rows.iter().enumerate() {
+            let mut options = row.options.clone();
+            options.sort();
+            by_key.entry((row.parent.clone(), options)).or_default().push((g, r));
+            if let Some(sku) = &row.sku {
+                by_sku.entry(sku.as_str()).or_default().push((g, r));
+            }
+        }
+    }
+
+    let mut sets: Vec<ConflictSet<Position>> = Vec::new();
+    let mut linked: HashSet<Vec<Position>> = HashSet::new();
+    for ((parent, options), positions) in by_key.into_iter().filter(|(_, p)| p.len() > 1) {
+        linked.insert(positions.clone());
+        let value = std::iter::once(parent)
+            .chain(options.into_iter().map(|(_, value)| value))
+            .collect::<Vec<_>>()
+            .join(" / ");
+        sets.push((ConflictKind::Codigo, value, positions));
+    }
+    for (sku, positions) in by_sku.into_iter().filter(|(_, p)| p.len() > 1) {
+        if !linked.contains(&positions) {
+            sets.push((ConflictKind::Sku, sku.to_string(), positions));
+        }
+    }
+    sets.sort_by(|a, b| (a.2[0], a.0, &a.1).cmp(&(b.2[0], b.0, &b.1)));
+    let found: Vec<(Conflict, Vec<Position>)> = sets
+        .into_iter()
+        .map(|(kind, value, positions)| {
+            // Rows moved by a merge come after their new product's rows
+            let mut rows: Vec<(u64, String)> = positions
+                .iter()
+                .map(|&(g, r)| (groups[g][r].line, groups[g][r].codigo.clone()))
+                .collect();
+            rows.sort();
+            let (lines, codigos) = rows.into_iter().unzip();
+            let conflict = Conflict { kind, value, lines, codigos, resolved: None };
+            (conflict, positions)
+        })
+        .collect();
+
+    let mut slots: Vec<Vec<Option<Row>>> = std::mem::take(groups)
+        .into_iter()
+        .map(|rows| rows.into_iter().map(Some).collect())
+        .collect();
+    let mut conflicts = Vec::new();
+    for (mut conflict, positions) in found {
+        let remaining: Vec<Position> = positions
+            .into_iter()
+            .filter(|&(g, r)| slots[g][r].as_ref().is_some_and(|row| row.variant))
+            .collect();
+        if let Some(resolution) = resolution {
+            if remaining.len() > 1 {
+                apply_to_rows(&mut slots, &remaining, resolution.strategy);
+            }
+            conflict.resolved = Some(resolution.strategy);
+        }
+        conflicts.push(conflict);
+    }
+
+    *groups = slots
+        .into_iter()
+        .map(|rows| rows.into_iter().flatten().collect::<Vec<_>>())
+        .filter(|rows| !rows.is_empty())
+        .collect();
+    conflicts
+}
+
+/// Leaves one row of `positions` in the slot of the first one when they
+/// belong to the same product, so the product keeps its place and its
+/// product fields come from the row that was kept.
+fn apply_to_rows(slots: &mut [Vec<Option<Row>>], positions: &[Position], strategy: DuplicateStrategy) {
+    let (first_g, first_r) = positions[0];
+    match strategy {
+        DuplicateStrategy::KeepFirst => {
+            for &position in &positions[1..] {
+                take_variant(slots, position);
+            }
+        }
+        DuplicateStrategy::KeepLast => {
+            let (last_g, last_r) = positions[positions.len() - 1];
+            for &position in &positions[1..positions.len() - 1] {
+                take_variant(slots, position);
+            }
+            if last_g == first_g {
+                slots[first_g][first_r] = slots[last_g][last_r].take();
+            } else {
+                take_variant(slots, (first_g, first_r));
+            }
+        }
+        DuplicateStrategy::Merge => {
+            for &position in &positions[1..] {
+                let Some(other) = take_variant(slots, position) else { continue };
+                if let Some(row) = slots[first_g][first_r].as_mut() {
+                    merge(row, other);
+                }
+            }
+        }
+    }
+}
+
+/// Removes the variant of the row at `(g, r)`, returning the row. The first
+/// row of a product with other variants stays for its product fields, a SKU
+/// shared with another product must not leave this one without its name and
+/// price. A product left without variants is removed.
+fn take_variant(slots: &mut [Vec<Option<Row>>], (g, r): Position) -> Option<Row> {
+    let defines_product = slots[g][..r].iter().all(Option::is_none);
+    let more_variants = slots[g][r + 1..].iter().flatten().any(|row| row.variant);
+    let taken = if defines_product && more_variants {
+        let row = slots[g][r].as_mut()?;
+        row.variant = false;
+        Some(row.clone())
+    } else {
+        slots[g][r].take()
+    };
+    if !slots[g].iter().flatten().any(|row| row.variant) {
+        slots[g].iter_mut().for_each(|slot| *slot = None);
+    }
+    taken
+}
+
+/// Fills the empty cells of `row` from `other`; image URLs are combined.
+fn merge(row: &mut Row, other: Row) {
+    fn fill(text: &mut String, other: String) {
+        if text.is_empty() {
+            *text = other;
+        }
+    }
+    fn fill_list(list: &mut Vec<String>, other: Vec<String>) {
+        if list.is_empty() {
+            *list = other;
+        }
+    }
+
+    fill(&mut row.nome, other.nome);
+    fill(&mut row.descricao, other.descricao);
+    fill_list(&mut row.categoria, other.categoria);
+    fill_list(&mut row.tags, other.tags);
+    row.uuid = row.uuid.take().or(other.uuid);
+    row.preco = row.preco.or(other.preco);
+    row.sku = row.sku.take().or(other.sku);
+    row.estoque = row.estoque.or(other.estoque);
+    row.ean = row.ean.take().or(other.ean);
+    row.ncm = row.ncm.take().or(other.ncm);
+    row.peso_g = row.peso_g.or(other.peso_g);
+    row.altura_cm = row.altura_cm.or(other.altura_cm);
+    row.largura_cm = row.largura_cm.or(other.largura_cm);
+    row.comprimento_cm = row.comprimento_cm.or(other.comprimento_cm);
+    row.colecao = row.colecao.take().or(other.colecao);
+    for url in other.imagens {
+        if !row.imagens.contains(&url) {
+            row.imagens.push(url);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::csv_parser::{parse_rows, ImportOptions, Money, ParseReport};
+
+    fn parse(lines: &[&str], strategy: DuplicateStrategy) -> ParseReport {
+        let split = |line: &str| line.split(';').map(str::to_string).collect::<Vec<_>>();
+        let headers = split("codigo_interno;nome;preco;sku;opcao_cor");
+        let rows = lines.iter().enumerate().map(|(i, line)| Ok((i as u64 + 2, split(line))));
+        let options = ImportOptions {
+            duplicates: Some(ConflictResolution { strategy, names: false }),
+            ..Default::default()
+        };
+        parse_rows(&headers, &options, rows).unwrap()
+    }
+
+    /// "SKU-X" is a variant of A and the first row of B
+    const SHARED_SKU: [&str; 4] = [
+        "A;Camiseta;10,00;A-AZ;Azul",
+        "A;;;SKU-X;Verde",
+        "B;Caneca;20,00;SKU-X;Branco",
+        "B;;;B-PR;Preto",
+    ];
+
+    #[test]
+    fn dropping_a_shared_sku_keeps_the_other_product_fields() {
+        for strategy in [DuplicateStrategy::KeepFirst, DuplicateStrategy::Merge] {
+            let report = parse(&SHARED_SKU, strategy);
+            let caneca = report.products.iter().find(|p| p.codigo_interno == "B").unwrap();
+            assert_eq!(caneca.nome, "Caneca");
+            assert_eq!(caneca.preco, Money::brl(2000));
+            let skus: Vec<&str> = caneca.variants.iter().map(|v| v.sku.as_str()).collect();
+            assert_eq!(skus, ["B-PR"]);
+        }
+    }
+
+    #[test]
+    fn keeping_the_last_shared_sku_drops_the_first_variant() {
+        let report = parse(&SHARED_SKU, DuplicateStrategy::KeepLast);
+        let camiseta = report.products.iter().find(|p| p.codigo_interno == "A").unwrap();
+        assert_eq!(camiseta.variants.len(), 1);
+        let caneca = report.products.iter().find(|p| p.codigo_interno == "B").unwrap();
+        assert_eq!((caneca.nome.as_str(), caneca.variants.len()), ("Caneca", 2));
+    }
+
+    #[test]
+    fn a_product_left_without_variants_is_removed() {
+        let lines = ["A;Camiseta;10,00;SKU-X;Azul", "B;Caneca;20,00;SKU-X;Branco"];
+        let report = parse(&lines, DuplicateStrategy::KeepFirst);
+        let codigos: Vec<&str> = report.products.iter().map(|p| p.codigo_interno.as_str()).collect();
+        assert_eq!(codigos, ["A"]);
+    }
+
+    #[test]
+    fn conflicts_starting_on_the_same_row_come_in_a_fixed_order() {
+        let lines = ["A;Camiseta;10,00;SKU-X;Azul", "A;;;A-2;Azul", "B;Caneca;20,00;SKU-X;Branco"];
+        for _ in 0..20 {
+            let report = parse(&lines, DuplicateStrategy::KeepFirst);
+            let kinds: Vec<ConflictKind> = report.conflicts.iter().map(|c| c.kind).collect();
+            assert_eq!(kinds, [ConflictKind::Codigo, ConflictKind::Sku]);
+        }
+    }
+}
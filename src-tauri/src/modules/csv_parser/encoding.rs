@@ -0,0 +1,208 @@
+use encoding_rs::{Decoder, DecoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use std::io::Read;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Decodes a spreadsheet export. Byte order marks are honoured and removed;
+/// without one the file is read as UTF-8 when valid, otherwise as
+/// Windows-1252, which is what Excel in pt-BR writes for "CSV (separado por
+/// vírgulas)".
+pub fn decode(bytes: &[u8]) -> (String, &'static Encoding) {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+        return (text.into_owned(), encoding);
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) => (text.to_string(), UTF_8),
+        Err(_) => {
+            let (text, _) = WINDOWS_1252.decode_without_bom_handling(bytes);
+            (text.into_owned(), WINDOWS_1252)
+        }
+    }
+}
+
+/// Picks the encoding like `decode` from the first bytes of a file. A
+/// UTF-8 sequence cut at the end of `head` still counts as UTF-8.
+///
+/// Without a byte order mark UTF-8 is only a guess: a Windows-1252 file
+/// whose accents all come after `head` looks like UTF-8 too. Read it with
+/// `DecodeReader::strict`, which stops at the first invalid sequence.
+pub fn sniff(head: &[u8]) -> Sniffed {
+    if let Some((encoding, _)) = Encoding::for_bom(head) {
+        return Sniffed { encoding, guessed: false };
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => Sniffed { encoding: UTF_8, guessed: true },
+        Err(e) if e.error_len().is_none() => Sniffed { encoding: UTF_8, guessed: true },
+        Err(_) => Sniffed { encoding: WINDOWS_1252, guessed: false },
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sniffed {
+    pub encoding: &'static Encoding,
+    /// UTF-8 from a valid head, to be confirmed by the rest of the file
+    pub guessed: bool,
+}
+
+/// The rest of a file sniffed as UTF-8 is not valid UTF-8. Like `decode`,
+/// the caller reads it again as Windows-1252.
+#[derive(Debug)]
+pub struct NotUtf8;
+
+impl std::fmt::Display for NotUtf8 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("the file is not valid UTF-8")
+    }
+}
+
+impl std::error::Error for NotUtf8 {}
+
+/// Whether `error` comes from a strict `DecodeReader` finding invalid
+/// UTF-8, through the CSV reader that was reading from it.
+pub fn is_not_utf8(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        let io = match cause.downcast_ref::<csv::Error>().map(csv::Error::kind) {
+            Some(csv::ErrorKind::Io(io)) => Some(io),
+            _ => cause.downcast_ref::<std::io::Error>(),
+        };
+        io.and_then(|io| io.get_ref()).is_some_and(|inner| inner.is::<NotUtf8>())
+    })
+}
+
+/// Decodes a byte stream to UTF-8 chunk by chunk, dropping the byte order
+/// mark, so large files never have to be held in memory. Invalid sequences
+/// become U+FFFD, or fail with `NotUtf8` when strict.
+pub struct DecodeReader<R> {
+    inner: R,
+    decoder: Decoder,
+    strict: bool,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: Read> DecodeReader<R> {
+    pub fn new(inner: R, encoding: &'static Encoding) -> Self {
+        Self {
+            inner,
+            decoder: encoding.new_decoder_with_bom_removal(),
+            strict: false,
+            input: vec![0; CHUNK_SIZE],
+            output: Vec::new(),
+            position: 0,
+            finished: false,
+        }
+    }
+
+    /// Reads with the sniffed encoding, strictly when it was only a guess.
+    pub fn sniffed(inner: R, sniffed: Sniffed) -> Self {
+        Self { strict: sniffed.guessed, ..Self::new(inner, sniffed.encoding) }
+    }
+
+    /// Decodes the next chunk into `output`. Returns false at the end.
+    fn fill(&mut self) -> std::io::Result<bool> {
+        while self.position >= self.output.len() {
+            if self.finished {
+                return Ok(false);
+            }
+            let read = self.inner.read(&mut self.input)?;
+            let last = read == 0;
+            let capacity = self.decoder.max_utf8_buffer_length(read).unwrap_or(CHUNK_SIZE * 3);
+            self.output.clear();
+            self.output.resize(capacity, 0);
+            let written = if self.strict {
+                let (result, _, written) =
+                    self.decoder.decode_to_utf8_without_replacement(&self.input[..read], &mut self.output, last);
+                if let DecoderResult::Malformed(..) = result {
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, NotUtf8));
+                }
+                written
+            } else {
+                let (_, _, written, _) = self.decoder.decode_to_utf8(&self.input[..read], &mut self.output, last);
+                written
+            };
+            self.output.truncate(written);
+            self.position = 0;
+            self.finished = last;
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if !self.fill()? {
+            return Ok(0);
+        }
+        let available = &self.output[self.position..];
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+pub fn has_bom(bytes: &[u8]) -> bool {
+    Encoding::for_bom(bytes).is_some()
+}
+
+/// Encodes `text` as `decode` found it, with the byte order mark when the
+/// original had one.
+pub fn encode(text: &str, encoding: &'static Encoding, bom: bool) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        // encoding_rs only decodes UTF-16
+        let little_endian = encoding == UTF_16LE;
+        let units = std::iter::once(0xFEFF).filter(|_| bom).chain(text.encode_utf16());
+        for unit in units {
+            let pair = if little_endian { unit.to_le_bytes() } else { unit.to_be_bytes() };
+            bytes.extend_from_slice(&pair);
+        }
+        return bytes;
+    }
+
+    if bom && encoding == UTF_8 {
+        bytes.extend_from_slice(b"\xEF\xBB\xBF");
+    }
+    let (encoded, _, _) = encoding.encode(text);
+    bytes.extend_from_slice(&encoded);
+    bytes
+}
+
+/// Picks the delimiter from the header line, counting only characters
+/// outside quotes. Falls back to a comma.
+pub fn detect_delimiter(text: &str) -> u8 {
+    let header = text.lines().find(|line| !line.trim().is_empty()).unwrap_or("");
+    let candidates = [b';', b',', b'\t', b'|'];
+    let mut counts = [0usize; 4];
+
+    let mut in_quotes = false;
+    for byte in header.bytes() {
+        if byte == b'"' {
+            in_quotes = !in_quotes;
+            continue;
+        }
+        if in_quotes {
+            continue;
+        }
+        if let Some(index) = candidates.iter().position(|c| *c == byte) {
+            counts[index] += 1;
+        }
+    }
+
+    let (best, count) = counts
+        .iter()
+        .enumerate()
+        .max_by_key(|(index, count)| (**count, std::cmp::Reverse(*index)))
+        .map(|(index, count)| (candidates[index], *count))
+        .unwrap_or((b',', 0));
+
+    if count == 0 {
+        b','
+    } else {
+        best
+    }
+}
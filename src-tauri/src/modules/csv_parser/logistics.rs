@@ -0,0 +1,161 @@
+/// Largest package side accepted, in centimetres.
+const MAX_SIDE_CM: f64 = 1000.0;
+/// Heaviest package accepted, in grams.
+const MAX_WEIGHT_G: u32 = 1_000_000;
+pub const MAX_SKU_LEN: usize = 100;
+
+/// Stock count: a whole number, zero or more.
+pub fn parse_stock(raw: &str) -> Result<i64, String> {
+    match raw.parse::<i64>() {
+        Ok(n) if n >= 0 => Ok(n),
+        _ => Err("estoque must be a whole number, zero or more".to_string()),
+    }
+}
+
+/// Weight in whole grams.
+pub fn parse_weight(raw: &str) -> Result<u32, String> {
+    match raw.parse::<u32>() {
+        Ok(0) => Err("peso_g must be greater than zero".to_string()),
+        Ok(n) if n <= MAX_WEIGHT_G => Ok(n),
+        Ok(_) => Err(format!("peso_g is over the limit of {} g", MAX_WEIGHT_G)),
+        Err(_) => Err("peso_g must be a whole number of grams".to_string()),
+    }
+}
+
+/// A package side in centimetres, with either a comma or a dot as decimal
+/// separator ("12,5" or "12.5").
+pub fn parse_side(column: &str, raw: &str) -> Result<f64, String> {
+    let value: f64 = raw
+        .replace(',', ".")
+        .parse()
+        .map_err(|_| format!("{} must be a number of centimetres", column))?;
+    if !value.is_finite() || value <= 0.0 {
+        return Err(format!("{} must be greater than zero", column));
+    }
+    if value > MAX_SIDE_CM {
+        return Err(format!("{} is over the limit of {} cm", column, MAX_SIDE_CM));
+    }
+    Ok(value)
+}
+
+/// EAN/GTIN barcode: 8, 12, 13 or 14 digits with a valid check digit.
+pub fn parse_ean(raw: &str) -> Result<String, String> {
+    if !raw.chars().all(|c| c.is_ascii_digit()) || ![8, 12, 13, 14].contains(&raw.len()) {
+        return Err("ean must have 8, 12, 13 or 14 digits".to_string());
+    }
+
+    let digits: Vec<u32> = raw.chars().filter_map(|c| c.to_digit(10)).collect();
+    let (body, check) = digits.split_at(digits.len() - 1);
+    let sum: u32 = body
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 0 { d * 3 } else { *d })
+        .sum();
+    if (10 - sum % 10) % 10 != check[0] {
+        return Err("ean check digit is wrong".to_string());
+    }
+    Ok(raw.to_string())
+}
+
+/// NCM (Mercosur tariff code): 8 digits, dots allowed as in "6109.10.00".
+/// Returned without dots.
+pub fn parse_ncm(raw: &str) -> Result<String, String> {
+    let digits: String = raw.chars().filter(|c| *c != '.').collect();
+    if digits.len() != 8 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err("ncm must have 8 digits, e.g. 6109.10.00".to_string());
+    }
+    Ok(digits)
+}
+
+pub fn parse_sku(raw: &str) -> Result<String, String> {
+    if raw.chars().count() > MAX_SKU_LEN {
+        return Err(format!("sku is longer than {} characters", MAX_SKU_LEN));
+    }
+    if raw.chars().any(char::is_whitespace) {
+        return Err("sku must not contain spaces".to_string());
+    }
+    Ok(raw.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::csv_parser::{stream_products_file, ImportOptions};
+
+    #[test]
+    fn stock_is_a_whole_number_from_zero() {
+        assert_eq!(parse_stock("0"), Ok(0));
+        assert_eq!(parse_stock("15"), Ok(15));
+        for bad in ["-1", "2,5", "1.0", "dez", ""] {
+            assert!(parse_stock(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn weight_is_whole_grams_within_the_limit() {
+        assert_eq!(parse_weight("250"), Ok(250));
+        assert_eq!(parse_weight("1000000"), Ok(MAX_WEIGHT_G));
+        assert_eq!(parse_weight("0"), Err("peso_g must be greater than zero".to_string()));
+        assert!(parse_weight("1000001").unwrap_err().contains("over the limit"));
+        for bad in ["0,25", "-5", "1kg"] {
+            assert_eq!(parse_weight(bad), Err("peso_g must be a whole number of grams".to_string()), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn sides_take_a_comma_or_a_dot() {
+        assert_eq!(parse_side("altura_cm", "12,5"), Ok(12.5));
+        assert_eq!(parse_side("altura_cm", "12.5"), Ok(12.5));
+        assert_eq!(parse_side("altura_cm", "1000"), Ok(1000.0));
+        assert!(parse_side("altura_cm", "1000,1").unwrap_err().contains("over the limit"));
+        for bad in ["0", "-3", "NaN", "inf"] {
+            let error = parse_side("largura_cm", bad).unwrap_err();
+            assert_eq!(error, "largura_cm must be greater than zero", "{}", bad);
+        }
+        assert_eq!(parse_side("largura_cm", "dez"), Err("largura_cm must be a number of centimetres".to_string()));
+    }
+
+    #[test]
+    fn ean_check_digit_is_verified() {
+        for valid in ["96385074", "036000291452", "7891000315507", "4006381333931", "10012345678902"] {
+            assert_eq!(parse_ean(valid), Ok(valid.to_string()));
+        }
+        assert_eq!(parse_ean("4006381333932"), Err("ean check digit is wrong".to_string()));
+        for bad in ["40063813339", "40063813339310000", "4006381333-93", "ABCDEFGH", ""] {
+            assert_eq!(parse_ean(bad), Err("ean must have 8, 12, 13 or 14 digits".to_string()), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn ncm_has_eight_digits_with_optional_dots() {
+        assert_eq!(parse_ncm("6109.10.00"), Ok("61091000".to_string()));
+        assert_eq!(parse_ncm("61091000"), Ok("61091000".to_string()));
+        for bad in ["6109.10", "6109.10.000", "6109-10-00", "6109.10.0A"] {
+            assert!(parse_ncm(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn invalid_logistics_cells_are_reported_while_parsing() {
+        let csv = "codigo_interno;nome;preco;estoque;ean;ncm;peso_g;altura_cm\n\
+                   A1;Camiseta;10,00;5;7891000315507;6109.10.00;250;2,5\n\
+                   A2;Bone;10,00;-1;7891000315508;6109;0;0\n";
+        let path = std::env::temp_dir().join(format!("logistics-{}.csv", uuid::Uuid::new_v4()));
+        std::fs::write(&path, csv).unwrap();
+        let report = stream_products_file(&path, &ImportOptions::default(), |_| {});
+        std::fs::remove_file(&path).unwrap();
+        let report = report.unwrap();
+
+        let variant = &report.products[0].variants[0];
+        assert_eq!(variant.estoque, Some(5));
+        assert_eq!(variant.ean.as_deref(), Some("7891000315507"));
+        assert_eq!(variant.ncm.as_deref(), Some("61091000"));
+        assert_eq!((variant.peso_g, variant.altura_cm), (Some(250), Some(2.5)));
+
+        let mut columns: Vec<&str> =
+            report.diagnostics.iter().filter(|d| d.line == 3).map(|d| d.column.as_str()).collect();
+        columns.sort_unstable();
+        assert_eq!(columns, ["altura_cm", "ean", "estoque", "ncm", "peso_g"]);
+    }
+}
@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::variants::OPTION_PREFIX;
+use super::Platform;
+use crate::modules::text::slugify;
+
+/// Headers scoring below this are left unmapped.
+const MIN_SCORE: f64 = 0.85;
+pub const SAMPLE_ROWS: usize = 5;
+
+/// Fields the importer understands, with header spellings seen in client
+/// files. Synonyms are compared after `normalize`.
+const FIELDS: [(&str, bool, &[&str]); 21] = [
+    ("codigo_interno", true, &["codigo", "cod", "codigo interno", "codigo produto", "referencia", "ref", "id produto", "code"]),
+    ("nome", true, &["produto", "nome produto", "titulo", "title", "name", "descricao curta"]),
+    ("preco", true, &["valor", "preco venda", "preco de venda", "valor venda", "preco unitario", "price", "pvp"]),
+    ("descricao", false, &["descricao completa", "descricao longa", "detalhes", "description"]),
+    ("uuid", false, &["guid"]),
+    ("codigo_pai", false, &["codigo pai", "produto pai", "pai", "parent", "parent sku"]),
+    ("sku", false, &["codigo sku", "sku variante"]),
+    ("estoque", false, &["quantidade", "qtd", "qtde", "saldo", "estoque atual", "stock", "inventory"]),
+    ("ean", false, &["gtin", "codigo de barras", "codigo barras", "barcode"]),
+    ("ncm", false, &["codigo ncm", "classificacao fiscal"]),
+    ("peso_g", false, &["peso", "peso gramas", "peso g", "weight"]),
+    ("altura_cm", false, &["altura", "height"]),
+    ("largura_cm", false, &["largura", "width"]),
+    ("comprimento_cm", false, &["comprimento", "profundidade", "length"]),
+    ("categoria", false, &["categorias", "departamento", "category"]),
+    ("colecao", false, &["colecoes", "linha", "collection"]),
+    ("tags", false, &["tag", "etiquetas", "palavras chave"]),
+    ("imagens", false, &["imagem", "fotos", "url imagem", "url da imagem", "images", "image src"]),
+    ("opcao_tamanho", false, &["tamanho", "tam", "size", "numeracao"]),
+    ("opcao_cor", false, &["cor", "cores", "color", "colour"]),
+    ("opcao_voltagem", false, &["voltagem", "tensao", "voltage"]),
+];
+
+/// Target field -> header in the source file, e.g. "preco" -> "Valor".
+/// Option columns use `opcao_<name>` as the field.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ColumnMapping(pub BTreeMap<String, String>);
+
+impl ColumnMapping {
+    /// Renames mapped headers to their field names. Headers the mapping does
+    /// not mention are blanked so they cannot match a field by accident.
+    pub fn apply(&self, headers: &[String]) -> Vec<String> {
+        headers
+            .iter()
+            .map(|header| {
+                self.0
+                    .iter()
+                    .find(|(_, source)| source.trim() == header.trim())
+                    .map(|(field, _)| field.clone())
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldInfo {
+    pub name: &'static str,
+    pub required: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MappingSuggestion {
+    pub header: String,
+    pub field: Option<String>,
+    pub score: f64,
+}
+
+/// First phase of an import: what the file looks like and how its columns
+/// would be mapped, for the user to confirm before parsing.
+#[derive(Debug, Default, Serialize)]
+pub struct HeaderPreview {
+    pub headers: Vec<String>,
+    pub samples: Vec<Vec<String>>,
+    pub suggestions: Vec<MappingSuggestion>,
+    pub mapping: ColumnMapping,
+    pub fields: Vec<FieldInfo>,
+    pub format: String,
+    pub delimiter: Option<char>,
+    pub encoding: Option<String>,
+    pub sheet: Option<String>,
+    pub sheets: Vec<String>,
+    /// Exports of other platforms are imported without a mapping
+    pub platform: Option<Platform>,
+}
+
+impl HeaderPreview {
+    pub fn new(headers: Vec<String>, samples: Vec<Vec<String>>) -> Self {
+        let (mapping, suggestions) = suggest_mapping(&headers);
+        Self {
+            platform: Platform::detect(&headers),
+            headers,
+            samples,
+            suggestions,
+            mapping,
+            fields: FIELDS
+                .iter()
+                .map(|(name, required, _)| FieldInfo { name, required: *required })
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Pairs headers with fields by fuzzy matching, best scores first, each
+/// field and header used at most once. Headers already named `opcao_*`
+/// map to themselves.
+pub fn suggest_mapping(headers: &[String]) -> (ColumnMapping, Vec<MappingSuggestion>) {
+    let mut candidates: Vec<(f64, usize, String)> = Vec::new();
+    for (index, header) in headers.iter().enumerate() {
+        let trimmed = header.trim();
+        if trimmed.len() > OPTION_PREFIX.len() && trimmed.starts_with(OPTION_PREFIX) {
+            candidates.push((1.0, index, trimmed.to_string()));
+        }
+        for (field, _, synonyms) in FIELDS.iter() {
+            let score = score(header, field, synonyms);
+            if score >= MIN_SCORE {
+                candidates.push((score, index, field.to_string()));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    let mut mapping = ColumnMapping::default();
+    let mut suggestions: Vec<MappingSuggestion> = headers
+        .iter()
+        .map(|header| MappingSuggestion { header: header.clone(), field: None, score: 0.0 })
+        .collect();
+    for (score, index, field) in candidates {
+        if suggestions[index].field.is_some() || mapping.0.contains_key(&field) {
+            continue;
+        }
+        mapping.0.insert(field.clone(), headers[index].trim().to_string());
+        suggestions[index].field = Some(field);
+        suggestions[index].score = score;
+    }
+
+    (mapping, suggestions)
+}
+
+/// "Preço de Venda (R$)" -> "preco de venda r", how headers are compared
+/// here and in the platform importers.
+pub(super) fn normalize(header: &str) -> String {
+    slugify(header).replace('-', " ")
+}
+
+fn score(header: &str, field: &str, synonyms: &[&str]) -> f64 {
+    let header = normalize(header);
+    if header.is_empty() {
+        return 0.0;
+    }
+
+    let field_name = field.replace('_', " ");
+    std::iter::once(field_name.as_str())
+        .chain(synonyms.iter().copied())
+        .map(|candidate| {
+            if header == candidate {
+                1.0
+            } else if header.starts_with(&format!("{} ", candidate)) {
+                // "preco venda r" still means "preco venda"
+                0.9
+            } else {
+                strsim::jaro_winkler(&header, candidate)
+            }
+        })
+        .fold(0.0, f64::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numeric_id_column_is_not_taken_as_uuid() {
+        let headers: Vec<String> = ["ID", "Código", "Produto", "Preço"].iter().map(|h| h.to_string()).collect();
+        let (mapping, _) = suggest_mapping(&headers);
+        assert_eq!(mapping.0.get("uuid"), None);
+        assert_eq!(mapping.0.get("codigo_interno").map(String::as_str), Some("Código"));
+        assert_eq!(mapping.0.get("preco").map(String::as_str), Some("Preço"));
+    }
+}
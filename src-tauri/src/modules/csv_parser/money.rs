@@ -0,0 +1,357 @@
+/// Parses a price as typed in a spreadsheet into integer centavos, accepting
+/// both pt-BR (`1.299,90`, `R$ 29,90`) and plain (`29.90`) notations.
+///
+/// When both separators appear the last one is the decimal separator. A lone
+/// separator followed by exactly three digits is a thousands separator
+/// (`1.299` is R$ 1.299,00) unless the integer part is `0`, anything else is
+/// a decimal separator. More than two decimal places is rejected rather than
+/// rounded, and so is text without any digit.
+pub fn parse_centavos(raw: &str) -> Result<i64, String> {
+    let mut text: String = raw
+        .trim()
+        .trim_start_matches("R$")
+        .trim_start_matches("r$")
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '\u{a0}')
+        .collect();
+
+    if text.is_empty() {
+        return Err("price is empty".to_string());
+    }
+
+    let negative = if text.starts_with('(') && text.ends_with(')') {
+        text = text[1..text.len() - 1].to_string();
+        true
+    } else if let Some(rest) = text.strip_prefix('-') {
+        text = rest.to_string();
+        true
+    } else {
+        false
+    };
+    let text = text.trim_start_matches("R$").to_string();
+
+    if !text.chars().any(|c| c.is_ascii_digit())
+        || !text.chars().all(|c| c.is_ascii_digit() || c == '.' || c == ',')
+    {
+        return Err(format!("'{}' is not a valid price", raw.trim()));
+    }
+
+    let last_dot = text.rfind('.');
+    let last_comma = text.rfind(',');
+    let decimal_separator = match (last_dot, last_comma) {
+        (Some(dot), Some(comma)) => Some(if dot > comma { '.' } else { ',' }),
+        (Some(_), None) => lone_separator_decimal(&text, '.'),
+        (None, Some(_)) => lone_separator_decimal(&text, ','),
+        (None, None) => None,
+    };
+
+    let (integer_part, fraction_part) = match decimal_separator {
+        Some(sep) => {
+            let index = text.rfind(sep).unwrap_or(text.len());
+            (&text[..index], &text[index + 1..])
+        }
+        None => (text.as_str(), ""),
+    };
+
+    let thousands_separator = match decimal_separator {
+        Some('.') => ',',
+        Some(_) => '.',
+        None => if text.contains('.') { '.' } else { ',' },
+    };
+    let integer_digits = validate_groups(integer_part, thousands_separator)
+        .ok_or_else(|| format!("'{}' is not a valid price", raw.trim()))?;
+
+    if fraction_part.len() > 2 || !fraction_part.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("'{}' has more than two decimal places", raw.trim()));
+    }
+
+    let units: i64 = if integer_digits.is_empty() {
+        0
+    } else {
+        integer_digits
+            .parse()
+            .map_err(|_| format!("'{}' is out of range", raw.trim()))?
+    };
+    let cents: i64 = format!("{:0<2}", fraction_part).parse().unwrap_or(0);
+
+    let total = units
+        .checked_mul(100)
+        .and_then(|v| v.checked_add(cents))
+        .ok_or_else(|| format!("'{}' is out of range", raw.trim()))?;
+
+    Ok(if negative { -total } else { total })
+}
+
+/// With a single kind of separator, `1.299` / `1,299` and repeated
+/// separators (`1.299.000`) are thousands groups, anything else is decimal.
+/// A leading `0` never starts a thousands group, so `0.999` is decimal.
+fn lone_separator_decimal(text: &str, separator: char) -> Option<char> {
+    let occurrences = text.matches(separator).count();
+    let after = text.len() - text.rfind(separator).unwrap_or(0) - 1;
+    let leading_zero = text.split(separator).next().is_some_and(|group| group.starts_with('0'));
+    if !leading_zero && (occurrences > 1 || after == 3) {
+        None
+    } else {
+        Some(separator)
+    }
+}
+
+/// Strips thousands separators, checking that every group after the first
+/// has exactly three digits.
+fn validate_groups(integer_part: &str, separator: char) -> Option<String> {
+    let groups: Vec<&str> = integer_part.split(separator).collect();
+    if groups.len() > 1 {
+        let first_ok = !groups[0].is_empty() && groups[0].len() <= 3 && !groups[0].starts_with('0');
+        let rest_ok = groups[1..].iter().all(|g| g.len() == 3);
+        if !first_ok || !rest_ok {
+            return None;
+        }
+    }
+
+    let digits: String = groups.concat();
+    if digits.chars().all(|c| c.is_ascii_digit()) {
+        Some(digits)
+    } else {
+        None
+    }
+}
+
+/// ISO 4217 currency code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const BRL: Currency = Currency(*b"BRL");
+
+    pub fn new(code: &str) -> Result<Self, String> {
+        let upper = code.trim().to_ascii_uppercase();
+        let bytes = upper.as_bytes();
+        if bytes.len() != 3 || !bytes.iter().all(u8::is_ascii_uppercase) {
+            return Err(format!("'{}' is not an ISO 4217 currency code", code));
+        }
+        Ok(Self([bytes[0], bytes[1], bytes[2]]))
+    }
+
+    pub fn code(&self) -> &str {
+        // Always ASCII, checked in `new`
+        std::str::from_utf8(&self.0).unwrap_or("BRL")
+    }
+}
+
+impl std::fmt::Display for Currency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// An exact amount in minor units (centavos for BRL), so amounts read from
+/// spreadsheets are never rounded. Medusa v2 takes prices in major units,
+/// converted only at the API boundary by `to_major_f64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Money {
+    pub centavos: i64,
+    pub currency: Currency,
+}
+
+impl Money {
+    pub fn new(centavos: i64, currency: Currency) -> Self {
+        Self { centavos, currency }
+    }
+
+    pub fn brl(centavos: i64) -> Self {
+        Self::new(centavos, Currency::BRL)
+    }
+
+    /// Parses a spreadsheet price, see `parse_centavos`.
+    pub fn parse(raw: &str, currency: Currency) -> Result<Self, String> {
+        parse_centavos(raw).map(|centavos| Self::new(centavos, currency))
+    }
+
+    /// Amount in minor units.
+    pub fn minor_units(&self) -> i64 {
+        self.centavos
+    }
+
+    /// Amount in major units, as the Medusa v2 API takes and returns prices,
+    /// e.g. 49.9 for R$ 49,90.
+    pub fn to_major_f64(self) -> f64 {
+        self.centavos as f64 / 100.0
+    }
+
+    /// Plain decimal notation with two places, e.g. `1299.90`.
+    pub fn to_decimal_string(self) -> String {
+        let sign = if self.centavos < 0 { "-" } else { "" };
+        let abs = self.centavos.unsigned_abs();
+        format!("{}{}.{:02}", sign, abs / 100, abs % 100)
+    }
+
+    /// pt-BR notation as written back to spreadsheets, e.g. `1.299,90`.
+    pub fn to_ptbr_string(self) -> String {
+        let sign = if self.centavos < 0 { "-" } else { "" };
+        let abs = self.centavos.unsigned_abs();
+        let units = (abs / 100).to_string();
+
+        let mut grouped = String::new();
+        for (index, digit) in units.chars().enumerate() {
+            if index > 0 && (units.len() - index).is_multiple_of(3) {
+                grouped.push('.');
+            }
+            grouped.push(digit);
+        }
+        format!("{}{},{:02}", sign, grouped, abs % 100)
+    }
+
+    /// Converts a floating point amount in major units, rounding to the
+    /// nearest centavo. Only for values that never were exact, such as
+    /// numbers typed into a spreadsheet cell.
+    pub fn from_major_f64(amount: f64, currency: Currency) -> Result<Self, String> {
+        let scaled = (amount * 100.0).round();
+        if !scaled.is_finite() || scaled.abs() > i64::MAX as f64 {
+            return Err(format!("{} is not a valid amount", amount));
+        }
+        Ok(Self::new(scaled as i64, currency))
+    }
+}
+
+impl std::fmt::Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.currency, self.to_decimal_string())
+    }
+}
+
+impl serde::Serialize for Money {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("Money", 2)?;
+        state.serialize_field("centavos", &self.centavos)?;
+        state.serialize_field("currency", self.currency.code())?;
+        state.end()
+    }
+}
+
+/// Accepts the serialized form, `{ "centavos": 2990, "currency": "BRL" }`,
+/// as well as a bare decimal string (`"29,90"`) or number (`29.9`) in BRL,
+/// which is how wizard states saved before this type stored prices.
+impl<'de> serde::Deserialize<'de> for Money {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Full {
+                centavos: i64,
+                #[serde(default)]
+                currency: Option<String>,
+            },
+            Integer(i64),
+            Number(f64),
+            Text(String),
+        }
+
+        let money = match Repr::deserialize(deserializer)? {
+            Repr::Full { centavos, currency } => {
+                let currency = match currency {
+                    Some(code) => Currency::new(&code).map_err(serde::de::Error::custom)?,
+                    None => Currency::BRL,
+                };
+                Money::new(centavos, currency)
+            }
+            Repr::Integer(units) => units
+                .checked_mul(100)
+                .map(Money::brl)
+                .ok_or_else(|| serde::de::Error::custom("amount out of range"))?,
+            Repr::Number(amount) => {
+                Money::from_major_f64(amount, Currency::BRL).map_err(serde::de::Error::custom)?
+            }
+            Repr::Text(text) => Money::parse(&text, Currency::BRL).map_err(serde::de::Error::custom)?,
+        };
+        Ok(money)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ptbr_and_plain_notations() {
+        assert_eq!(parse_centavos("1.299,90"), Ok(129990));
+        assert_eq!(parse_centavos("29.90"), Ok(2990));
+        assert_eq!(parse_centavos("29,9"), Ok(2990));
+        assert_eq!(parse_centavos("1,299.90"), Ok(129990));
+        assert_eq!(parse_centavos("1.299.000"), Ok(129900000));
+        assert_eq!(parse_centavos("R$ 0,01"), Ok(1));
+        assert_eq!(parse_centavos("R$\u{a0}49,90"), Ok(4990));
+        assert_eq!(parse_centavos(",50"), Ok(50));
+        assert_eq!(parse_centavos("7"), Ok(700));
+    }
+
+    #[test]
+    fn lone_separator_before_three_digits_is_thousands() {
+        assert_eq!(parse_centavos("1.299"), Ok(129900));
+        assert_eq!(parse_centavos("1,299"), Ok(129900));
+    }
+
+    #[test]
+    fn parses_negatives() {
+        assert_eq!(parse_centavos("-29,90"), Ok(-2990));
+        assert_eq!(parse_centavos("(29,90)"), Ok(-2990));
+        assert_eq!(parse_centavos("-R$ 1.299,90"), Ok(-129990));
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        for raw in ["", "   ", "R$", ".", ",", ",,", "-", "()", "abc", "12a", "1.2.3", "12.34.567", "1,2345"] {
+            assert!(parse_centavos(raw).is_err(), "{:?} was accepted", raw);
+        }
+    }
+
+    #[test]
+    fn rejects_more_than_two_decimal_places() {
+        assert!(parse_centavos("0.999").is_err());
+        assert!(parse_centavos("0,999").is_err());
+        assert!(parse_centavos("1.299,901").is_err());
+    }
+
+    #[test]
+    fn rejects_overflow() {
+        assert!(parse_centavos("92233720368547759").is_err());
+        assert!(parse_centavos("99999999999999999999,99").is_err());
+        assert_eq!(parse_centavos("92233720368547758,07"), Ok(i64::MAX));
+    }
+
+    #[test]
+    fn formats_ptbr() {
+        assert_eq!(Money::brl(129990).to_ptbr_string(), "1.299,90");
+        assert_eq!(Money::brl(1).to_ptbr_string(), "0,01");
+        assert_eq!(Money::brl(100000000).to_ptbr_string(), "1.000.000,00");
+        assert_eq!(Money::brl(-2990).to_ptbr_string(), "-29,90");
+        assert_eq!(Money::brl(-129990).to_decimal_string(), "-1299.90");
+    }
+
+    #[test]
+    fn ptbr_string_round_trips() {
+        for centavos in [0, 1, 99, 100, 2990, 129990, 100000000, -1, -129990, i64::MAX, i64::MIN + 1] {
+            let text = Money::brl(centavos).to_ptbr_string();
+            assert_eq!(parse_centavos(&text), Ok(centavos), "{}", text);
+            let decimal = Money::brl(centavos).to_decimal_string();
+            assert_eq!(parse_centavos(&decimal), Ok(centavos), "{}", decimal);
+        }
+    }
+
+    #[test]
+    fn major_f64_round_trips() {
+        for centavos in [0, 1, 10, 99, 2990, 4990, 129990, 2198, -2990, 99999999999] {
+            let money = Money::brl(centavos);
+            assert_eq!(Money::from_major_f64(money.to_major_f64(), Currency::BRL), Ok(money));
+        }
+        assert_eq!(Money::from_major_f64(21.989, Currency::BRL), Ok(Money::brl(2199)));
+        assert_eq!(Money::from_major_f64(0.1 + 0.2, Currency::BRL), Ok(Money::brl(30)));
+    }
+
+    #[test]
+    fn major_f64_rejects_non_finite_and_overflow() {
+        for amount in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, 1e18] {
+            assert!(Money::from_major_f64(amount, Currency::BRL).is_err(), "{} was accepted", amount);
+        }
+    }
+}
@@ -0,0 +1,437 @@
+//! Product exports of other e-commerce platforms. Each importer rewrites
+//! the export into our own columns, so the rows go through the same
+//! validation and variant grouping as a file made by hand.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::mapping::normalize;
+use super::variants::OPTION_PREFIX;
+use super::Table;
+use crate::modules::text::slugify;
+
+mod nuvemshop;
+mod shopify;
+mod tray;
+mod woocommerce;
+
+/// Our columns, in the order translated tables use them. Option columns
+/// follow.
+const COLUMNS: [&str; 18] = [
+    "uuid",
+    "codigo_interno",
+    "codigo_pai",
+    "nome",
+    "preco",
+    "descricao",
+    "sku",
+    "estoque",
+    "ean",
+    "ncm",
+    "peso_g",
+    "altura_cm",
+    "largura_cm",
+    "comprimento_cm",
+    "categoria",
+    "colecao",
+    "tags",
+    "imagens",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    WooCommerce,
+    Shopify,
+    Nuvemshop,
+    Tray,
+}
+
+impl Platform {
+    /// Recognizes an export by the headers only its platform writes.
+    pub fn detect(headers: &[String]) -> Option<Self> {
+        let source = Source::new(headers);
+        if source.has("handle") && source.has("variant price") {
+            Some(Self::Shopify)
+        } else if source.has("identificador url") {
+            Some(Self::Nuvemshop)
+        } else if (source.has("type") || source.has("tipo")) && (source.has("parent") || source.has("ascendente")) {
+            Some(Self::WooCommerce)
+        } else if source.has("referencia") && source.has_any(tray::PARENT) {
+            Some(Self::Tray)
+        } else {
+            None
+        }
+    }
+
+    pub fn translate(self, headers: &[String], rows: Vec<(u64, Vec<String>)>) -> Table {
+        let source = Source::new(headers);
+        let rows: Vec<Record> = rows
+            .into_iter()
+            .filter(|(_, cells)| cells.iter().any(|c| !c.trim().is_empty()))
+            .map(|(line, cells)| Record { line, cells })
+            .collect();
+
+        let mut output = Output::default();
+        match self {
+            Self::WooCommerce => woocommerce::translate(&source, &rows, &mut output),
+            Self::Shopify => shopify::translate(&source, &rows, &mut output),
+            Self::Nuvemshop => nuvemshop::translate(&source, &rows, &mut output),
+            Self::Tray => tray::translate(&source, &rows, &mut output),
+        }
+        output.into_table()
+    }
+}
+
+struct Record {
+    line: u64,
+    cells: Vec<String>,
+}
+
+/// Header lookup that ignores case, accents and punctuation, so "Peso (kg)"
+/// is found as "peso kg".
+struct Source {
+    index: HashMap<String, usize>,
+}
+
+impl Source {
+    fn new(headers: &[String]) -> Self {
+        let mut index = HashMap::new();
+        for (i, header) in headers.iter().enumerate() {
+            index.entry(normalize(header)).or_insert(i);
+        }
+        Self { index }
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.index.contains_key(name)
+    }
+
+    fn has_any(&self, names: &[&str]) -> bool {
+        names.iter().any(|name| self.has(name))
+    }
+
+    /// The trimmed cell under the first of `names` present in the file.
+    fn get<'a>(&self, record: &'a Record, names: &[&str]) -> &'a str {
+        names
+            .iter()
+            .find_map(|name| self.index.get(*name))
+            .and_then(|&i| record.cells.get(i))
+            .map(|cell| cell.trim())
+            .unwrap_or("")
+    }
+}
+
+/// Rows sharing a `key`, e.g. Shopify's handle, in the order each key first
+/// appears.
+fn group_by<'a>(rows: &'a [Record], key: impl Fn(&'a Record) -> &'a str) -> Vec<(&'a str, Vec<&'a Record>)> {
+    let mut groups: Vec<(&str, Vec<&Record>)> = Vec::new();
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    for row in rows {
+        let key = key(row);
+        match index.get(key) {
+            Some(&i) => groups[i].1.push(row),
+            None => {
+                index.insert(key, groups.len());
+                groups.push((key, vec![row]));
+            }
+        }
+    }
+    groups
+}
+
+/// Rows in our columns. Option columns are added as the importers name them.
+#[derive(Default)]
+struct Output {
+    options: Vec<String>,
+    rows: Vec<(u64, HashMap<String, String>)>,
+}
+
+impl Output {
+    fn push(&mut self, line: u64, row: Fields) {
+        for column in row.0.keys().filter(|c| c.starts_with(OPTION_PREFIX)) {
+            if !self.options.contains(column) {
+                self.options.push(column.clone());
+            }
+        }
+        self.rows.push((line, row.0));
+    }
+
+    fn into_table(self) -> Table {
+        let headers: Vec<String> = COLUMNS
+            .iter()
+            .map(|c| c.to_string())
+            .chain(self.options)
+            .collect();
+        let rows = self
+            .rows
+            .into_iter()
+            .map(|(line, mut fields)| {
+                let cells = headers
+                    .iter()
+                    .map(|h| fields.remove(h).unwrap_or_default())
+                    .collect();
+                (line, cells)
+            })
+            .collect();
+        (headers, rows)
+    }
+}
+
+/// One translated row, our column -> cell text.
+#[derive(Default, Clone)]
+struct Fields(HashMap<String, String>);
+
+impl Fields {
+    /// Sets `column` unless `value` is empty.
+    fn set(&mut self, column: &str, value: impl Into<String>) {
+        let value = value.into();
+        if !value.trim().is_empty() {
+            self.0.insert(column.to_string(), value);
+        }
+    }
+
+    /// Sets the option column for an option named `name` on the platform,
+    /// e.g. "Tamanho" -> `opcao_tamanho`.
+    fn option(&mut self, name: &str, value: &str) {
+        let column = slugify(name).replace('-', "_");
+        if !column.is_empty() {
+            self.set(&format!("{}{}", OPTION_PREFIX, column), value);
+        }
+    }
+}
+
+/// "0,350" kg -> "350" g. Zero means "not set" on every platform and is
+/// dropped; text that is not a number is passed on for validation to report.
+fn kg_to_grams(raw: &str) -> String {
+    match raw.replace(',', ".").parse::<f64>() {
+        Ok(kg) if kg > 0.0 => format!("{}", (kg * 1000.0).round() as i64),
+        Ok(_) => String::new(),
+        Err(_) => raw.to_string(),
+    }
+}
+
+/// Whole grams, with zero meaning "not set".
+fn grams(raw: &str) -> String {
+    match raw.replace(',', ".").parse::<f64>() {
+        Ok(g) if g > 0.0 => format!("{}", g.round() as i64),
+        Ok(_) => String::new(),
+        Err(_) => raw.to_string(),
+    }
+}
+
+/// Zero dimensions mean "not set".
+fn dimension(raw: &str) -> String {
+    match raw.replace(',', ".").parse::<f64>() {
+        Ok(value) if value <= 0.0 => String::new(),
+        _ => raw.to_string(),
+    }
+}
+
+/// Splits a comma separated list where a literal comma is written "\,", as
+/// WooCommerce does in category names.
+fn split_list(raw: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&',') => {
+                current.push(',');
+                chars.next();
+            }
+            ',' => items.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    items.push(current);
+    items
+        .into_iter()
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// The first category of a list such as "Roupas > Camisetas, Promoções";
+/// our products have a single category.
+fn first_category(raw: &str) -> String {
+    split_list(raw).into_iter().next().unwrap_or_default()
+}
+
+/// Adds `urls` to `images`, skipping repeats.
+fn add_images<'a>(images: &mut Vec<String>, urls: impl IntoIterator<Item = &'a str>) {
+    for url in urls.into_iter().map(str::trim).filter(|u| !u.is_empty()) {
+        if !images.iter().any(|i| i == url) {
+            images.push(url.to_string());
+        }
+    }
+}
+
+/// Plain text from an HTML description: paragraphs and line breaks become
+/// new lines, other tags are dropped and common entities decoded.
+fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = rest[start + 1..start + end].trim().to_lowercase();
+        let name = tag.trim_start_matches('/').split([' ', '/']).next().unwrap_or("");
+        if matches!(name, "br" | "p" | "div" | "li" | "h1" | "h2" | "h3" | "h4" | "tr") && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Product level fields shared by every variant row of a product.
+#[derive(Default, Clone)]
+struct ProductFields {
+    nome: String,
+    descricao: String,
+    categoria: String,
+    tags: String,
+    imagens: Vec<String>,
+}
+
+impl ProductFields {
+    fn apply(&self, fields: &mut Fields) {
+        fields.set("nome", self.nome.as_str());
+        fields.set("descricao", self.descricao.as_str());
+        fields.set("categoria", self.categoria.as_str());
+        fields.set("tags", self.tags.as_str());
+        fields.set("imagens", self.imagens.join(" | "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::csv_parser::{stream_products_file, ImportOptions, ParseReport, Product};
+    use std::path::Path;
+
+    fn import(platform: &str) -> ParseReport {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("../examples/importacao/{}.csv", platform));
+        let report = stream_products_file(&path, &ImportOptions::default(), |_| {}).unwrap();
+        assert_eq!(report.rejected_rows, 0, "{:?}", report.diagnostics);
+        report
+    }
+
+    fn product<'a>(report: &'a ParseReport, codigo: &str) -> &'a Product {
+        report.products.iter().find(|p| p.codigo_interno == codigo).unwrap()
+    }
+
+    fn skus(product: &Product) -> Vec<&str> {
+        product.variants.iter().map(|v| v.sku.as_str()).collect()
+    }
+
+    #[test]
+    fn imports_shopify_export() {
+        let report = import("shopify");
+        assert_eq!(report.platform, Some(Platform::Shopify));
+        assert_eq!(report.products.len(), 2);
+
+        let camiseta = product(&report, "camiseta-basica");
+        assert_eq!(camiseta.nome, "Camiseta Básica");
+        assert_eq!(camiseta.preco.to_ptbr_string(), "59,90");
+        assert_eq!(camiseta.descricao, "Camiseta 100% algodão.\nLavar à mão & secar à sombra.");
+        assert_eq!(camiseta.categoria, ["Camisetas"]);
+        assert_eq!(camiseta.tags, ["verão", "algodão"]);
+        assert_eq!(skus(camiseta), ["CAM-P-BR", "CAM-M-BR", "CAM-M-PT"]);
+        assert_eq!(camiseta.variants[2].preco.map(|p| p.to_ptbr_string()).as_deref(), Some("64,90"));
+        assert_eq!(camiseta.variants[2].options["Cor"], "Preto");
+        assert_eq!(camiseta.variants[0].estoque, Some(10));
+        assert_eq!(camiseta.variants[0].peso_g, Some(180));
+        // Image-only rows add images, not variants
+        assert_eq!(camiseta.imagens.len(), 4);
+    }
+
+    #[test]
+    fn imports_nuvemshop_export() {
+        let report = import("nuvemshop");
+        assert_eq!(report.platform, Some(Platform::Nuvemshop));
+
+        let vestido = product(&report, "vestido-floral");
+        assert_eq!(vestido.nome, "Vestido Floral");
+        assert_eq!(vestido.categoria, ["Roupas", "Vestidos"]);
+        assert_eq!(skus(vestido), ["VES-FLO-P", "VES-FLO-M", "VES-FLO-G"]);
+        assert_eq!(vestido.variants[2].preco.map(|p| p.to_ptbr_string()).as_deref(), Some("199,90"));
+        assert_eq!(vestido.variants[2].peso_g, Some(380));
+        assert_eq!(vestido.variants[0].comprimento_cm, Some(35.0));
+
+        let bolsa = product(&report, "bolsa-palha");
+        assert_eq!(bolsa.preco.to_ptbr_string(), "129,00");
+        assert_eq!(bolsa.categoria, ["Acessórios"]);
+        assert_eq!(bolsa.variants[0].ean.as_deref(), Some("7891234567895"));
+    }
+
+    #[test]
+    fn imports_tray_export() {
+        let report = import("tray");
+        assert_eq!(report.platform, Some(Platform::Tray));
+
+        let luminaria = product(&report, "LUM-MESA");
+        assert_eq!(luminaria.preco.to_ptbr_string(), "149,90");
+        assert_eq!(skus(luminaria), ["LUM-MESA-127", "LUM-MESA-220"]);
+        assert_eq!(luminaria.variants[1].ncm.as_deref(), Some("94052000"));
+        assert_eq!(luminaria.variants[1].peso_g, Some(1200));
+        assert_eq!(luminaria.imagens.len(), 3);
+
+        let tapete = product(&report, "TAP-SALA");
+        assert_eq!(tapete.preco.to_ptbr_string(), "389,00");
+        assert_eq!(tapete.categoria, ["Casa", "Decoração"]);
+    }
+
+    #[test]
+    fn imports_woocommerce_export() {
+        let report = import("woocommerce");
+        assert_eq!(report.platform, Some(Platform::WooCommerce));
+
+        let tenis = product(&report, "TENIS-RUN");
+        assert_eq!(tenis.nome, "Tênis de Corrida");
+        assert_eq!(tenis.categoria, ["Calçados", "Tênis"]);
+        // The last variation has no SKU and points to its parent by SKU
+        assert_eq!(skus(tenis), ["TENIS-RUN-38", "TENIS-RUN-39", "TENIS-RUN-13"]);
+        assert_eq!(tenis.variants[2].preco.map(|p| p.to_ptbr_string()).as_deref(), Some("319,90"));
+        assert_eq!(tenis.variants[2].peso_g, Some(650));
+
+        let mochila = product(&report, "MOCHILA-20L");
+        assert_eq!(mochila.categoria, ["Acessórios, Bolsas e Mochilas"]);
+    }
+
+    #[test]
+    fn shopify_hs_code_is_kept_only_as_a_full_ncm() {
+        let headers: Vec<String> =
+            ["Handle", "Title", "Variant SKU", "Variant Price", "Variant HS Code", "Variant Tax Code"]
+                .iter()
+                .map(|h| h.to_string())
+                .collect();
+        let row = |line: u64, sku: &str, hs: &str| {
+            let cells = ["caneca", "Caneca", sku, "39.90", hs, "P0000000"];
+            (line, cells.iter().map(|c| c.to_string()).collect())
+        };
+        let rows = vec![row(2, "CAN-1", "691200"), row(3, "CAN-2", "6912.00.00")];
+
+        let (headers, rows) = Platform::Shopify.translate(&headers, rows);
+        let ncm = headers.iter().position(|h| h == "ncm").unwrap();
+        assert_eq!(rows[0].1[ncm], "");
+        assert_eq!(rows[1].1[ncm], "6912.00.00");
+    }
+}
@@ -0,0 +1,59 @@
+//! Nuvemshop product CSV. Rows sharing an `Identificador URL` are the
+//! variants of one product; the first row carries the name, description,
+//! categories and variation names, later rows may leave them empty.
+
+use super::{
+    add_images, dimension, first_category, group_by, html_to_text, kg_to_grams, Fields, Output, ProductFields, Record,
+    Source,
+};
+
+const VARIATION_SLOTS: usize = 3;
+const IMAGES: &[&str] = &["imagens", "url da imagem", "url imagem", "imagem"];
+
+pub(super) fn translate(source: &Source, rows: &[Record], output: &mut Output) {
+    for (handle, group) in group_by(rows, |row| source.get(row, &["identificador url"])) {
+        let head = group[0];
+        let mut product = ProductFields {
+            nome: source.get(head, &["nome"]).to_string(),
+            descricao: html_to_text(source.get(head, &["descricao"])),
+            categoria: first_category(source.get(head, &["categorias"])),
+            tags: source.get(head, &["tags"]).to_string(),
+            imagens: Vec::new(),
+        };
+        for row in &group {
+            add_images(&mut product.imagens, source.get(row, IMAGES).split(['|', ',']));
+        }
+
+        let mut variation_names = vec![String::new(); VARIATION_SLOTS];
+        for row in group {
+            for (n, name) in variation_names.iter_mut().enumerate() {
+                let on_row = source.get(row, &[&format!("nome da variacao {}", n + 1)]);
+                if !on_row.is_empty() {
+                    *name = on_row.to_string();
+                }
+            }
+
+            let mut fields = Fields::default();
+            let sku = source.get(row, &["sku"]);
+            fields.set("codigo_pai", handle);
+            fields.set("codigo_interno", if sku.is_empty() { handle } else { sku });
+            fields.set("sku", sku);
+            fields.set("preco", source.get(row, &["preco"]));
+            fields.set("estoque", source.get(row, &["estoque"]));
+            fields.set("ean", source.get(row, &["codigo de barras"]));
+            fields.set("peso_g", kg_to_grams(source.get(row, &["peso kg"])));
+            fields.set("altura_cm", dimension(source.get(row, &["altura cm"])));
+            fields.set("largura_cm", dimension(source.get(row, &["largura cm"])));
+            fields.set("comprimento_cm", dimension(source.get(row, &["comprimento cm"])));
+            product.apply(&mut fields);
+
+            for (n, name) in variation_names.iter().enumerate() {
+                if !name.is_empty() {
+                    fields.option(name, source.get(row, &[&format!("valor da variacao {}", n + 1)]));
+                }
+            }
+
+            output.push(row.line, fields);
+        }
+    }
+}
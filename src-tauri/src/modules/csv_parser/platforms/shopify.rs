@@ -0,0 +1,79 @@
+//! Shopify product CSV. Rows sharing a `Handle` are one product: the first
+//! carries the product fields, every row with a price or option value is a
+//! variant and rows with only `Image Src` add images.
+
+use super::{add_images, grams, group_by, html_to_text, Fields, Output, ProductFields, Record, Source};
+
+const OPTION_SLOTS: usize = 3;
+/// What Shopify writes in `Option1 Name` for products without options
+const NO_OPTION_NAME: &str = "Title";
+
+pub(super) fn translate(source: &Source, rows: &[Record], output: &mut Output) {
+    for (handle, group) in group_by(rows, |row| source.get(row, &["handle"])) {
+        let head = group
+            .iter()
+            .find(|row| !source.get(row, &["title"]).is_empty())
+            .unwrap_or(&group[0]);
+
+        let mut product = ProductFields {
+            nome: source.get(head, &["title"]).to_string(),
+            descricao: html_to_text(source.get(head, &["body html"])),
+            categoria: [source.get(head, &["type"]), source.get(head, &["product category"])]
+                .into_iter()
+                .find(|c| !c.is_empty())
+                .unwrap_or("")
+                .to_string(),
+            tags: source.get(head, &["tags"]).to_string(),
+            imagens: Vec::new(),
+        };
+        for row in &group {
+            add_images(&mut product.imagens, [source.get(row, &["image src"]), source.get(row, &["variant image"])]);
+        }
+
+        let option_names: Vec<String> = (1..=OPTION_SLOTS)
+            .map(|n| source.get(head, &[&format!("option{} name", n)]).to_string())
+            .collect();
+
+        for row in group.iter().filter(|row| is_variant(source, row)) {
+            let mut fields = Fields::default();
+            let sku = source.get(row, &["variant sku"]);
+            fields.set("codigo_pai", handle);
+            fields.set("codigo_interno", if sku.is_empty() { handle } else { sku });
+            fields.set("sku", sku);
+            fields.set("preco", source.get(row, &["variant price"]));
+            fields.set("estoque", source.get(row, &["variant inventory qty"]));
+            fields.set("ean", source.get(row, &["variant barcode"]));
+            fields.set("ncm", ncm(source.get(row, &["variant hs code"])));
+            fields.set("peso_g", grams(source.get(row, &["variant grams"])));
+            product.apply(&mut fields);
+
+            for (n, name) in option_names.iter().enumerate() {
+                let value = source.get(row, &[&format!("option{} value", n + 1)]);
+                if !name.is_empty() && name != NO_OPTION_NAME {
+                    fields.option(name, value);
+                }
+            }
+
+            output.push(row.line, fields);
+        }
+    }
+}
+
+/// `Variant HS Code` is the 6 digit international code, which the NCM
+/// extends. Stores that sell in Brazil often fill in the full 8 digit NCM,
+/// the only value kept.
+fn ncm(hs_code: &str) -> &str {
+    let digits = hs_code.chars().filter(|c| *c != '.').collect::<String>();
+    if digits.len() == 8 && digits.chars().all(|c| c.is_ascii_digit()) {
+        hs_code
+    } else {
+        ""
+    }
+}
+
+/// Image-only rows have neither a price, a SKU nor an option value.
+fn is_variant(source: &Source, row: &Record) -> bool {
+    ["variant price", "variant sku", "option1 value"]
+        .iter()
+        .any(|name| !source.get(row, &[name]).is_empty())
+}
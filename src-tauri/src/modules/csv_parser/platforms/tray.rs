@@ -0,0 +1,153 @@
+//! Tray product spreadsheet. Each row is a product; variations are rows
+//! whose parent column holds the id of their product, with up to three
+//! "Variação N" / "Valor da variação N" pairs. A product with variations is
+//! sold only through them. Weights are in grams, images in numbered
+//! "Imagem N" columns.
+
+use std::collections::HashMap;
+
+use super::{add_images, dimension, first_category, grams, html_to_text, Fields, Output, ProductFields, Record, Source};
+
+const ID: &[&str] = &["id", "codigo", "id produto", "codigo do produto"];
+pub(super) const PARENT: &[&str] = &["id pai", "codigo pai", "id produto pai", "codigo do produto pai"];
+const REFERENCE: &[&str] = &["referencia"];
+const NAME: &[&str] = &["nome", "nome do produto"];
+const DESCRIPTION: &[&str] = &["descricao", "descricao completa"];
+const PRICE: &[&str] = &["preco de venda", "preco"];
+const PROMOTIONAL_PRICE: &[&str] = &["preco promocional"];
+const STOCK: &[&str] = &["estoque"];
+const EAN: &[&str] = &["ean", "codigo de barras", "gtin"];
+const NCM: &[&str] = &["ncm"];
+const WEIGHT: &[&str] = &["peso", "peso g", "peso gramas"];
+const HEIGHT: &[&str] = &["altura", "altura cm"];
+const WIDTH: &[&str] = &["largura", "largura cm"];
+const LENGTH: &[&str] = &["comprimento", "comprimento cm"];
+const CATEGORY: &[&str] = &["categoria", "categorias"];
+const TAGS: &[&str] = &["tags", "palavras chave"];
+const VARIATION_SLOTS: usize = 3;
+const IMAGE_SLOTS: usize = 10;
+
+pub(super) fn translate(source: &Source, rows: &[Record], output: &mut Output) {
+    let mut variations: HashMap<&str, Vec<&Record>> = HashMap::new();
+    for row in rows {
+        let parent = source.get(row, PARENT);
+        if !parent.is_empty() && parent != source.get(row, ID) {
+            variations.entry(parent).or_default().push(row);
+        }
+    }
+    let ids: Vec<&str> = rows.iter().map(|row| source.get(row, ID)).collect();
+
+    for row in rows {
+        let parent = source.get(row, PARENT);
+        let is_variation = !parent.is_empty() && parent != source.get(row, ID);
+        // Variations of products that are in the file are written with them
+        if is_variation && ids.contains(&parent) {
+            continue;
+        }
+
+        let code = code(source, row, parent);
+        let mut product = product_fields(source, row);
+        let children = variations.get(source.get(row, ID)).filter(|_| !is_variation);
+
+        let Some(children) = children else {
+            let mut fields = variant_fields(source, row, None);
+            if is_variation {
+                fields.set("codigo_pai", parent);
+            }
+            fields.set("codigo_interno", code.as_str());
+            set_variations(source, row, &mut fields);
+            product.apply(&mut fields);
+            output.push(row.line, fields);
+            continue;
+        };
+
+        for child in children {
+            add_images(&mut product.imagens, images(source, child));
+        }
+        for child in children {
+            let mut fields = variant_fields(source, child, Some(row));
+            fields.set("codigo_pai", code.as_str());
+            fields.set("codigo_interno", self::code(source, child, &code));
+            set_variations(source, child, &mut fields);
+            product.apply(&mut fields);
+            output.push(child.line, fields);
+        }
+    }
+}
+
+/// The reference when there is one, so codes match the image folders the
+/// store already uses; otherwise the Tray id, prefixed by the parent code
+/// for variations.
+fn code(source: &Source, row: &Record, parent: &str) -> String {
+    let reference = source.get(row, REFERENCE);
+    let id = source.get(row, ID);
+    match (reference.is_empty(), parent.is_empty() || parent == id) {
+        (false, _) => reference.to_string(),
+        (true, true) => id.to_string(),
+        (true, false) => format!("{}-{}", parent, id),
+    }
+}
+
+fn images<'a>(source: &Source, row: &'a Record) -> Vec<&'a str> {
+    (1..=IMAGE_SLOTS)
+        .map(|n| {
+            source.get(row, &[
+                &format!("imagem {}", n),
+                &format!("url imagem {}", n),
+                &format!("url da imagem {}", n),
+            ])
+        })
+        .filter(|url| !url.is_empty())
+        .collect()
+}
+
+fn product_fields(source: &Source, row: &Record) -> ProductFields {
+    let mut product = ProductFields {
+        nome: source.get(row, NAME).to_string(),
+        descricao: html_to_text(source.get(row, DESCRIPTION)),
+        categoria: first_category(source.get(row, CATEGORY)),
+        tags: source.get(row, TAGS).to_string(),
+        imagens: Vec::new(),
+    };
+    add_images(&mut product.imagens, images(source, row));
+    product
+}
+
+/// Price, stock and shipping data. Variations fall back to their product's
+/// price, weight and dimensions.
+fn variant_fields(source: &Source, row: &Record, parent: Option<&Record>) -> Fields {
+    let get = |names: &[&str]| {
+        let value = source.get(row, names);
+        match parent {
+            Some(parent) if value.is_empty() => source.get(parent, names),
+            _ => value,
+        }
+    };
+
+    let mut fields = Fields::default();
+    let price = get(PRICE);
+    fields.set("preco", if price.is_empty() { get(PROMOTIONAL_PRICE) } else { price });
+    fields.set("sku", source.get(row, REFERENCE));
+    fields.set("estoque", source.get(row, STOCK));
+    fields.set("ean", source.get(row, EAN));
+    fields.set("ncm", get(NCM));
+    fields.set("peso_g", grams(get(WEIGHT)));
+    fields.set("altura_cm", dimension(get(HEIGHT)));
+    fields.set("largura_cm", dimension(get(WIDTH)));
+    fields.set("comprimento_cm", dimension(get(LENGTH)));
+    fields
+}
+
+fn set_variations(source: &Source, row: &Record, fields: &mut Fields) {
+    for n in 1..=VARIATION_SLOTS {
+        let name = source.get(row, &[
+            &format!("variacao {}", n),
+            &format!("tipo variacao {}", n),
+            &format!("tipo da variacao {}", n),
+        ]);
+        let value = source.get(row, &[&format!("valor variacao {}", n), &format!("valor da variacao {}", n)]);
+        if !name.is_empty() {
+            fields.option(name, value);
+        }
+    }
+}
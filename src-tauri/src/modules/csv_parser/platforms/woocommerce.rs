@@ -0,0 +1,169 @@
+//! WooCommerce product CSV, as written by its built-in exporter in English
+//! or Portuguese. Simple products are one row each. A variable product is a
+//! row with the product fields followed by `variation` rows pointing at it in
+//! `Parent`, either as "id:<ID>" or by SKU; only the variations become
+//! variants.
+
+use std::collections::HashMap;
+
+use super::{
+    add_images, dimension, first_category, grams, html_to_text, kg_to_grams, split_list, Fields, Output,
+    ProductFields, Record, Source,
+};
+
+const ID: &[&str] = &["id"];
+const TYPE: &[&str] = &["type", "tipo"];
+const SKU: &[&str] = &["sku"];
+const NAME: &[&str] = &["name", "nome"];
+const DESCRIPTION: &[&str] = &["description", "descricao"];
+const SHORT_DESCRIPTION: &[&str] = &["short description", "descricao curta"];
+const REGULAR_PRICE: &[&str] = &["regular price", "preco"];
+const SALE_PRICE: &[&str] = &["sale price", "preco promocional"];
+const STOCK: &[&str] = &["stock", "estoque"];
+const GTIN: &[&str] = &["gtin upc ean or isbn", "gtin upc ean ou isbn"];
+const WEIGHT_KG: &[&str] = &["weight kg", "peso kg"];
+const WEIGHT_G: &[&str] = &["weight g", "peso g"];
+const LENGTH: &[&str] = &["length cm", "comprimento cm"];
+const WIDTH: &[&str] = &["width cm", "largura cm"];
+const HEIGHT: &[&str] = &["height cm", "altura cm"];
+const CATEGORIES: &[&str] = &["categories", "categorias"];
+const TAGS: &[&str] = &["tags"];
+const IMAGES: &[&str] = &["images", "imagens"];
+const PARENT: &[&str] = &["parent", "ascendente"];
+/// Attribute columns are numbered from 1, the exporter adds as many as the
+/// store uses.
+const MAX_ATTRIBUTES: usize = 20;
+
+pub(super) fn translate(source: &Source, rows: &[Record], output: &mut Output) {
+    let kind = |row: &Record| source.get(row, TYPE).to_lowercase();
+
+    let mut parent_index: HashMap<String, usize> = HashMap::new();
+    for (i, row) in rows.iter().enumerate().filter(|(_, row)| kind(row).contains("variable")) {
+        parent_index.insert(format!("id:{}", source.get(row, ID)), i);
+        let sku = source.get(row, SKU);
+        if !sku.is_empty() {
+            parent_index.insert(sku.to_string(), i);
+        }
+    }
+
+    let mut variations: HashMap<usize, Vec<&Record>> = HashMap::new();
+    let mut orphans: Vec<&Record> = Vec::new();
+    for row in rows.iter().filter(|row| kind(row).contains("variation")) {
+        match parent_index.get(source.get(row, PARENT)) {
+            Some(&parent) => variations.entry(parent).or_default().push(row),
+            None => orphans.push(row),
+        }
+    }
+
+    for (i, row) in rows.iter().enumerate() {
+        let kind = kind(row);
+        if kind.contains("variation") || kind.contains("grouped") {
+            continue;
+        }
+
+        let code = product_code(source, row);
+        let product = product_fields(source, row);
+        let Some(children) = variations.get(&i) else {
+            let mut fields = variant_fields(source, row, None);
+            fields.set("codigo_interno", code.as_str());
+            product.apply(&mut fields);
+            output.push(row.line, fields);
+            continue;
+        };
+
+        let mut product = product;
+        for child in children {
+            add_images(&mut product.imagens, split_list(source.get(child, IMAGES)).iter().map(String::as_str));
+        }
+        for child in children {
+            let mut fields = variant_fields(source, child, Some(row));
+            fields.set("codigo_pai", code.as_str());
+            fields.set("codigo_interno", variation_code(source, child, &code));
+            set_attributes(source, child, &mut fields);
+            product.apply(&mut fields);
+            output.push(child.line, fields);
+        }
+    }
+
+    // Variations whose parent is not in the file still import, grouped by
+    // the parent reference.
+    for row in orphans {
+        let parent = source.get(row, PARENT);
+        let mut fields = variant_fields(source, row, None);
+        fields.set("codigo_pai", parent);
+        fields.set("codigo_interno", variation_code(source, row, parent));
+        set_attributes(source, row, &mut fields);
+        product_fields(source, row).apply(&mut fields);
+        output.push(row.line, fields);
+    }
+}
+
+fn product_code(source: &Source, row: &Record) -> String {
+    let sku = source.get(row, SKU);
+    if sku.is_empty() { source.get(row, ID) } else { sku }.to_string()
+}
+
+fn variation_code(source: &Source, row: &Record, parent: &str) -> String {
+    let sku = source.get(row, SKU);
+    if sku.is_empty() {
+        format!("{}-{}", parent, source.get(row, ID))
+    } else {
+        sku.to_string()
+    }
+}
+
+fn product_fields(source: &Source, row: &Record) -> ProductFields {
+    let description = [source.get(row, DESCRIPTION), source.get(row, SHORT_DESCRIPTION)]
+        .into_iter()
+        .find(|d| !d.is_empty())
+        .unwrap_or("");
+    let mut product = ProductFields {
+        nome: source.get(row, NAME).to_string(),
+        descricao: html_to_text(description),
+        categoria: first_category(source.get(row, CATEGORIES)),
+        tags: source.get(row, TAGS).to_string(),
+        imagens: Vec::new(),
+    };
+    add_images(&mut product.imagens, split_list(source.get(row, IMAGES)).iter().map(String::as_str));
+    product
+}
+
+/// Price, stock and shipping data of a row. Variations fall back to the
+/// parent's price, weight and dimensions when they leave them empty.
+fn variant_fields(source: &Source, row: &Record, parent: Option<&Record>) -> Fields {
+    let get = |names: &[&str]| {
+        let value = source.get(row, names);
+        match parent {
+            Some(parent) if value.is_empty() => source.get(parent, names),
+            _ => value,
+        }
+    };
+
+    let mut fields = Fields::default();
+    fields.set("sku", source.get(row, SKU));
+    let regular = get(REGULAR_PRICE);
+    fields.set("preco", if regular.is_empty() { get(SALE_PRICE) } else { regular });
+    fields.set("estoque", source.get(row, STOCK));
+    fields.set("ean", source.get(row, GTIN));
+    if source.has_any(WEIGHT_KG) {
+        fields.set("peso_g", kg_to_grams(get(WEIGHT_KG)));
+    } else {
+        fields.set("peso_g", grams(get(WEIGHT_G)));
+    }
+    fields.set("comprimento_cm", dimension(get(LENGTH)));
+    fields.set("largura_cm", dimension(get(WIDTH)));
+    fields.set("altura_cm", dimension(get(HEIGHT)));
+    fields
+}
+
+/// A variation's attributes are its options. On other rows attributes only
+/// describe the product and are not imported.
+fn set_attributes(source: &Source, row: &Record, fields: &mut Fields) {
+    for n in 1..=MAX_ATTRIBUTES {
+        let name = source.get(row, &[&format!("attribute {} name", n), &format!("nome do atributo {}", n)]);
+        let value = source.get(row, &[&format!("attribute {} value s", n), &format!("valor es do atributo {}", n)]);
+        if !name.is_empty() {
+            fields.option(name, value);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use anyhow::{Context, Result};
+use calamine::{open_workbook_auto, Data, Reader, Sheets};
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use super::mapping::{self, ColumnMapping, HeaderPreview};
+use super::money::{Currency, Money};
+use super::{parse_rows, ImportOptions, ParseReport, Table};
+use crate::modules::text::slugify;
+
+pub const EXTENSIONS: [&str; 5] = ["xlsx", "xlsm", "xlsb", "xls", "ods"];
+/// Header words of price columns, after `slugify`
+const PRICE_WORDS: [&str; 4] = ["preco", "price", "valor", "pvp"];
+
+/// Reads products from an Excel or OpenDocument worksheet. Without `sheet`
+/// the first worksheet with the required columns is used. The first
+/// non-empty row holds the headers; diagnostics use the sheet's row numbers.
+pub fn parse_spreadsheet(path: &Path, options: &ImportOptions) -> Result<ParseReport> {
+    let mut workbook = open_workbook_auto(path)
+        .with_context(|| format!("Failed to open spreadsheet {}", path.display()))?;
+    let sheets = workbook.sheet_names();
+
+    let mut report = match options.sheet.as_deref() {
+        Some(name) if sheets.iter().any(|s| s == name) => parse_sheet(&mut workbook, name, options)?,
+        Some(name) => anyhow::bail!("Worksheet '{}' not found, the file has: {}", name, sheets.join(", ")),
+        None => {
+            let mut first_error = None;
+            let mut found = None;
+            for name in &sheets {
+                match parse_sheet(&mut workbook, name, options) {
+                    Ok(report) => {
+                        found = Some(report);
+                        break;
+                    }
+                    Err(e) => {
+                        first_error.get_or_insert(e);
+                    }
+                }
+            }
+            match (found, first_error) {
+                (Some(report), _) => report,
+                (None, Some(e)) => return Err(e),
+                (None, None) => anyhow::bail!("The spreadsheet has no worksheets"),
+            }
+        }
+    };
+
+    report.format = format_of(path);
+    report.sheets = sheets;
+    Ok(report)
+}
+
+/// Headers and sample rows of `sheet`, or of the first worksheet with data.
+pub fn preview_spreadsheet(path: &Path, sheet: Option<&str>) -> Result<HeaderPreview> {
+    let mut workbook = open_workbook_auto(path)
+        .with_context(|| format!("Failed to open spreadsheet {}", path.display()))?;
+    let sheets = workbook.sheet_names();
+
+    let (name, (headers, rows)) = match sheet {
+        Some(name) if sheets.iter().any(|s| s == name) => (name.to_string(), read_sheet(&mut workbook, name, None)?),
+        Some(name) => anyhow::bail!("Worksheet '{}' not found, the file has: {}", name, sheets.join(", ")),
+        None => sheets
+            .iter()
+            .find_map(|name| read_sheet(&mut workbook, name, None).ok().map(|table| (name.clone(), table)))
+            .context("The spreadsheet has no worksheet with data")?,
+    };
+
+    let samples = rows
+        .into_iter()
+        .filter(|(_, cells)| cells.iter().any(|c| !c.trim().is_empty()))
+        .take(mapping::SAMPLE_ROWS)
+        .map(|(_, cells)| cells)
+        .collect();
+
+    let mut preview = HeaderPreview::new(headers, samples);
+    preview.format = format_of(path);
+    preview.sheet = Some(name);
+    preview.sheets = sheets;
+    Ok(preview)
+}
+
+fn format_of(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+fn parse_sheet<R: Read + Seek>(
+    workbook: &mut Sheets<R>,
+    name: &str,
+    options: &ImportOptions,
+) -> Result<ParseReport> {
+    let (headers, rows) = read_sheet(workbook, name, options.mapping.as_ref())?;
+    let mut report = parse_rows(&headers, options, rows.into_iter().map(Ok))
+        .with_context(|| format!("Worksheet '{}'", name))?;
+    report.sheet = Some(name.to_string());
+    Ok(report)
+}
+
+/// The header row (first non-empty row) and the rows below it, numbered as
+/// in the sheet. Numbers in the price columns, the one `mapping` takes the
+/// price from or any whose header names a price, are read as money.
+fn read_sheet<R: Read + Seek>(workbook: &mut Sheets<R>, name: &str, mapping: Option<&ColumnMapping>) -> Result<Table> {
+    let range = workbook
+        .worksheet_range(name)
+        .with_context(|| format!("Failed to read worksheet '{}'", name))?;
+    let first_row = range.start().map(|(row, _)| row as u64).unwrap_or(0);
+
+    let mut rows = range
+        .rows()
+        .enumerate()
+        .map(|(index, cells)| (first_row + index as u64 + 1, cells))
+        .skip_while(|(_, cells)| cells.iter().all(|c| cell_text(c).trim().is_empty()));
+
+    let (_, header_cells) = rows
+        .next()
+        .with_context(|| format!("Worksheet '{}' is empty", name))?;
+    let headers: Vec<String> = header_cells.iter().map(cell_text).collect();
+    let prices = price_columns(&headers, mapping);
+
+    let rows = rows
+        .map(|(line, cells)| {
+            let cells = cells
+                .iter()
+                .enumerate()
+                .map(|(index, cell)| if prices.contains(&index) { price_text(cell) } else { cell_text(cell) })
+                .collect();
+            (line, cells)
+        })
+        .collect();
+    Ok((headers, rows))
+}
+
+/// Columns holding prices: the one mapped to `preco` and those whose header
+/// has a price word, such as "Preço de venda" or "Variant Price".
+fn price_columns(headers: &[String], mapping: Option<&ColumnMapping>) -> Vec<usize> {
+    let mapped = mapping.and_then(|mapping| mapping.0.get("preco")).map(|header| header.trim());
+    headers
+        .iter()
+        .enumerate()
+        .filter(|(_, header)| {
+            mapped == Some(header.trim())
+                || slugify(header).split('-').any(|word| PRICE_WORDS.contains(&word))
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// A number in a price column as exact money. Formulas leave values such as
+/// 21.989, which are rounded to the centavo as the sheet displays them; the
+/// text is always `21.99`, never read back as a thousands group.
+fn price_text(cell: &Data) -> String {
+    let amount = match cell {
+        Data::Float(f) => *f,
+        Data::Int(i) => *i as f64,
+        other => return cell_text(other),
+    };
+    // Only the amount is written, the currency is set by the import
+    Money::from_major_f64(amount, Currency::BRL)
+        .map(Money::to_decimal_string)
+        .unwrap_or_else(|_| cell_text(cell))
+}
+
+/// Cell text as the CSV reader would see it. Whole numbers lose the ".0"
+/// so codes and barcodes read back as typed, and float noise such as
+/// 29.900000000000002 is rounded away.
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::Float(f) if f.fract() == 0.0 && f.abs() < 1e15 => format!("{}", *f as i64),
+        Data::Float(f) => format!("{}", (f * 1e6).round() / 1e6),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::csv_parser::money::parse_centavos;
+
+    #[test]
+    fn computed_prices_are_rounded_to_centavos() {
+        assert_eq!(price_text(&Data::Float(21.989)), "21.99");
+        assert_eq!(parse_centavos(&price_text(&Data::Float(21.989))), Ok(2199));
+        assert_eq!(parse_centavos(&price_text(&Data::Float(1.299))), Ok(130));
+        assert_eq!(parse_centavos(&price_text(&Data::Float(1299.0))), Ok(129900));
+        assert_eq!(parse_centavos(&price_text(&Data::Int(49))), Ok(4900));
+        assert_eq!(price_text(&Data::String("1.299,90".to_string())), "1.299,90");
+    }
+
+    #[test]
+    fn finds_price_columns_by_header_and_mapping() {
+        let headers: Vec<String> = ["Código", "Preço de Venda (R$)", "Variant Price", "Peso", "Custo"]
+            .iter()
+            .map(|h| h.to_string())
+            .collect();
+        assert_eq!(price_columns(&headers, None), vec![1, 2]);
+
+        let mapping = ColumnMapping([("preco".to_string(), "Custo".to_string())].into_iter().collect());
+        assert_eq!(price_columns(&headers, Some(&mapping)), vec![1, 2, 4]);
+    }
+}
@@ -0,0 +1,212 @@
+//! Reads product CSVs straight from disk in chunks, so catalogs with
+//! hundreds of thousands of rows are never loaded into one string.
+
+use anyhow::{Context, Result};
+use encoding_rs::WINDOWS_1252;
+use serde::Serialize;
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::rc::Rc;
+
+use super::encoding::{self, DecodeReader, Sniffed};
+use super::mapping::{self, HeaderPreview};
+use super::{parse_rows, ImportOptions, ParseReport};
+
+/// Bytes read to detect the encoding and delimiter
+const SNIFF_BYTES: u64 = 64 * 1024;
+/// Rows between two progress reports
+const PROGRESS_ROWS: usize = 2000;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ImportProgress {
+    #[serde(rename = "bytesRead")]
+    pub bytes_read: u64,
+    #[serde(rename = "totalBytes")]
+    pub total_bytes: u64,
+    /// Data rows read so far
+    pub rows: usize,
+}
+
+/// Parses the CSV at `path`, reporting progress every few thousand rows.
+/// The encoding and delimiter are detected from the first 64 KiB; every row
+/// is validated. A file taken for UTF-8 that turns out not to be is parsed
+/// again from the start as Windows-1252.
+pub fn parse_csv_file(
+    path: &Path,
+    options: &ImportOptions,
+    mut on_progress: impl FnMut(ImportProgress),
+) -> Result<ParseReport> {
+    let (source, sniffed, delimiter, total_bytes) = open(path)?;
+    match parse_stream(source, sniffed, delimiter, options, total_bytes, &mut on_progress) {
+        Err(e) if encoding::is_not_utf8(&e) => {
+            let (file, sniffed) = reopen_as_windows_1252(path)?;
+            parse_stream(file, sniffed, delimiter, options, total_bytes, &mut on_progress)
+        }
+        result => result,
+    }
+}
+
+/// Headers and the first rows of the CSV at `path`, reading no further than
+/// the samples.
+pub fn preview_csv_file(path: &Path) -> Result<HeaderPreview> {
+    let (source, sniffed, delimiter, _) = open(path)?;
+    let ((headers, samples), sniffed) = match read_samples(source, sniffed, delimiter) {
+        Err(e) if encoding::is_not_utf8(&e) => {
+            let (file, sniffed) = reopen_as_windows_1252(path)?;
+            (read_samples(file, sniffed, delimiter)?, sniffed)
+        }
+        result => (result?, sniffed),
+    };
+
+    let mut preview = HeaderPreview::new(headers, samples);
+    preview.format = "csv".to_string();
+    preview.delimiter = Some(delimiter as char);
+    preview.encoding = Some(sniffed.encoding.name().to_string());
+    Ok(preview)
+}
+
+/// The file at `path` with the encoding and delimiter detected from its
+/// first 64 KiB, and its size.
+fn open(path: &Path) -> Result<(impl Read, Sniffed, u8, u64)> {
+    let mut file = File::open(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let total_bytes = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+    let mut head = Vec::new();
+    (&mut file).take(SNIFF_BYTES).read_to_end(&mut head)?;
+    let sniffed = encoding::sniff(&head);
+    let (head_text, _, _) = sniffed.encoding.decode(&head);
+    let delimiter = encoding::detect_delimiter(&head_text);
+    Ok((Cursor::new(head).chain(file), sniffed, delimiter, total_bytes))
+}
+
+fn reopen_as_windows_1252(path: &Path) -> Result<(File, Sniffed)> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok((file, Sniffed { encoding: WINDOWS_1252, guessed: false }))
+}
+
+fn read_samples(source: impl Read, sniffed: Sniffed, delimiter: u8) -> Result<(Vec<String>, Vec<Vec<String>>)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(DecodeReader::sniffed(source, sniffed));
+    let headers: Vec<String> = reader.headers()?.iter().map(str::to_string).collect();
+
+    let mut samples = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        if record.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+        samples.push(record.iter().map(str::to_string).collect());
+        if samples.len() == mapping::SAMPLE_ROWS {
+            break;
+        }
+    }
+    Ok((headers, samples))
+}
+
+fn parse_stream(
+    source: impl Read,
+    sniffed: Sniffed,
+    delimiter: u8,
+    options: &ImportOptions,
+    total_bytes: u64,
+    on_progress: &mut impl FnMut(ImportProgress),
+) -> Result<ParseReport> {
+    let bytes_read = Rc::new(Cell::new(0));
+    let source = Counting { inner: source, count: bytes_read.clone() };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(DecodeReader::sniffed(source, sniffed));
+    let headers: Vec<String> = reader.headers()?.iter().map(str::to_string).collect();
+
+    let mut rows = 0;
+    let records = reader.records().map(|result| {
+        let record = result?;
+        rows += 1;
+        if rows % PROGRESS_ROWS == 0 {
+            on_progress(ImportProgress { bytes_read: bytes_read.get(), total_bytes, rows });
+        }
+        let line = record.position().map(|p| p.line()).unwrap_or(0);
+        Ok((line, record.iter().map(str::to_string).collect()))
+    });
+
+    let mut report = parse_rows(&headers, options, records)?;
+    on_progress(ImportProgress { bytes_read: total_bytes, total_bytes, rows: report.total_rows });
+    report.format = "csv".to_string();
+    report.delimiter = Some(delimiter as char);
+    report.encoding = Some(sniffed.encoding.name().to_string());
+    Ok(report)
+}
+
+/// Counts the bytes taken from the file, for progress.
+struct Counting<R> {
+    inner: R,
+    count: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.set(self.count.get() + read as u64);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A CSV whose first 64 KiB are plain ASCII, followed by `last_name`.
+    fn parse_with_late_name(last_name: &[u8]) -> ParseReport {
+        let mut bytes = b"codigo_interno;nome;preco\n".to_vec();
+        for i in 0..4000 {
+            bytes.extend_from_slice(format!("P{};Produto {};10,00\n", i, i).as_bytes());
+        }
+        assert!(bytes.len() as u64 > SNIFF_BYTES);
+        bytes.extend_from_slice(b"X1;");
+        bytes.extend_from_slice(last_name);
+        bytes.extend_from_slice(b";10,00\n");
+
+        let path = std::env::temp_dir().join(format!("stream-{}.csv", uuid::Uuid::new_v4()));
+        std::fs::write(&path, bytes).unwrap();
+        let report = parse_csv_file(&path, &ImportOptions::default(), |_| {});
+        std::fs::remove_file(&path).unwrap();
+        report.unwrap()
+    }
+
+    fn last_name(report: &ParseReport) -> &str {
+        &report.products.iter().find(|p| p.codigo_interno == "X1").unwrap().nome
+    }
+
+    #[test]
+    fn windows_1252_after_an_ascii_head_is_read_again() {
+        let report = parse_with_late_name(b"Cal\xe7a Jeans");
+        assert_eq!(report.encoding.as_deref(), Some("windows-1252"));
+        assert_eq!(last_name(&report), "Calça Jeans");
+        assert_eq!(report.products.len(), 4001);
+    }
+
+    #[test]
+    fn utf8_after_an_ascii_head_stays_utf8() {
+        let report = parse_with_late_name("Calça Jeans".as_bytes());
+        assert_eq!(report.encoding.as_deref(), Some("UTF-8"));
+        assert_eq!(last_name(&report), "Calça Jeans");
+    }
+
+    #[test]
+    fn previews_the_first_rows() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples/importacao/nuvemshop.csv");
+        let preview = preview_csv_file(&path).unwrap();
+        assert_eq!(preview.encoding.as_deref(), Some("windows-1252"));
+        assert_eq!(preview.delimiter, Some(';'));
+        assert!(preview.headers.iter().any(|h| h == "Preço"));
+        assert_eq!(preview.samples.len(), 4);
+        assert_eq!(preview.samples[0][1], "Vestido Floral");
+    }
+}
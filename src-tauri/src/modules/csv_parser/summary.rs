@@ -0,0 +1,102 @@
+//! What the wizard keeps of an import: counts, the first diagnostics and
+//! pages of products. The full catalog stays in the backend.
+
+use serde::Serialize;
+
+use super::{CategoryNode, Conflict, ParseReport, Platform, Product, RowDiagnostic};
+
+/// Diagnostics and conflicts sent with the summary; the rest are only counted
+pub const MAX_DIAGNOSTICS: usize = 200;
+pub const PAGE_SIZE: usize = 20;
+
+#[derive(Debug, Serialize)]
+pub struct ImportSummary {
+    pub products: usize,
+    pub variants: usize,
+    #[serde(rename = "totalRows")]
+    pub total_rows: usize,
+    #[serde(rename = "rejectedRows")]
+    pub rejected_rows: usize,
+    #[serde(rename = "generatedUuids")]
+    pub generated_uuids: usize,
+    /// Image URLs in the `imagens` column, to be downloaded
+    #[serde(rename = "imageUrls")]
+    pub image_urls: usize,
+    /// The first `MAX_DIAGNOSTICS`, by line
+    pub diagnostics: Vec<RowDiagnostic>,
+    #[serde(rename = "diagnosticCount")]
+    pub diagnostic_count: usize,
+    /// The first `MAX_DIAGNOSTICS`, by line
+    pub conflicts: Vec<Conflict>,
+    #[serde(rename = "conflictCount")]
+    pub conflict_count: usize,
+    pub format: String,
+    pub delimiter: Option<char>,
+    pub encoding: Option<String>,
+    pub sheet: Option<String>,
+    pub sheets: Vec<String>,
+    pub platform: Option<Platform>,
+    pub categories: Vec<CategoryNode>,
+    pub collections: Vec<String>,
+    pub tags: Vec<String>,
+    /// First page of products
+    pub preview: ProductPage,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProductPage {
+    pub products: Vec<Product>,
+    pub page: usize,
+    #[serde(rename = "pageSize")]
+    pub page_size: usize,
+    pub total: usize,
+}
+
+impl ImportSummary {
+    /// Summarizes `report`, taking its products out so the caller can keep
+    /// them.
+    pub fn take(report: &mut ParseReport) -> (Self, Vec<Product>) {
+        let products = std::mem::take(&mut report.products);
+        let diagnostic_count = report.diagnostics.len();
+        report.diagnostics.truncate(MAX_DIAGNOSTICS);
+        let conflict_count = report.conflicts.len();
+        report.conflicts.truncate(MAX_DIAGNOSTICS);
+
+        let summary = Self {
+            products: products.len(),
+            variants: products.iter().map(|p| p.variants.len()).sum(),
+            total_rows: report.total_rows,
+            rejected_rows: report.rejected_rows,
+            generated_uuids: report.generated_uuids,
+            image_urls: products.iter().map(|p| p.imagens.len()).sum(),
+            diagnostics: std::mem::take(&mut report.diagnostics),
+            diagnostic_count,
+            conflicts: std::mem::take(&mut report.conflicts),
+            conflict_count,
+            format: std::mem::take(&mut report.format),
+            delimiter: report.delimiter,
+            encoding: report.encoding.take(),
+            sheet: report.sheet.take(),
+            sheets: std::mem::take(&mut report.sheets),
+            platform: report.platform,
+            categories: std::mem::take(&mut report.categories),
+            collections: std::mem::take(&mut report.collections),
+            tags: std::mem::take(&mut report.tags),
+            preview: page(&products, 0, PAGE_SIZE),
+        };
+        (summary, products)
+    }
+}
+
+/// Products of the zero-based `page`. An empty page past the end.
+pub fn page(products: &[Product], page: usize, page_size: usize) -> ProductPage {
+    let page_size = page_size.max(1);
+    let start = page.saturating_mul(page_size).min(products.len());
+    let end = (start + page_size).min(products.len());
+    ProductPage {
+        products: products[start..end].to_vec(),
+        page,
+        page_size,
+        total: products.len(),
+    }
+}
@@ -0,0 +1,99 @@
+use serde::Serialize;
+
+use super::Product;
+use crate::modules::text::slugify;
+
+const CATEGORY_SEPARATOR: char = '>';
+const MAX_CATEGORY_DEPTH: usize = 5;
+
+/// A category with its subcategories, e.g. "Roupas" containing "Camisetas".
+/// The handle is built from the full path so "Roupas > Acessórios" and
+/// "Calçados > Acessórios" stay apart.
+#[derive(Debug, Clone, Serialize)]
+pub struct CategoryNode {
+    pub name: String,
+    pub handle: String,
+    pub children: Vec<CategoryNode>,
+}
+
+/// Splits "Roupas > Camisetas" into its levels.
+pub fn parse_category_path(raw: &str) -> Result<Vec<String>, String> {
+    let path: Vec<String> = raw
+        .split(CATEGORY_SEPARATOR)
+        .map(|level| level.trim().to_string())
+        .collect();
+
+    if path.iter().any(|level| level.is_empty()) {
+        return Err("categoria has an empty level, use 'Parent > Child'".to_string());
+    }
+    if path.len() > MAX_CATEGORY_DEPTH {
+        return Err(format!("categoria has {} levels, the limit is {}", path.len(), MAX_CATEGORY_DEPTH));
+    }
+    if slugify(&path.join(" ")).is_empty() {
+        return Err("categoria needs at least one letter or digit".to_string());
+    }
+    Ok(path)
+}
+
+pub fn category_handle(path: &[String]) -> String {
+    slugify(&path.join(" "))
+}
+
+/// Tags are separated by commas, semicolons or pipes. Repeated tags are
+/// dropped ignoring case.
+pub fn parse_tags(raw: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in raw.split([',', ';', '|']).map(str::trim).filter(|t| !t.is_empty()) {
+        if !tags.iter().any(|t| t.to_lowercase() == tag.to_lowercase()) {
+            tags.push(tag.to_string());
+        }
+    }
+    tags
+}
+
+/// Builds the category tree used by `products`, keeping the order in which
+/// categories first appear.
+pub fn category_tree(products: &[Product]) -> Vec<CategoryNode> {
+    let mut roots: Vec<CategoryNode> = Vec::new();
+    for product in products {
+        let mut level = &mut roots;
+        for depth in 0..product.categoria.len() {
+            let handle = category_handle(&product.categoria[..=depth]);
+            let index = match level.iter().position(|node| node.handle == handle) {
+                Some(index) => index,
+                None => {
+                    level.push(CategoryNode {
+                        name: product.categoria[depth].clone(),
+                        handle,
+                        children: Vec::new(),
+                    });
+                    level.len() - 1
+                }
+            };
+            level = &mut level[index].children;
+        }
+    }
+    roots
+}
+
+/// Distinct collection names, in order of appearance.
+pub fn collections(products: &[Product]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in products.iter().filter_map(|p| p.colecao.as_ref()) {
+        if !names.iter().any(|n| slugify(n) == slugify(name)) {
+            names.push(name.clone());
+        }
+    }
+    names
+}
+
+/// Distinct tags, in order of appearance.
+pub fn tags(products: &[Product]) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in products.iter().flat_map(|p| &p.tags) {
+        if !tags.iter().any(|t| t.to_lowercase() == tag.to_lowercase()) {
+            tags.push(tag.clone());
+        }
+    }
+    tags
+}
@@ -0,0 +1,196 @@
+//! Writes the UUIDs generated during an import back into a copy of the CSV,
+//! so importing that copy again gives the products the same identifiers and
+//! a redeploy updates them instead of creating duplicates.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::{encoding, is_spreadsheet, Columns, ImportOptions, Platform, Product};
+
+const UUID_HEADER: &str = "uuid";
+
+#[derive(Debug, Serialize)]
+pub struct UuidWriteBack {
+    /// The new file, next to the original
+    pub path: String,
+    /// Rows that received a UUID
+    pub rows: usize,
+    /// Header of the uuid column; added at the end when the file had none
+    #[serde(rename = "uuidHeader")]
+    pub uuid_header: String,
+}
+
+/// Copies the CSV at `path` to "<name>-uuid.<ext>", filling empty uuid cells
+/// with the UUID of the product each row belongs to. Delimiter, encoding,
+/// byte order mark and line endings are kept.
+pub fn write_back_uuids(path: &Path, options: &ImportOptions, products: &[Product]) -> Result<UuidWriteBack> {
+    if is_spreadsheet(path) {
+        anyhow::bail!("UUIDs can only be written back to CSV files");
+    }
+
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let (content, source_encoding) = encoding::decode(&bytes);
+    let delimiter = encoding::detect_delimiter(&content);
+
+    let mut records = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .has_headers(false)
+        .from_reader(content.as_bytes())
+        .records()
+        .collect::<std::result::Result<Vec<_>, _>>()?
+        .into_iter()
+        .map(|record| record.iter().map(str::to_string).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let Some(headers) = records.first().cloned() else {
+        anyhow::bail!("The file is empty");
+    };
+
+    let field_headers = match &options.mapping {
+        Some(mapping) => mapping.apply(&headers),
+        None if Platform::detect(&headers).is_some() => {
+            anyhow::bail!("Exports of other platforms have no uuid column to write back to")
+        }
+        None => headers.clone(),
+    };
+    let columns = Columns::from_headers(&field_headers)?;
+    let uuid_column = match columns.uuid {
+        Some(index) => index,
+        None => {
+            records[0].push(UUID_HEADER.to_string());
+            headers.len()
+        }
+    };
+
+    let uuids: HashMap<&str, &str> = products
+        .iter()
+        .filter_map(|p| p.uuid.as_deref().map(|uuid| (p.codigo_interno.as_str(), uuid)))
+        .collect();
+
+    let mut filled = 0;
+    for cells in records.iter_mut().skip(1) {
+        let cell = |index: usize| cells.get(index).map(|c| c.trim()).unwrap_or("");
+        let parent = columns
+            .codigo_pai
+            .map(cell)
+            .filter(|c| !c.is_empty())
+            .unwrap_or_else(|| cell(columns.codigo_interno));
+        let Some(uuid) = uuids.get(parent).map(|u| u.to_string()) else {
+            continue;
+        };
+        if !cell(uuid_column).is_empty() {
+            continue;
+        }
+
+        if cells.len() <= uuid_column {
+            cells.resize(uuid_column + 1, String::new());
+        }
+        cells[uuid_column] = uuid;
+        filled += 1;
+    }
+
+    let terminator = if content.contains("\r\n") {
+        csv::Terminator::CRLF
+    } else {
+        csv::Terminator::Any(b'\n')
+    };
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .terminator(terminator)
+        .flexible(true)
+        .from_writer(Vec::new());
+    for cells in &records {
+        writer.write_record(cells)?;
+    }
+    let text = String::from_utf8(writer.into_inner().context("Failed to write CSV")?)?;
+
+    let target = sibling_path(path);
+    std::fs::write(&target, encoding::encode(&text, source_encoding, encoding::has_bom(&bytes)))
+        .with_context(|| format!("Failed to write {}", target.display()))?;
+
+    Ok(UuidWriteBack {
+        path: target.to_string_lossy().to_string(),
+        rows: filled,
+        uuid_header: records[0][uuid_column].trim().to_string(),
+    })
+}
+
+/// "produtos.csv" -> "produtos-uuid.csv", in the same folder.
+fn sibling_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("produtos");
+    let name = match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => format!("{}-uuid.{}", stem, extension),
+        None => format!("{}-uuid", stem),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn products() -> Vec<Product> {
+        vec![
+            Product { uuid: Some("u-1".to_string()), ..Product::sample("A1") },
+            Product { uuid: Some("u-2".to_string()), ..Product::sample("B2") },
+        ]
+    }
+
+    /// Writes `bytes` as produtos.csv in a fresh folder and returns the
+    /// bytes of the copy with the UUIDs.
+    fn write_back(bytes: &[u8]) -> (UuidWriteBack, Vec<u8>) {
+        let dir = std::env::temp_dir().join(format!("uuids-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("produtos.csv");
+        std::fs::write(&path, bytes).unwrap();
+        let written = write_back_uuids(&path, &ImportOptions::default(), &products()).unwrap();
+        let copy = std::fs::read(dir.join("produtos-uuid.csv")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        (written, copy)
+    }
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        std::iter::once(0xFEFF).chain(text.encode_utf16()).flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn windows_1252_semicolons_and_crlf_are_kept() {
+        let (written, copy) = write_back(
+            b"codigo_interno;nome;preco;uuid\r\nA1;Cal\xe7a;10,00;\r\nB2;Bon\xe9;5,00;antigo\r\n",
+        );
+        assert_eq!(
+            copy,
+            b"codigo_interno;nome;preco;uuid\r\nA1;Cal\xe7a;10,00;u-1\r\nB2;Bon\xe9;5,00;antigo\r\n".to_vec()
+        );
+        assert_eq!((written.rows, written.uuid_header.as_str()), (1, "uuid"));
+    }
+
+    #[test]
+    fn utf8_byte_order_mark_is_kept() {
+        let (_, copy) = write_back("\u{feff}codigo_interno,nome,preco,uuid\nA1,Calça,\"10,00\",\n".as_bytes());
+        assert_eq!(copy, "\u{feff}codigo_interno,nome,preco,uuid\nA1,Calça,\"10,00\",u-1\n".as_bytes());
+    }
+
+    #[test]
+    fn utf16_is_written_as_utf16() {
+        let (written, copy) = write_back(&utf16le("codigo_interno\tnome\tpreco\tuuid\r\nB2\tBoné\t5,00\t\r\n"));
+        assert_eq!(copy, utf16le("codigo_interno\tnome\tpreco\tuuid\r\nB2\tBoné\t5,00\tu-2\r\n"));
+        assert_eq!(written.rows, 1);
+    }
+
+    #[test]
+    fn uuid_column_is_added_when_missing() {
+        // Rows of unknown products are left as they are
+        let (written, copy) = write_back(
+            b"codigo_interno;nome;preco\nA1;Camiseta;10,00\nA1;Camiseta;10,00\nC3;Meia;2,00\n",
+        );
+        assert_eq!(
+            copy,
+            b"codigo_interno;nome;preco;uuid\nA1;Camiseta;10,00;u-1\nA1;Camiseta;10,00;u-1\nC3;Meia;2,00\n".to_vec()
+        );
+        assert_eq!((written.rows, written.uuid_header.as_str()), (2, "uuid"));
+    }
+}
@@ -0,0 +1,363 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use super::conflicts::{self, Conflict, ConflictResolution};
+use super::{Money, Product, RowDiagnostic};
+use crate::modules::text::slugify;
+
+pub const OPTION_PREFIX: &str = "opcao_";
+
+/// An option such as "Tamanho" with the values used by the product's
+/// variants, in the order they first appear in the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProductOption {
+    pub title: String,
+    pub values: Vec<String>,
+}
+
+/// One sellable variant. Products without option columns get a single
+/// variant with no options.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Variant {
+    pub sku: String,
+    /// Option title to value, e.g. "Tamanho" -> "M"
+    #[serde(default)]
+    pub options: BTreeMap<String, String>,
+    /// Set only when it differs from the product price
+    #[serde(default)]
+    pub preco: Option<Money>,
+    #[serde(default)]
+    pub estoque: Option<i64>,
+    #[serde(default)]
+    pub ean: Option<String>,
+    /// Mercosur tariff code, 8 digits
+    #[serde(default)]
+    pub ncm: Option<String>,
+    #[serde(default)]
+    pub peso_g: Option<u32>,
+    #[serde(default)]
+    pub altura_cm: Option<f64>,
+    #[serde(default)]
+    pub largura_cm: Option<f64>,
+    #[serde(default)]
+    pub comprimento_cm: Option<f64>,
+}
+
+impl Variant {
+    /// Option values joined in the product's option order, e.g. "M / Branco".
+    pub fn title(&self, options: &[ProductOption]) -> String {
+        options
+            .iter()
+            .filter_map(|option| self.options.get(&option.title))
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" / ")
+    }
+}
+
+#[cfg(test)]
+impl Variant {
+    /// A variant without options, price override, stock or shipping data.
+    pub fn sample(sku: &str) -> Self {
+        Variant {
+            sku: sku.to_string(),
+            options: BTreeMap::new(),
+            preco: None,
+            estoque: None,
+            ean: None,
+            ncm: None,
+            peso_g: None,
+            altura_cm: None,
+            largura_cm: None,
+            comprimento_cm: None,
+        }
+    }
+}
+
+/// "opcao_tamanho" -> "Tamanho", "opcao_cor_principal" -> "Cor principal".
+pub fn option_title(column: &str) -> String {
+    let name = column.trim_start_matches(OPTION_PREFIX).replace('_', " ");
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+/// A row that passed cell validation. `parent` is the code rows are grouped
+/// by: `codigo_pai` when present, otherwise the row's own `codigo_interno`.
+#[derive(Clone)]
+pub(super) struct Row {
+    pub line: u64,
+    pub parent: String,
+    pub codigo: String,
+    pub uuid: Option<String>,
+    pub nome: String,
+    pub preco: Option<Money>,
+    pub descricao: String,
+    /// (column, value) for every non-empty option cell
+    pub options: Vec<(String, String)>,
+    pub sku: Option<String>,
+    pub estoque: Option<i64>,
+    pub ean: Option<String>,
+    pub peso_g: Option<u32>,
+    pub altura_cm: Option<f64>,
+    pub largura_cm: Option<f64>,
+    pub comprimento_cm: Option<f64>,
+    pub ncm: Option<String>,
+    pub categoria: Vec<String>,
+    pub colecao: Option<String>,
+    pub tags: Vec<String>,
+    pub imagens: Vec<String>,
+    /// False for the first row of a product whose variant was dropped as a
+    /// duplicate; it still provides the product fields
+    pub variant: bool,
+}
+
+/// Groups rows into products as they are read. The first row of a group
+/// provides the product fields, every row becomes a variant.
+#[derive(Default)]
+pub(super) struct CatalogBuilder {
+    groups: Vec<Vec<Row>>,
+    index: HashMap<String, usize>,
+}
+
+impl CatalogBuilder {
+    pub fn contains(&self, parent: &str) -> bool {
+        self.index.contains_key(parent)
+    }
+
+    pub fn push(&mut self, row: Row) {
+        match self.index.get(&row.parent) {
+            Some(&i) => self.groups[i].push(row),
+            None => {
+                self.index.insert(row.parent.clone(), self.groups.len());
+                self.groups.push(vec![row]);
+            }
+        }
+    }
+
+    /// Builds the products, after resolving duplicates when a resolution is
+    /// given. Variant rows that are missing an option the rest of the group
+    /// uses or that still repeat a combination or SKU are rejected, with one
+    /// diagnostic per rejected row.
+    pub fn finish(mut self, resolution: Option<&ConflictResolution>) -> (Vec<Product>, Vec<RowDiagnostic>, Vec<Conflict>) {
+        let conflicts = conflicts::resolve(&mut self.groups, resolution);
+        let mut products = Vec::new();
+        let mut diagnostics = Vec::new();
+        for rows in self.groups {
+            if let Some(product) = build_product(rows, &mut diagnostics) {
+                products.push(product);
+            }
+        }
+        (products, diagnostics, conflicts)
+    }
+}
+
+fn build_product(rows: Vec<Row>, diagnostics: &mut Vec<RowDiagnostic>) -> Option<Product> {
+    let first = rows.first()?;
+    let mut product = Product {
+        uuid: first.uuid.clone(),
+        codigo_interno: first.parent.clone(),
+        nome: first.nome.clone(),
+        preco: first.preco.unwrap_or_else(|| Money::brl(0)),
+        descricao: first.descricao.clone(),
+        options: Vec::new(),
+        variants: Vec::new(),
+        categoria: first.categoria.clone(),
+        colecao: first.colecao.clone(),
+        tags: first.tags.clone(),
+        imagens: first.imagens.clone(),
+    };
+
+    let mut columns: Vec<&str> = Vec::new();
+    for row in rows.iter().filter(|row| row.variant) {
+        for (column, _) in &row.options {
+            if !columns.contains(&column.as_str()) {
+                columns.push(column);
+            }
+        }
+    }
+    product.options = columns
+        .iter()
+        .map(|column| ProductOption { title: option_title(column), values: Vec::new() })
+        .collect();
+
+    let mut seen_combinations: HashMap<Vec<&str>, u64> = HashMap::new();
+    let mut seen_skus: HashMap<String, u64> = HashMap::new();
+    for row in rows.iter().filter(|row| row.variant) {
+        let mut reject = |column: &str, value: &str, reason: String| {
+            diagnostics.push(RowDiagnostic {
+                line: row.line,
+                column: column.to_string(),
+                value: value.to_string(),
+                reason,
+            });
+        };
+
+        let value_of = |column: &str| {
+            row.options.iter().find(|(c, _)| c == column).map(|(_, v)| v.as_str())
+        };
+        if let Some(missing) = columns.iter().find(|column| value_of(column).is_none()) {
+            reject(
+                missing,
+                "",
+                format!("{} is required, other variants of {} use it", missing, product.codigo_interno),
+            );
+            continue;
+        }
+
+        let values: Vec<&str> = columns.iter().filter_map(|column| value_of(column)).collect();
+        if let Some(line) = seen_combinations.get(&values) {
+            if values.is_empty() {
+                reject(
+                    "codigo_interno",
+                    &row.codigo,
+                    format!(
+                        "duplicate codigo_interno, already used on line {}; add opcao_* columns to import variants",
+                        line
+                    ),
+                );
+            } else {
+                reject(
+                    columns[0],
+                    &values.join(" / "),
+                    format!("variant with the same options already defined on line {}", line),
+                );
+            }
+            continue;
+        }
+
+        let sku = row.sku.clone().unwrap_or_else(|| {
+            if row.codigo != row.parent || values.is_empty() {
+                row.codigo.clone()
+            } else {
+                format!("{}-{}", row.parent, slugify(&values.join(" ")))
+            }
+        });
+        if let Some(line) = seen_skus.get(&sku) {
+            reject("sku", &sku, format!("duplicate sku, already used on line {}", line));
+            continue;
+        }
+
+        seen_combinations.insert(values.clone(), row.line);
+        seen_skus.insert(sku.clone(), row.line);
+
+        let mut options = BTreeMap::new();
+        for (option, value) in product.options.iter_mut().zip(&values) {
+            if !option.values.iter().any(|v| v == value) {
+                option.values.push(value.to_string());
+            }
+            options.insert(option.title.clone(), value.to_string());
+        }
+
+        product.variants.push(Variant {
+            sku,
+            options,
+            preco: row.preco.filter(|preco| *preco != product.preco),
+            estoque: row.estoque,
+            ean: row.ean.clone(),
+            // Variants share the package data of the first row unless they set their own
+            ncm: row.ncm.clone().or_else(|| first.ncm.clone()),
+            peso_g: row.peso_g.or(first.peso_g),
+            altura_cm: row.altura_cm.or(first.altura_cm),
+            largura_cm: row.largura_cm.or(first.largura_cm),
+            comprimento_cm: row.comprimento_cm.or(first.comprimento_cm),
+        });
+    }
+
+    if product.variants.is_empty() {
+        return None;
+    }
+    Some(product)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modules::csv_parser::{parse_rows, ImportOptions, Money, ParseReport};
+
+    fn parse(headers: &str, lines: &[&str]) -> ParseReport {
+        let split = |line: &str| line.split(';').map(str::to_string).collect::<Vec<_>>();
+        let rows = lines.iter().enumerate().map(|(i, line)| Ok((i as u64 + 2, split(line))));
+        parse_rows(&split(headers), &ImportOptions::default(), rows).unwrap()
+    }
+
+    fn skus(report: &ParseReport, codigo: &str) -> Vec<String> {
+        let product = report.products.iter().find(|p| p.codigo_interno == codigo).unwrap();
+        product.variants.iter().map(|v| v.sku.clone()).collect()
+    }
+
+    #[test]
+    fn rows_are_grouped_by_codigo_pai() {
+        let report = parse(
+            "codigo_interno;codigo_pai;nome;preco;opcao_tamanho",
+            &["CAM-P;CAM;Camiseta;10,00;P", "CAM-M;CAM;;;M", "BON;;Boné;20,00;"],
+        );
+        assert_eq!(report.products.len(), 2);
+        let camiseta = &report.products[0];
+        assert_eq!((camiseta.codigo_interno.as_str(), camiseta.nome.as_str()), ("CAM", "Camiseta"));
+        assert_eq!(camiseta.options[0].title, "Tamanho");
+        assert_eq!(camiseta.options[0].values, ["P", "M"]);
+        // Rows with their own code keep it as SKU
+        assert_eq!(skus(&report, "CAM"), ["CAM-P", "CAM-M"]);
+        assert_eq!(skus(&report, "BON"), ["BON"]);
+    }
+
+    #[test]
+    fn rows_without_codigo_pai_group_by_codigo_interno() {
+        let report = parse(
+            "codigo_interno;nome;preco;opcao_cor;opcao_tamanho",
+            &["CAM;Camiseta;10,00;Azul;P", "CAM;;;Azul;M", "CAM;;;Verde Água;P"],
+        );
+        assert_eq!(report.products.len(), 1);
+        assert_eq!(skus(&report, "CAM"), ["CAM-azul-p", "CAM-azul-m", "CAM-verde-agua-p"]);
+        let variant = &report.products[0].variants[2];
+        assert_eq!(variant.options["Cor"], "Verde Água");
+        assert_eq!(variant.title(&report.products[0].options), "Verde Água / P");
+    }
+
+    #[test]
+    fn variant_price_is_kept_only_when_it_differs() {
+        let report = parse(
+            "codigo_interno;nome;preco;opcao_tamanho",
+            &["CAM;Camiseta;10,00;P", "CAM;;10,00;M", "CAM;;12,50;GG", "CAM;;;XG"],
+        );
+        let product = &report.products[0];
+        assert_eq!(product.preco, Money::brl(1000));
+        let prices: Vec<Option<Money>> = product.variants.iter().map(|v| v.preco).collect();
+        assert_eq!(prices, [None, None, Some(Money::brl(1250)), None]);
+    }
+
+    #[test]
+    fn repeated_option_combination_is_rejected() {
+        let report = parse(
+            "codigo_interno;nome;preco;opcao_cor",
+            &["CAM;Camiseta;10,00;Azul", "CAM;;;Verde", "CAM;;;Azul"],
+        );
+        assert_eq!(skus(&report, "CAM"), ["CAM-azul", "CAM-verde"]);
+        let diagnostic = report.diagnostics.iter().find(|d| d.line == 4).unwrap();
+        assert_eq!((diagnostic.column.as_str(), diagnostic.value.as_str()), ("opcao_cor", "Azul"));
+        assert_eq!(diagnostic.reason, "variant with the same options already defined on line 2");
+    }
+
+    #[test]
+    fn repeated_code_without_options_is_rejected() {
+        let report = parse("codigo_interno;nome;preco", &["CAM;Camiseta;10,00", "CAM;Camiseta;10,00"]);
+        assert_eq!(skus(&report, "CAM"), ["CAM"]);
+        let diagnostic = report.diagnostics.iter().find(|d| d.line == 3).unwrap();
+        assert_eq!(diagnostic.column, "codigo_interno");
+        assert!(diagnostic.reason.starts_with("duplicate codigo_interno, already used on line 2"));
+    }
+
+    #[test]
+    fn option_used_by_other_variants_is_required() {
+        let report = parse(
+            "codigo_interno;nome;preco;opcao_cor;opcao_tamanho",
+            &["CAM;Camiseta;10,00;Azul;P", "CAM;;;Verde;", "CAM;;;Verde;M"],
+        );
+        assert_eq!(skus(&report, "CAM"), ["CAM-azul-p", "CAM-verde-m"]);
+        let diagnostic = report.diagnostics.iter().find(|d| d.line == 3).unwrap();
+        assert_eq!(diagnostic.column, "opcao_tamanho");
+        assert_eq!(diagnostic.reason, "opcao_tamanho is required, other variants of CAM use it");
+    }
+}
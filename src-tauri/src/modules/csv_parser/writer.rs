@@ -0,0 +1,106 @@
+//! Writes products in the schema `parse_csv` reads, laid out for Excel in
+//! pt-BR: semicolons, UTF-8 with a byte order mark, CRLF line endings and
+//! prices like "1.299,90".
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+
+use super::variants::OPTION_PREFIX;
+use super::Product;
+use crate::modules::text::slugify;
+
+const COLUMNS: [&str; 17] = [
+    "codigo_interno",
+    "nome",
+    "preco",
+    "descricao",
+    "uuid",
+    "sku",
+    "estoque",
+    "ean",
+    "ncm",
+    "peso_g",
+    "altura_cm",
+    "largura_cm",
+    "comprimento_cm",
+    "categoria",
+    "colecao",
+    "tags",
+    "imagens",
+];
+
+/// One row per variant, all with the product's `codigo_interno` so the
+/// importer groups them back together. Product fields other than the name
+/// are written on the first row only, which is the row it takes them from.
+pub fn write_csv(products: &[Product]) -> Result<Vec<u8>> {
+    let mut option_columns: Vec<String> = Vec::new();
+    for option in products.iter().flat_map(|p| &p.options) {
+        let column = option_column(&option.title);
+        if !option_columns.contains(&column) {
+            option_columns.push(column);
+        }
+    }
+
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b';')
+        .terminator(csv::Terminator::CRLF)
+        .from_writer(b"\xEF\xBB\xBF".to_vec());
+
+    let headers = COLUMNS
+        .iter()
+        .map(|c| c.to_string())
+        .chain(option_columns.iter().cloned());
+    writer.write_record(headers)?;
+
+    for product in products {
+        for (index, variant) in product.variants.iter().enumerate() {
+            let first = index == 0;
+            let product_field = |value: String| if first { value } else { String::new() };
+            let price = variant.preco.unwrap_or(product.preco);
+
+            let mut row = vec![
+                product.codigo_interno.clone(),
+                product.nome.clone(),
+                price.to_ptbr_string(),
+                product_field(product.descricao.clone()),
+                product_field(product.uuid.clone().unwrap_or_default()),
+                variant.sku.clone(),
+                variant.estoque.map(|v| v.to_string()).unwrap_or_default(),
+                variant.ean.clone().unwrap_or_default(),
+                variant.ncm.clone().unwrap_or_default(),
+                variant.peso_g.map(|v| v.to_string()).unwrap_or_default(),
+                variant.altura_cm.map(format_cm).unwrap_or_default(),
+                variant.largura_cm.map(format_cm).unwrap_or_default(),
+                variant.comprimento_cm.map(format_cm).unwrap_or_default(),
+                product_field(product.categoria.join(" > ")),
+                product_field(product.colecao.clone().unwrap_or_default()),
+                product_field(product.tags.join(", ")),
+                product_field(product.imagens.join(" | ")),
+            ];
+            let options: HashMap<String, &String> = variant
+                .options
+                .iter()
+                .map(|(title, value)| (option_column(title), value))
+                .collect();
+            row.extend(
+                option_columns
+                    .iter()
+                    .map(|column| options.get(column).map(|v| v.to_string()).unwrap_or_default()),
+            );
+            writer.write_record(&row)?;
+        }
+    }
+
+    writer.into_inner().context("Failed to write CSV")
+}
+
+/// "Cor principal" -> "opcao_cor_principal", which the importer turns back
+/// into "Cor principal".
+fn option_column(title: &str) -> String {
+    format!("{}{}", OPTION_PREFIX, slugify(title).replace('-', "_"))
+}
+
+/// Centimetres with a decimal comma, as typed in pt-BR spreadsheets.
+fn format_cm(value: f64) -> String {
+    format!("{}", value).replace('.', ",")
+}
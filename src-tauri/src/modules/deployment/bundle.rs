@@ -0,0 +1,343 @@
+use anyhow::{Context, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use std::fs::File;
+use std::path::Path;
+use tauri::Emitter;
+
+use super::secrets::{render_env, StackSecrets};
+use super::{config_products, generate_caddyfile, generate_docker_compose, project_slug, IMAGES_BUCKET};
+use crate::modules::csv_parser::{self, Product};
+use crate::modules::medusa::{self, TaxonomyIds};
+use crate::modules::images;
+use crate::modules::text::slugify;
+use crate::DeployConfig;
+
+/// Seed files reference images through this placeholder, `install.sh`
+/// replaces it with `PUBLIC_URL` from `.env`.
+const PUBLIC_URL_PLACEHOLDER: &str = "__PUBLIC_URL__";
+
+const INSTALL_SCRIPT: &str = r#"#!/bin/sh
+# Installs __SLUG__ from this bundle: starts the stack, uploads the product
+# images to MinIO and seeds the products through the Medusa admin API.
+set -eu
+cd "$(dirname "$0")"
+
+env_value() {
+    grep "^$1=" .env | head -n 1 | cut -d= -f2-
+}
+
+if ! command -v docker >/dev/null 2>&1; then
+    echo "Docker is required: https://docs.docker.com/engine/install/" >&2
+    exit 1
+fi
+if ! command -v curl >/dev/null 2>&1; then
+    echo "curl is required" >&2
+    exit 1
+fi
+
+if docker compose version >/dev/null 2>&1; then
+    COMPOSE="docker compose"
+elif command -v docker-compose >/dev/null 2>&1; then
+    COMPOSE="docker-compose"
+else
+    echo "Docker Compose is required: https://docs.docker.com/compose/install/" >&2
+    exit 1
+fi
+
+PROJECT="$(env_value COMPOSE_PROJECT_NAME)"
+PUBLIC_URL="$(env_value PUBLIC_URL)"
+ADMIN_EMAIL="$(env_value MEDUSA_ADMIN_EMAIL)"
+ADMIN_PASSWORD="$(env_value MEDUSA_ADMIN_PASSWORD)"
+MINIO_USER="$(env_value MINIO_ROOT_USER)"
+MINIO_PASSWORD="$(env_value MINIO_ROOT_PASSWORD)"
+API="http://localhost:9000"
+
+echo "==> Starting containers"
+$COMPOSE --env-file .env up -d
+
+echo "==> Waiting for Medusa"
+tries=0
+until curl -fs "$API/health" >/dev/null 2>&1; do
+    tries=$((tries + 1))
+    if [ "$tries" -gt 60 ]; then
+        echo "Medusa did not become healthy, check: $COMPOSE logs medusa" >&2
+        exit 1
+    fi
+    sleep 5
+done
+
+if [ -d images ] && [ -n "$(ls -A images)" ]; then
+    echo "==> Uploading product images"
+    docker run --rm \
+        --network "${PROJECT}_medusa_network" \
+        -v "$(pwd)/images:/images:ro" \
+        --entrypoint sh minio/mc -c "
+            mc alias set local http://minio:9000 '$MINIO_USER' '$MINIO_PASSWORD' &&
+            mc mb -p local/__BUCKET__ &&
+            mc anonymous set download local/__BUCKET__ &&
+            mc cp -r /images/ local/__BUCKET__/"
+fi
+
+echo "==> Creating admin user"
+$COMPOSE exec -T medusa npx medusa user -e "$ADMIN_EMAIL" -p "$ADMIN_PASSWORD" >/dev/null 2>&1 || true
+
+TOKEN="$(curl -fs -X POST "$API/auth/user/emailpass" \
+    -H 'Content-Type: application/json' \
+    -d "{\"email\":\"$ADMIN_EMAIL\",\"password\":\"$ADMIN_PASSWORD\"}" \
+    | sed -n 's/.*"token":"\([^"]*\)".*/\1/p')"
+if [ -z "$TOKEN" ]; then
+    echo "Could not log in as $ADMIN_EMAIL" >&2
+    exit 1
+fi
+
+admin_get() {
+    curl -fs -G "$API$1" -H "Authorization: Bearer $TOKEN" --data-urlencode "fields=id" ${2:+--data-urlencode "$2"}
+}
+
+admin_post() {
+    curl -fs -X POST "$API$1" \
+        -H "Authorization: Bearer $TOKEN" \
+        -H 'Content-Type: application/json' \
+        -d "$2"
+}
+
+first_id() {
+    grep -o '"id":"[^"]*"' | head -n 1 | cut -d'"' -f4
+}
+
+# Each taxonomy line is "<placeholder> <endpoint> <payload>", parents before
+# children. Created ids replace their placeholders in later payloads.
+echo "==> Creating categories, collections and tags"
+printf 's#__PUBLIC_URL__#%s#g\n' "$PUBLIC_URL" > seed/ids.sed
+while IFS=' ' read -r placeholder endpoint payload || [ -n "$placeholder" ]; do
+    [ -z "$placeholder" ] && continue
+    payload="$(printf '%s' "$payload" | sed -f seed/ids.sed)"
+    id="$(admin_post "$endpoint" "$payload" | first_id)" || true
+    if [ -n "$id" ]; then
+        echo "s#$placeholder#$id#g" >> seed/ids.sed
+    else
+        echo "Could not create $placeholder" >&2
+    fi
+done < seed/taxonomy.txt
+
+echo "==> Seeding products"
+created=0
+failed=0
+while IFS= read -r line || [ -n "$line" ]; do
+    [ -z "$line" ] && continue
+    payload="$(printf '%s' "$line" | sed -f seed/ids.sed)"
+    if admin_post /admin/products "$payload" >/dev/null; then
+        created=$((created + 1))
+    else
+        failed=$((failed + 1))
+    fi
+done < seed/products.ndjson
+
+# Each inventory line is "<sku> TAB <stock> TAB <inventory item payload>"
+if [ -s seed/inventory.txt ]; then
+    echo "==> Setting stock levels"
+    LOCATION="$(admin_get /admin/stock-locations | first_id)" || true
+    if [ -z "$LOCATION" ]; then
+        LOCATION="$(admin_post /admin/stock-locations '{"name":"__STOCK_LOCATION__"}' | first_id)" || true
+        CHANNEL="$(admin_get /admin/sales-channels | first_id)" || true
+        if [ -n "$LOCATION" ] && [ -n "$CHANNEL" ]; then
+            admin_post "/admin/stock-locations/$LOCATION/sales-channels" "{\"add\":[\"$CHANNEL\"]}" >/dev/null || true
+        fi
+    fi
+
+    TAB="$(printf '\t')"
+    while IFS="$TAB" read -r sku stock payload || [ -n "$sku" ]; do
+        [ -z "$sku" ] && continue
+        item="$(admin_get /admin/inventory-items "sku=$sku" | first_id)" || true
+        if [ -z "$item" ] || [ -z "$LOCATION" ] \
+            || ! admin_post "/admin/inventory-items/$item" "$payload" >/dev/null \
+            || ! admin_post "/admin/inventory-items/$item/location-levels" \
+                "{\"location_id\":\"$LOCATION\",\"stocked_quantity\":$stock}" >/dev/null; then
+            echo "Could not set the stock of $sku" >&2
+        fi
+    done < seed/inventory.txt
+fi
+
+echo "==> Done: $created products created, $failed failed"
+echo "Store: $PUBLIC_URL"
+echo "Admin: $PUBLIC_URL/app ($ADMIN_EMAIL / see MEDUSA_ADMIN_PASSWORD in .env)"
+"#;
+
+#[derive(Debug, Serialize)]
+pub struct BundleSummary {
+    pub path: String,
+    pub products: usize,
+    pub images: usize,
+    /// Image URLs that could not be downloaded and stay linked as they are
+    #[serde(rename = "failedDownloads")]
+    pub failed_downloads: usize,
+    pub bytes: u64,
+}
+
+/// Writes a self-contained tar.gz with everything needed to install the store
+/// without SSH access: compose file, Caddyfile, `.env`, optimized images, the
+/// product seed and `install.sh`. Image URLs from the product file are
+/// downloaded and bundled like the images of the product folders.
+pub async fn export_bundle(config: &DeployConfig, output_path: &Path, app: &tauri::AppHandle) -> Result<BundleSummary> {
+    let slug = project_slug(&config.identity);
+    let domain = config.server.as_ref()
+        .map(|s| s.domain.as_str())
+        .filter(|d| !d.is_empty())
+        .unwrap_or("localhost");
+    let public_url = format!("https://{}", domain);
+
+    let secrets = StackSecrets::generate(&format!("admin@{}", domain));
+    let env = render_env(config, &slug, &public_url, &secrets);
+    let compose = generate_docker_compose(config)?;
+    let caddyfile = generate_caddyfile(config)?;
+
+    let products = config_products(config)?;
+    let cache_dir = images::cache_dir(app)?;
+    let sources = images::collect_sources(&products, &config.images_mapping, &cache_dir, |progress| {
+        let _ = app.emit("image-download-progress", progress);
+    })
+    .await?;
+    let processed = images::process_images(&products, &sources, &cache_dir, &config.image_options, |progress| {
+        let _ = app.emit("image-progress", progress);
+    })
+    .await?;
+
+    let install = INSTALL_SCRIPT
+        .replace("__SLUG__", &slug)
+        .replace("__BUCKET__", IMAGES_BUCKET)
+        .replace("__STOCK_LOCATION__", medusa::STOCK_LOCATION_NAME);
+    let files = vec![
+        ("docker-compose.yml", compose, 0o644),
+        ("Caddyfile", caddyfile, 0o644),
+        (".env", env, 0o600),
+        ("install.sh", install, 0o755),
+    ];
+
+    let staging = std::env::temp_dir().join(format!("medusa-bundle-{}", uuid::Uuid::new_v4().simple()));
+    let result = stage_and_pack(&slug, &products, &sources.downloads, &processed, files, &staging, output_path);
+    let _ = std::fs::remove_dir_all(&staging);
+
+    let image_count = result?;
+    Ok(BundleSummary {
+        path: output_path.display().to_string(),
+        products: products.len(),
+        images: image_count,
+        failed_downloads: sources.downloads.failed.len(),
+        bytes: std::fs::metadata(output_path)?.len(),
+    })
+}
+
+fn stage_and_pack(
+    slug: &str,
+    products: &[Product],
+    downloads: &images::DownloadReport,
+    processed: &images::ProcessedImages,
+    files: Vec<(&str, String, u32)>,
+    staging: &Path,
+    output_path: &Path,
+) -> Result<usize> {
+    let ids = taxonomy_placeholders(products);
+    let images_dir = staging.join("images");
+    let staged = images::stage_images(processed, &images_dir)?;
+    let manifest = staged.with_base(&format!("{}/images/{}", PUBLIC_URL_PLACEHOLDER, IMAGES_BUCKET));
+
+    let mut seed = String::new();
+    for product in products {
+        // Downloaded URLs are replaced by their bundled copies
+        let images = manifest.images_of(&product.codigo_interno);
+        let product = &Product { imagens: downloads.remote_of(product), ..product.clone() };
+        let mut payload = medusa::product_payload(product, images);
+        medusa::link_taxonomy(&mut payload, product, &ids);
+        seed.push_str(&serde_json::to_string(&payload)?);
+        seed.push('\n');
+    }
+
+    let file = File::create(output_path)
+        .with_context(|| format!("Failed to create {}", output_path.display()))?;
+    let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    for (name, contents, mode) in files {
+        append_text(&mut tar, &format!("{}/{}", slug, name), &contents, mode)?;
+    }
+    append_text(&mut tar, &format!("{}/seed/taxonomy.txt", slug), &taxonomy_seed(products, &ids)?, 0o644)?;
+    append_text(&mut tar, &format!("{}/seed/products.ndjson", slug), &seed, 0o644)?;
+    append_text(&mut tar, &format!("{}/seed/inventory.txt", slug), &inventory_seed(products)?, 0o644)?;
+    if images_dir.is_dir() {
+        tar.append_dir_all(format!("{}/images", slug), &images_dir)?;
+    }
+
+    tar.into_inner()?.finish()?;
+    Ok(staged.image_count())
+}
+
+/// Placeholder ids for the catalog's categories, collections and tags, which
+/// `install.sh` swaps for the real ids as it creates them.
+fn taxonomy_placeholders(products: &[Product]) -> TaxonomyIds {
+    let mut ids = TaxonomyIds::default();
+    for (node, _) in medusa::flatten_categories(&csv_parser::category_tree(products)) {
+        ids.categories.insert(node.handle.clone(), format!("__CATEGORY_{}__", node.handle));
+    }
+    for title in csv_parser::collections(products) {
+        let handle = slugify(&title);
+        ids.collections.insert(handle.clone(), format!("__COLLECTION_{}__", handle));
+    }
+    for (index, value) in csv_parser::tags(products).iter().enumerate() {
+        ids.tags.insert(value.to_lowercase(), format!("__TAG_{}__", index + 1));
+    }
+    ids
+}
+
+/// One "<placeholder> <endpoint> <payload>" line per taxonomy entry, parents
+/// before children.
+fn taxonomy_seed(products: &[Product], ids: &TaxonomyIds) -> Result<String> {
+    let mut lines = Vec::new();
+    for (node, parent) in medusa::flatten_categories(&csv_parser::category_tree(products)) {
+        let parent_id = parent.and_then(|handle| ids.categories.get(handle)).map(String::as_str);
+        lines.push((
+            &ids.categories[&node.handle],
+            "/admin/product-categories",
+            medusa::category_payload(node, parent_id),
+        ));
+    }
+    for title in csv_parser::collections(products) {
+        lines.push((&ids.collections[&slugify(&title)], "/admin/collections", medusa::collection_payload(&title)));
+    }
+    for value in csv_parser::tags(products) {
+        lines.push((&ids.tags[&value.to_lowercase()], "/admin/product-tags", medusa::tag_payload(&value)));
+    }
+
+    let mut seed = String::new();
+    for (placeholder, endpoint, payload) in lines {
+        seed.push_str(&format!("{} {} {}\n", placeholder, endpoint, serde_json::to_string(&payload)?));
+    }
+    Ok(seed)
+}
+
+/// One "<sku> TAB <stock> TAB <payload>" line per variant with stock.
+fn inventory_seed(products: &[Product]) -> Result<String> {
+    let mut seed = String::new();
+    for variant in products.iter().flat_map(|p| &p.variants) {
+        if let Some(estoque) = variant.estoque {
+            let payload = serde_json::to_string(&medusa::inventory_item_payload(variant))?;
+            seed.push_str(&format!("{}\t{}\t{}\n", variant.sku, estoque, payload));
+        }
+    }
+    Ok(seed)
+}
+
+fn append_text<W: std::io::Write>(tar: &mut tar::Builder<W>, path: &str, contents: &str, mode: u32) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(mode);
+    header.set_mtime(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    );
+    header.set_cksum();
+    tar.append_data(&mut header, path, contents.as_bytes())?;
+    Ok(())
+}
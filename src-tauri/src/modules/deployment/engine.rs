@@ -0,0 +1,549 @@
+use anyhow::{Context, Result};
+use http_body_util::{BodyExt, Empty};
+use hyper::body::{Bytes, Incoming};
+use hyper::{Method, Request, Response};
+use hyper_util::rt::TokioIo;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use super::runtime::Engine;
+use crate::modules::ssh;
+
+/// Client for the Docker Engine API (also served by Podman) over a Unix
+/// socket, either local or forwarded from a remote host through SSH.
+#[derive(Debug, Clone)]
+pub struct DockerEngine {
+    socket: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+pub struct ContainerState {
+    pub status: String,
+    pub running: bool,
+    /// `None` when the container has no healthcheck
+    pub health: Option<String>,
+}
+
+impl ContainerState {
+    pub fn is_ready(&self) -> bool {
+        match self.health.as_deref() {
+            Some(health) => health == "healthy",
+            None => self.running,
+        }
+    }
+
+    pub fn has_failed(&self) -> bool {
+        self.status == "exited" || self.status == "dead" || self.health.as_deref() == Some("unhealthy")
+    }
+}
+
+/// Aggregated pull progress for one image, in bytes across all layers.
+#[derive(Debug, Clone)]
+pub struct PullProgress {
+    pub status: String,
+    pub current: u64,
+    pub total: u64,
+}
+
+#[derive(Deserialize)]
+struct InspectResponse {
+    #[serde(rename = "State")]
+    state: InspectState,
+}
+
+#[derive(Deserialize)]
+struct InspectState {
+    #[serde(rename = "Status")]
+    status: String,
+    #[serde(rename = "Running")]
+    running: bool,
+    #[serde(rename = "Health")]
+    health: Option<InspectHealth>,
+}
+
+#[derive(Deserialize)]
+struct InspectHealth {
+    #[serde(rename = "Status")]
+    status: String,
+}
+
+#[derive(Deserialize)]
+struct PullMessage {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default, rename = "progressDetail")]
+    progress_detail: Option<PullDetail>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PullDetail {
+    #[serde(default)]
+    current: Option<u64>,
+    #[serde(default)]
+    total: Option<u64>,
+}
+
+impl DockerEngine {
+    pub fn new(socket: impl Into<PathBuf>) -> Self {
+        Self { socket: socket.into() }
+    }
+
+    /// Finds the API socket for the local engine, honouring `DOCKER_HOST`.
+    pub fn local(engine: Engine) -> Option<Self> {
+        if let Some(path) = std::env::var("DOCKER_HOST")
+            .ok()
+            .and_then(|host| host.strip_prefix("unix://").map(PathBuf::from))
+        {
+            return Some(Self::new(path));
+        }
+
+        let home = std::env::var("HOME").unwrap_or_default();
+        let candidates: Vec<PathBuf> = match engine {
+            Engine::Docker => vec![
+                PathBuf::from("/var/run/docker.sock"),
+                Path::new(&home).join(".docker/run/docker.sock"),
+                Path::new(&home).join(".docker/desktop/docker.sock"),
+            ],
+            Engine::Podman => {
+                let runtime_dir = std::env::var("XDG_RUNTIME_DIR").unwrap_or_default();
+                vec![
+                    Path::new(&runtime_dir).join("podman/podman.sock"),
+                    PathBuf::from("/run/podman/podman.sock"),
+                ]
+            }
+        };
+
+        candidates.into_iter().find(|p| p.exists()).map(Self::new)
+    }
+
+    pub async fn ping(&self) -> Result<()> {
+        self.get_bytes("/_ping").await.map(|_| ())
+    }
+
+    pub async fn inspect_container(&self, name: &str) -> Result<ContainerState> {
+        let body = self.get_bytes(&format!("/containers/{}/json", name)).await?;
+        let inspect: InspectResponse = serde_json::from_slice(&body)
+            .context("Failed to parse container inspect response")?;
+
+        Ok(ContainerState {
+            status: inspect.state.status,
+            running: inspect.state.running,
+            health: inspect.state.health.map(|h| h.status),
+        })
+    }
+
+    /// Polls the container until it is healthy (or running, when it has no
+    /// healthcheck). Fails early if it exits or turns unhealthy.
+    pub async fn wait_ready(&self, name: &str, timeout: Duration) -> Result<ContainerState> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let state = self.inspect_container(name).await?;
+            if state.is_ready() {
+                return Ok(state);
+            }
+            if state.has_failed() {
+                anyhow::bail!(
+                    "Container {} is {}",
+                    name,
+                    state.health.as_deref().unwrap_or(&state.status)
+                );
+            }
+            if tokio::time::Instant::now() >= deadline {
+                anyhow::bail!("Timed out waiting for container {} ({})", name, state.status);
+            }
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        }
+    }
+
+    /// Streams the container's stdout and stderr line by line. With
+    /// `follow` the call returns only when the container stops.
+    pub async fn logs(
+        &self,
+        name: &str,
+        tail: usize,
+        follow: bool,
+        mut on_line: impl FnMut(&str),
+    ) -> Result<()> {
+        let path = format!(
+            "/containers/{}/logs?stdout=1&stderr=1&tail={}&follow={}",
+            name, tail, follow
+        );
+        let response = self.request(Method::GET, &path).await?;
+        let mut body = response.into_body();
+
+        let mut decoder = LogDecoder::default();
+        let mut pending = String::new();
+        while let Some(frame) = body.frame().await {
+            let frame = frame.context("Failed to read container logs")?;
+            if let Some(data) = frame.data_ref() {
+                decoder.push(data, &mut pending);
+                while let Some(pos) = pending.find('\n') {
+                    let line: String = pending.drain(..=pos).collect();
+                    on_line(line.trim_end());
+                }
+            }
+        }
+        decoder.finish(&mut pending);
+        if !pending.is_empty() {
+            on_line(pending.trim_end());
+        }
+
+        Ok(())
+    }
+
+    /// Pulls `image` ("name:tag"), reporting byte progress across layers.
+    pub async fn pull_image(&self, image: &str, mut on_progress: impl FnMut(&PullProgress)) -> Result<()> {
+        let (name, tag) = match image.rsplit_once(':') {
+            Some((name, tag)) if !tag.contains('/') => (name, tag),
+            _ => (image, "latest"),
+        };
+        let path = format!("/images/create?fromImage={}&tag={}", name, tag);
+        let response = self.request(Method::POST, &path).await?;
+        let mut body = response.into_body();
+
+        let mut tracker = PullTracker::default();
+        while let Some(frame) = body.frame().await {
+            let frame = frame.context("Failed to read pull progress")?;
+            let Some(data) = frame.data_ref() else { continue };
+            let progress = tracker
+                .push(data)
+                .map_err(|error| anyhow::anyhow!("Failed to pull {}: {}", image, error))?;
+            for progress in &progress {
+                on_progress(progress);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_bytes(&self, path: &str) -> Result<Bytes> {
+        let response = self.request(Method::GET, path).await?;
+        Ok(response.into_body().collect().await?.to_bytes())
+    }
+
+    async fn request(&self, method: Method, path: &str) -> Result<Response<Incoming>> {
+        let stream = connect(&self.socket).await?;
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .context("Docker API handshake failed")?;
+        tokio::spawn(async move {
+            let _ = connection.await;
+        });
+
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .header("Host", "docker")
+            .body(Empty::<Bytes>::new())?;
+        let response = sender.send_request(request).await
+            .with_context(|| format!("Docker API request {} failed", path))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.into_body().collect().await?.to_bytes();
+            let message = serde_json::from_slice::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|v| v.get("message").and_then(|m| m.as_str()).map(str::to_string))
+                .unwrap_or_else(|| String::from_utf8_lossy(&body).to_string());
+            anyhow::bail!("Docker API returned {}: {}", status, message);
+        }
+
+        Ok(response)
+    }
+}
+
+#[cfg(unix)]
+async fn connect(socket: &Path) -> Result<tokio::net::UnixStream> {
+    tokio::net::UnixStream::connect(socket)
+        .await
+        .with_context(|| format!("Failed to connect to Docker socket {}", socket.display()))
+}
+
+#[cfg(not(unix))]
+async fn connect(_socket: &Path) -> Result<tokio::io::DuplexStream> {
+    anyhow::bail!("The Docker Engine API over a Unix socket is not available on this platform")
+}
+
+/// Progress of an image pull from the newline separated JSON messages of
+/// the API, which may arrive split across chunks.
+#[derive(Default)]
+struct PullTracker {
+    /// Current and total bytes of each layer
+    layers: HashMap<String, (u64, u64)>,
+    pending: Vec<u8>,
+}
+
+impl PullTracker {
+    /// Takes the next chunk of the response, returning the progress after
+    /// each complete message in it, or the error Docker reported.
+    fn push(&mut self, data: &[u8]) -> std::result::Result<Vec<PullProgress>, String> {
+        self.pending.extend_from_slice(data);
+        let mut progress = Vec::new();
+        while let Some(pos) = self.pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=pos).collect();
+            let Ok(message) = serde_json::from_slice::<PullMessage>(&line) else { continue };
+
+            if let Some(error) = message.error {
+                return Err(error);
+            }
+
+            if let (Some(id), Some(detail)) = (&message.id, &message.progress_detail) {
+                if let (Some(current), Some(total)) = (detail.current, detail.total) {
+                    self.layers.insert(id.clone(), (current, total));
+                }
+            }
+
+            let (current, total) = self.layers
+                .values()
+                .fold((0, 0), |(c, t), (lc, lt)| (c + lc, t + lt));
+            progress.push(PullProgress {
+                status: message.status.unwrap_or_default(),
+                current,
+                total,
+            });
+        }
+        Ok(progress)
+    }
+}
+
+/// Containers without a TTY multiplex stdout/stderr in frames with an 8 byte
+/// header (stream type, 3 zero bytes, big endian length); raw TTY output is
+/// passed through as is. Frames may arrive split across chunks and a UTF-8
+/// character may be split across frames, so incomplete bytes are kept for
+/// the next chunk.
+#[derive(Default)]
+struct LogDecoder {
+    /// Received bytes not yet taken out of their frame
+    buffer: Vec<u8>,
+    /// Log bytes ending in an incomplete UTF-8 character
+    text: Vec<u8>,
+}
+
+impl LogDecoder {
+    fn push(&mut self, data: &[u8], out: &mut String) {
+        self.buffer.extend_from_slice(data);
+        while !self.buffer.is_empty() {
+            let header_like = self.buffer[0] <= 2 && self.buffer.iter().skip(1).take(3).all(|b| *b == 0);
+            if !header_like {
+                self.text.append(&mut self.buffer);
+                break;
+            }
+            if self.buffer.len() < 8 {
+                break;
+            }
+
+            let len = u32::from_be_bytes([self.buffer[4], self.buffer[5], self.buffer[6], self.buffer[7]]) as usize;
+            if self.buffer.len() < 8 + len {
+                break;
+            }
+            self.text.extend(self.buffer.drain(..8 + len).skip(8));
+        }
+        decode_utf8(&mut self.text, out);
+    }
+
+    /// Whatever is left at the end of the stream, invalid bytes replaced.
+    fn finish(self, out: &mut String) {
+        out.push_str(&String::from_utf8_lossy(&self.text));
+        out.push_str(&String::from_utf8_lossy(&self.buffer));
+    }
+}
+
+/// Moves the text of `bytes` into `out`, replacing invalid sequences but
+/// leaving a character cut at the end in `bytes`.
+fn decode_utf8(bytes: &mut Vec<u8>, out: &mut String) {
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => {
+                out.push_str(text);
+                bytes.clear();
+                return;
+            }
+            Err(e) => {
+                let valid = e.valid_up_to();
+                out.push_str(&String::from_utf8_lossy(&bytes[..valid]));
+                match e.error_len() {
+                    Some(invalid) => {
+                        out.push(char::REPLACEMENT_CHARACTER);
+                        bytes.drain(..valid + invalid);
+                    }
+                    None => {
+                        bytes.drain(..valid);
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Forwards a remote Docker socket to a local Unix socket with the OpenSSH
+/// client. The forward is torn down when the tunnel is dropped.
+pub struct SshTunnel {
+    child: Child,
+    socket: PathBuf,
+}
+
+impl SshTunnel {
+    pub async fn open(ip: &str, key_path: Option<&str>) -> Result<Self> {
+        let key_path = match key_path {
+            Some(path) => PathBuf::from(path),
+            None => ssh::get_default_ssh_key()?,
+        };
+        let socket = std::env::temp_dir().join(format!(
+            "medusa-docker-{}-{}.sock",
+            ip.replace(['.', ':'], "_"),
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&socket);
+
+        let child = Command::new("ssh")
+            .arg("-nNT")
+            .args(["-o", "BatchMode=yes"])
+            .args(["-o", "ExitOnForwardFailure=yes"])
+            .args(["-o", "StrictHostKeyChecking=accept-new"])
+            .arg("-i")
+            .arg(&key_path)
+            .arg("-L")
+            .arg(format!("{}:/var/run/docker.sock", socket.display()))
+            .arg(format!("root@{}", ip))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Failed to start ssh for the Docker socket tunnel")?;
+
+        let mut tunnel = Self { child, socket };
+        for _ in 0..50 {
+            if tunnel.socket.exists() {
+                return Ok(tunnel);
+            }
+            if let Some(status) = tunnel.child.try_wait()? {
+                anyhow::bail!("SSH tunnel to {} exited with {}", ip, status);
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+
+        anyhow::bail!("Timed out opening SSH tunnel to the Docker socket on {}", ip)
+    }
+
+    pub fn engine(&self) -> DockerEngine {
+        DockerEngine::new(&self.socket)
+    }
+}
+
+impl Drop for SshTunnel {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.socket);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(stream: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![stream, 0, 0, 0];
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    /// Feeds `chunks` one by one, as they would arrive from the socket.
+    fn decode(chunks: &[&[u8]]) -> String {
+        let mut decoder = LogDecoder::default();
+        let mut out = String::new();
+        for chunk in chunks {
+            decoder.push(chunk, &mut out);
+        }
+        decoder.finish(&mut out);
+        out
+    }
+
+    #[test]
+    fn frames_of_both_streams_are_joined() {
+        let bytes = [frame(1, b"servidor iniciado\n"), frame(2, b"aviso: cache vazio\n")].concat();
+        assert_eq!(decode(&[&bytes]), "servidor iniciado\naviso: cache vazio\n");
+    }
+
+    #[test]
+    fn frames_split_across_chunks_wait_for_the_rest() {
+        let bytes = [frame(1, b"primeira\n"), frame(1, b"segunda\n")].concat();
+        for at in 1..bytes.len() {
+            let (head, tail) = bytes.split_at(at);
+            let mut decoder = LogDecoder::default();
+            let mut out = String::new();
+            decoder.push(head, &mut out);
+            assert!("primeira\nsegunda\n".starts_with(&out), "split at {}: {:?}", at, out);
+            decoder.push(tail, &mut out);
+            assert_eq!(out, "primeira\nsegunda\n", "split at {}", at);
+        }
+    }
+
+    #[test]
+    fn characters_split_across_frames_are_kept_whole() {
+        let text = "Configuração concluída\n".as_bytes();
+        // Cuts "ç" in two
+        let cut = text.iter().position(|b| *b == 0xC3).unwrap() + 1;
+        let bytes = [frame(1, &text[..cut]), frame(1, &text[cut..])].concat();
+        assert_eq!(decode(&[&bytes]), "Configuração concluída\n");
+
+        let first = frame(1, &text[..cut]);
+        let second = frame(1, &text[cut..]);
+        assert_eq!(decode(&[&first, &second]), "Configuração concluída\n");
+    }
+
+    #[test]
+    fn tty_output_is_passed_through() {
+        let text = "Olá, mundo\n".as_bytes();
+        assert_eq!(decode(&[&text[..3], &text[3..]]), "Olá, mundo\n");
+    }
+
+    #[test]
+    fn invalid_bytes_are_replaced() {
+        assert_eq!(decode(&[&frame(1, b"a\xffb\n")]), "a\u{fffd}b\n");
+        // A character cut by the end of the stream
+        assert_eq!(decode(&[&frame(1, b"fim \xc3")]), "fim \u{fffd}");
+    }
+
+    #[test]
+    fn pull_progress_adds_up_the_layers() {
+        let mut tracker = PullTracker::default();
+        let progress = tracker
+            .push(
+                b"{\"status\":\"Pulling from library/redis\",\"id\":\"7\"}\n\
+                  {\"status\":\"Downloading\",\"id\":\"a\",\"progressDetail\":{\"current\":10,\"total\":100}}\n\
+                  {\"status\":\"Downloading\",\"id\":\"b\",\"progressDetail\":{\"current\":5,",
+            )
+            .unwrap();
+        let seen: Vec<(&str, u64, u64)> = progress.iter().map(|p| (p.status.as_str(), p.current, p.total)).collect();
+        assert_eq!(seen, [("Pulling from library/redis", 0, 0), ("Downloading", 10, 100)]);
+
+        // The rest of the split message, then an update of the first layer
+        let progress = tracker
+            .push(
+                b"\"total\":50}}\n\
+                  {\"status\":\"Downloading\",\"id\":\"a\",\"progressDetail\":{\"current\":60,\"total\":100}}\n",
+            )
+            .unwrap();
+        let seen: Vec<(u64, u64)> = progress.iter().map(|p| (p.current, p.total)).collect();
+        assert_eq!(seen, [(15, 150), (65, 150)]);
+    }
+
+    #[test]
+    fn pull_errors_are_returned() {
+        let mut tracker = PullTracker::default();
+        let error = tracker.push(b"{\"error\":\"manifest unknown\"}\n").unwrap_err();
+        assert_eq!(error, "manifest unknown");
+        // Lines that are not JSON are skipped
+        assert!(tracker.push(b"not json\n").unwrap().is_empty());
+    }
+}
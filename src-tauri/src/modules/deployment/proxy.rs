@@ -0,0 +1,192 @@
+//! Shared Caddy reverse proxy for hosting several stores on one server.
+//!
+//! The proxy runs as its own compose stack and owns ports 80/443. Each store
+//! stack joins the external `PROXY_NETWORK` and gets a site block in the
+//! shared Caddyfile, delimited by markers so it can be replaced or removed
+//! without touching the other stores.
+
+use anyhow::{Context, Result};
+use crate::modules::ssh::SshConnection;
+
+pub const PROXY_NETWORK: &str = "medusa_proxy";
+pub const PROXY_DIR: &str = "/opt/medusa-proxy";
+pub const PROXY_CONTAINER: &str = "medusa-proxy-caddy";
+pub const STORES_DIR: &str = "/opt/medusa-stores";
+
+const CADDYFILE_HEADER: &str = "# Managed by MedusaProject - one site block per store\n";
+
+fn site_begin(domain: &str) -> String {
+    format!("# >>> medusa-site {}", domain)
+}
+
+fn site_end(domain: &str) -> String {
+    format!("# <<< medusa-site {}", domain)
+}
+
+pub fn proxy_compose() -> String {
+    format!(r#"name: medusa-proxy
+
+services:
+  caddy:
+    image: caddy:2-alpine
+    container_name: {container}
+    restart: unless-stopped
+    ports:
+      - "80:80"
+      - "443:443"
+    volumes:
+      # The directory is mounted instead of the file so that replacing the
+      # Caddyfile atomically is visible inside the container
+      - ./config:/etc/caddy
+      - {stores}:/srv/stores:ro
+      - caddy_data:/data
+      - caddy_config:/config
+    networks:
+      - proxy
+
+networks:
+  proxy:
+    name: {network}
+    external: true
+
+volumes:
+  caddy_data:
+  caddy_config:
+"#,
+        container = PROXY_CONTAINER,
+        stores = STORES_DIR,
+        network = PROXY_NETWORK,
+    )
+}
+
+/// Inserts the site block for `domain`, replacing a previous one if present.
+pub fn upsert_site_block(caddyfile: &str, domain: &str, block: &str) -> String {
+    let mut result = remove_site_block(caddyfile, domain);
+    if !result.is_empty() {
+        result.push('\n');
+    }
+
+    result.push_str(&site_begin(domain));
+    result.push('\n');
+    result.push_str(block.trim_end());
+    result.push('\n');
+    result.push_str(&site_end(domain));
+    result.push('\n');
+    result
+}
+
+/// Removes the site block for `domain`, leaving the rest of the file as is
+/// apart from collapsing the blank lines around the removed block.
+pub fn remove_site_block(caddyfile: &str, domain: &str) -> String {
+    let begin = site_begin(domain);
+    let end = site_end(domain);
+
+    let mut lines: Vec<&str> = Vec::new();
+    let mut inside = false;
+    for line in caddyfile.lines() {
+        if line.trim() == begin {
+            inside = true;
+            continue;
+        }
+        if inside {
+            if line.trim() == end {
+                inside = false;
+            }
+            continue;
+        }
+        let blank = line.trim().is_empty();
+        if blank && lines.last().is_none_or(|l| l.trim().is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+
+    while lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
+    }
+
+    let mut result = lines.join("\n");
+    if !result.is_empty() {
+        result.push('\n');
+    }
+    result
+}
+
+/// Makes sure the proxy network and the shared Caddy stack are up.
+pub fn ensure_proxy(ssh: &SshConnection) -> Result<()> {
+    ssh.execute(&format!(
+        "docker network inspect {net} >/dev/null 2>&1 || docker network create {net}",
+        net = PROXY_NETWORK
+    ))
+    .context("Failed to create proxy network")?;
+
+    ssh.execute(&format!("mkdir -p {}/config {}", PROXY_DIR, STORES_DIR))?;
+    ssh.upload(&format!("{}/docker-compose.yml", PROXY_DIR), proxy_compose().as_bytes())?;
+    ssh.execute(&format!(
+        "test -f {dir}/config/Caddyfile || printf '%s' '{header}' > {dir}/config/Caddyfile",
+        dir = PROXY_DIR,
+        header = CADDYFILE_HEADER.trim_end()
+    ))?;
+
+    ssh.execute(&format!("cd {} && docker compose up -d", PROXY_DIR))
+        .context("Failed to start shared Caddy proxy")?;
+
+    Ok(())
+}
+
+/// Adds or replaces the site block for `domain` and reloads Caddy.
+pub fn add_site(ssh: &SshConnection, domain: &str, block: &str) -> Result<()> {
+    validate_domain(domain)?;
+
+    let current = read_caddyfile(ssh)?;
+    let updated = upsert_site_block(&current, domain, block);
+    apply_caddyfile(ssh, &updated)
+}
+
+/// Removes the site block for `domain` and reloads Caddy.
+pub fn remove_site(ssh: &SshConnection, domain: &str) -> Result<()> {
+    validate_domain(domain)?;
+
+    let current = read_caddyfile(ssh)?;
+    let updated = remove_site_block(&current, domain);
+    apply_caddyfile(ssh, &updated)
+}
+
+fn read_caddyfile(ssh: &SshConnection) -> Result<String> {
+    ssh.execute(&format!("cat {}/config/Caddyfile 2>/dev/null || true", PROXY_DIR))
+}
+
+/// Validates the new Caddyfile inside the running container before swapping
+/// it in, then performs a graceful reload so existing connections are kept.
+fn apply_caddyfile(ssh: &SshConnection, caddyfile: &str) -> Result<()> {
+    let staged = format!("{}/config/Caddyfile.next", PROXY_DIR);
+    ssh.upload(&staged, caddyfile.as_bytes())?;
+
+    if let Err(e) = ssh.execute(&format!(
+        "docker exec {} caddy validate --adapter caddyfile --config /etc/caddy/Caddyfile.next",
+        PROXY_CONTAINER
+    )) {
+        let _ = ssh.execute(&format!("rm -f {}", staged));
+        return Err(e.context("Generated Caddyfile is invalid"));
+    }
+
+    ssh.execute(&format!("mv {} {}/config/Caddyfile", staged, PROXY_DIR))?;
+    ssh.execute(&format!(
+        "docker exec {} caddy reload --adapter caddyfile --config /etc/caddy/Caddyfile",
+        PROXY_CONTAINER
+    ))
+    .context("Failed to reload Caddy")?;
+
+    Ok(())
+}
+
+fn validate_domain(domain: &str) -> Result<()> {
+    let valid = !domain.is_empty()
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    if !valid {
+        anyhow::bail!("Invalid domain: {}", domain);
+    }
+    Ok(())
+}
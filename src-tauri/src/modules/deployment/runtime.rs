@@ -0,0 +1,182 @@
+use anyhow::{Context, Result};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::{Command, Output};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    Docker,
+    Podman,
+}
+
+/// How compose is invoked on this machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComposeCommand {
+    /// `docker compose` (v2 CLI plugin)
+    Plugin,
+    /// Legacy standalone `docker-compose`
+    Standalone,
+    /// `podman-compose`
+    PodmanCompose,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RuntimeError {
+    #[error("Docker is not installed. Install Docker Desktop (https://docs.docker.com/get-docker/) or Podman with podman-compose")]
+    NotInstalled,
+    #[error("The Docker daemon is not reachable ({0}). Start Docker Desktop or run 'sudo systemctl start docker'")]
+    DaemonUnreachable(String),
+    #[error("Permission denied on the Docker socket. Add your user to the docker group with 'sudo usermod -aG docker $USER' and log in again")]
+    PermissionDenied,
+    #[error("Podman is not reachable ({0}). Run 'podman machine start' or check the Podman service")]
+    PodmanUnreachable(String),
+    #[error("Docker Compose was not found. Install the 'docker compose' v2 plugin or the legacy 'docker-compose'")]
+    ComposeMissing,
+    #[error("Podman was found but podman-compose is not installed. Install it with 'pip install podman-compose'")]
+    PodmanComposeMissing,
+}
+
+/// Container engine and compose flavour detected on the local machine.
+#[derive(Debug, Clone)]
+pub struct ContainerRuntime {
+    pub engine: Engine,
+    pub version: String,
+    pub compose: ComposeCommand,
+    pub compose_version: String,
+}
+
+impl ContainerRuntime {
+    /// Probes Docker first and falls back to Podman when the `docker` binary
+    /// is missing. Blocking, run it off the async runtime.
+    pub fn detect() -> std::result::Result<Self, RuntimeError> {
+        match run(&["docker", "version", "--format", "{{.Server.Version}}"]) {
+            Probe::Ok(version) => {
+                let (compose, compose_version) = detect_docker_compose()?;
+                Ok(Self {
+                    engine: Engine::Docker,
+                    version,
+                    compose,
+                    compose_version,
+                })
+            }
+            Probe::Failed(stderr) => Err(classify_docker_error(&stderr)),
+            Probe::NotFound => detect_podman(),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let engine = match self.engine {
+            Engine::Docker => "Docker",
+            Engine::Podman => "Podman",
+        };
+        let compose = match self.compose {
+            ComposeCommand::Plugin => "docker compose",
+            ComposeCommand::Standalone => "docker-compose",
+            ComposeCommand::PodmanCompose => "podman-compose",
+        };
+        format!("{} {} ({} {})", engine, self.version, compose, self.compose_version)
+    }
+
+    /// Runs a compose subcommand for `project` using the compose file in
+    /// `project_dir`, returning stdout or an error carrying stderr.
+    pub fn compose(&self, project_dir: &Path, project: &str, args: &[&str]) -> Result<String> {
+        let mut command = match self.compose {
+            ComposeCommand::Plugin => {
+                let mut c = Command::new("docker");
+                c.arg("compose");
+                c
+            }
+            ComposeCommand::Standalone => Command::new("docker-compose"),
+            ComposeCommand::PodmanCompose => Command::new("podman-compose"),
+        };
+
+        let output = command
+            .current_dir(project_dir)
+            .args(["-p", project, "-f", "docker-compose.yml"])
+            .args(args)
+            .output()
+            .context("Failed to run compose")?;
+
+        check_output(output, &format!("compose {}", args.join(" ")))
+    }
+}
+
+enum Probe {
+    Ok(String),
+    Failed(String),
+    NotFound,
+}
+
+fn run(argv: &[&str]) -> Probe {
+    match Command::new(argv[0]).args(&argv[1..]).output() {
+        Ok(output) if output.status.success() => {
+            Probe::Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+        Ok(output) => Probe::Failed(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Probe::NotFound,
+        Err(e) => Probe::Failed(e.to_string()),
+    }
+}
+
+fn detect_docker_compose() -> std::result::Result<(ComposeCommand, String), RuntimeError> {
+    if let Probe::Ok(version) = run(&["docker", "compose", "version", "--short"]) {
+        return Ok((ComposeCommand::Plugin, version));
+    }
+    if let Probe::Ok(version) = run(&["docker-compose", "version", "--short"]) {
+        return Ok((ComposeCommand::Standalone, version));
+    }
+    Err(RuntimeError::ComposeMissing)
+}
+
+fn detect_podman() -> std::result::Result<ContainerRuntime, RuntimeError> {
+    let version = match run(&["podman", "version", "--format", "{{.Version}}"]) {
+        Probe::Ok(version) => version,
+        Probe::Failed(stderr) => return Err(RuntimeError::PodmanUnreachable(first_line(&stderr))),
+        Probe::NotFound => return Err(RuntimeError::NotInstalled),
+    };
+
+    match run(&["podman-compose", "version"]) {
+        Probe::Ok(output) => Ok(ContainerRuntime {
+            engine: Engine::Podman,
+            version,
+            compose: ComposeCommand::PodmanCompose,
+            compose_version: podman_compose_version(&output),
+        }),
+        _ => Err(RuntimeError::PodmanComposeMissing),
+    }
+}
+
+/// The docker CLI exits non-zero when it cannot talk to the daemon, the
+/// message tells apart a stopped daemon from a socket permission problem.
+fn classify_docker_error(stderr: &str) -> RuntimeError {
+    let lower = stderr.to_lowercase();
+    if lower.contains("permission denied") {
+        RuntimeError::PermissionDenied
+    } else {
+        RuntimeError::DaemonUnreachable(first_line(stderr))
+    }
+}
+
+/// `podman-compose version` prints several lines, keep its own version.
+fn podman_compose_version(output: &str) -> String {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("podman-compose version"))
+        .map(|v| v.trim().to_string())
+        .unwrap_or_else(|| first_line(output))
+}
+
+fn first_line(text: &str) -> String {
+    text.lines().next().unwrap_or("").trim().to_string()
+}
+
+fn check_output(output: Output, what: &str) -> Result<String> {
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} failed: {}",
+            what,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::DeployConfig;
+
+/// Credentials shared by the services of one stack. They are written to the
+/// stack's `.env` and reused on redeploys, since Postgres and MinIO keep the
+/// credentials they were initialised with in their volumes.
+#[derive(Debug, Clone)]
+pub struct StackSecrets {
+    pub postgres_password: String,
+    pub jwt_secret: String,
+    pub cookie_secret: String,
+    pub minio_root_user: String,
+    pub minio_root_password: String,
+    pub admin_email: String,
+    pub admin_password: String,
+}
+
+impl StackSecrets {
+    pub fn generate(admin_email: &str) -> Self {
+        Self {
+            postgres_password: random_secret(),
+            jwt_secret: format!("{}{}", random_secret(), random_secret()),
+            cookie_secret: format!("{}{}", random_secret(), random_secret()),
+            minio_root_user: "medusa".to_string(),
+            minio_root_password: random_secret(),
+            admin_email: admin_email.to_string(),
+            admin_password: random_secret(),
+        }
+    }
+
+    /// Reads the secrets back from a previously rendered `.env`.
+    pub fn from_env(contents: &str) -> Option<Self> {
+        let vars = parse_env(contents);
+        let get = |key: &str| vars.get(key).filter(|v| !v.is_empty()).cloned();
+
+        Some(Self {
+            postgres_password: get("POSTGRES_PASSWORD")?,
+            jwt_secret: get("JWT_SECRET")?,
+            cookie_secret: get("COOKIE_SECRET")?,
+            minio_root_user: get("MINIO_ROOT_USER")?,
+            minio_root_password: get("MINIO_ROOT_PASSWORD")?,
+            admin_email: get("MEDUSA_ADMIN_EMAIL")?,
+            admin_password: get("MEDUSA_ADMIN_PASSWORD")?,
+        })
+    }
+
+    /// Reuses the secrets in `existing` when present, otherwise generates new ones.
+    pub fn load_or_generate(existing: Option<&str>, admin_email: &str) -> Self {
+        existing
+            .and_then(Self::from_env)
+            .unwrap_or_else(|| Self::generate(admin_email))
+    }
+}
+
+/// Renders the `.env` read by compose next to `docker-compose.yml`.
+pub fn render_env(config: &DeployConfig, slug: &str, public_url: &str, secrets: &StackSecrets) -> String {
+    let mp_token = config.payment
+        .get("mercadoPagoToken")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+
+    let test_mode = config.payment
+        .get("testMode")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+
+    let vars = [
+        ("COMPOSE_PROJECT_NAME", slug.to_string()),
+        ("PUBLIC_URL", public_url.to_string()),
+        ("POSTGRES_PASSWORD", secrets.postgres_password.clone()),
+        ("JWT_SECRET", secrets.jwt_secret.clone()),
+        ("COOKIE_SECRET", secrets.cookie_secret.clone()),
+        ("MINIO_ROOT_USER", secrets.minio_root_user.clone()),
+        ("MINIO_ROOT_PASSWORD", secrets.minio_root_password.clone()),
+        ("MEDUSA_ADMIN_EMAIL", secrets.admin_email.clone()),
+        ("MEDUSA_ADMIN_PASSWORD", secrets.admin_password.clone()),
+        ("MERCADOPAGO_ACCESS_TOKEN", sanitize_value(mp_token)),
+        ("MERCADOPAGO_TEST_MODE", test_mode.to_string()),
+    ];
+
+    let mut env = String::from("# Generated by MedusaProject - keep this file private\n");
+    for (key, value) in vars {
+        env.push_str(&format!("{}={}\n", key, value));
+    }
+    env
+}
+
+pub fn parse_env(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+fn random_secret() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+/// `.env` values are unquoted, drop anything that would break the line.
+fn sanitize_value(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '#' && *c != '"' && *c != '\'')
+        .collect()
+}
@@ -1,9 +1,59 @@
 use anyhow::Result;
 use serde_json::{json, Value};
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tauri::Manager;
 
-pub async fn scan_directory(directory: &str, products: &[Value]) -> Result<Value> {
+mod download;
+mod manifest;
+mod matching;
+mod optimize;
+mod pool;
+mod process;
+mod validation;
+
+pub use download::{download_images, DownloadOptions, DownloadProgress, DownloadReport};
+pub use manifest::{ImageManifest, ManifestFile, ManifestImage};
+pub use matching::{match_images, ImageMatches, MatchOptions};
+pub use optimize::{OptimizeOptions, OptimizedImage, RenditionKind};
+pub use process::{collect_sources, process_images, stage_images, ProcessedImages};
+pub use validation::{validate, InvalidImage};
+
+use validation::ImageProblem;
+
+/// URLs of a product's images found in the download cache, with their files
+type Downloaded<'a> = Vec<(&'a str, PathBuf)>;
+
+/// Where downloaded and optimized images are kept between runs.
+pub fn cache_dir(app: &tauri::AppHandle) -> Result<PathBuf> {
+    Ok(app.path().app_cache_dir()?.join("images"))
+}
+
+/// Cache file name for `parts`: their FNV-1a hash, stable across runs and
+/// builds. Each part is preceded by its length so parts cannot run together.
+fn hash_key(parts: &[&[u8]]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        for byte in (part.len() as u64).to_le_bytes().iter().chain(part.iter()) {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+/// Matches the image files of `directory` to `products` with the rules of
+/// `options`. Images already downloaded from a product's URLs into the
+/// `cache_dir` count as its images too, so a product without local files is
+/// not missing images when it has them. Every image is decoded; the ones
+/// that cannot be used are listed under `invalid` and not counted. Decoding
+/// runs on the image worker pool.
+pub async fn scan_directory(
+    directory: &str,
+    products: &[Value],
+    cache_dir: Option<&Path>,
+    options: &MatchOptions,
+) -> Result<Value> {
     let dir_path = Path::new(directory);
     
     if !dir_path.exists() || !dir_path.is_dir() {
@@ -13,111 +63,164 @@ pub async fn scan_directory(directory: &str, products: &[Value]) -> Result<Value
     let mut products_with_images = 0;
     let mut products_without_images = 0;
     let mut total_images = 0;
+    let mut invalid_images = 0;
     let mut missing_folders = Vec::new();
-    let mut orphan_folders = Vec::new();
+    let mut duplicate_codes = Vec::new();
     let mut details = Vec::new();
     
-    // Create a map of codigo_interno -> product info
-    let mut product_map: HashMap<String, (String, String)> = HashMap::new();
+    // codigo_interno, name and downloaded images (URL and cached file) of
+    // each product, in file order. A repeated code keeps the first product,
+    // both would share the same images
+    let mut seen = HashSet::new();
+    let mut product_list: Vec<(&str, &str, Downloaded)> = Vec::new();
     for product in products {
         if let (Some(codigo), Some(nome)) = (
             product.get("codigo_interno").and_then(|v| v.as_str()),
             product.get("nome").and_then(|v| v.as_str()),
         ) {
-            product_map.insert(codigo.to_string(), (codigo.to_string(), nome.to_string()));
+            if !seen.insert(codigo) {
+                duplicate_codes.push(codigo.to_string());
+                continue;
+            }
+            let downloaded = match (cache_dir, product.get("imagens").and_then(|v| v.as_array())) {
+                (Some(cache_dir), Some(urls)) => urls
+                    .iter()
+                    .filter_map(|url| url.as_str())
+                    .filter_map(|url| Some((url, download::cached(cache_dir, url)?)))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            product_list.push((codigo, nome, downloaded));
         }
     }
     
-    // Scan subdirectories
-    if let Ok(entries) = std::fs::read_dir(dir_path) {
-        let mut found_folders = Vec::new();
-        
-        for entry in entries.flatten() {
-            if let Ok(file_type) = entry.file_type() {
-                if file_type.is_dir() {
-                    if let Some(folder_name) = entry.file_name().to_str() {
-                        found_folders.push(folder_name.to_string());
-                        
-                        if product_map.contains_key(folder_name) {
-                            // Count images in this folder
-                            let image_count = count_images(&entry.path())?;
-                            if image_count > 0 {
-                                products_with_images += 1;
-                                total_images += image_count;
-                            }
-                            
-                            let (_, product_name) = &product_map[folder_name];
-                            details.push(json!({
-                                "codigo_interno": folder_name,
-                                "productName": product_name,
-                                "imageCount": image_count,
-                            }));
-                        } else {
-                            orphan_folders.push(folder_name.to_string());
-                        }
-                    }
-                }
+    let codes: Vec<&str> = product_list.iter().map(|(codigo, _, _)| *codigo).collect();
+    let matches = match_images(dir_path, &codes, options)?;
+    let local_files = |codigo: &str| matches.products.get(codigo).map(Vec::as_slice).unwrap_or(&[]);
+
+    // Validated together on the worker pool, then taken back in the same order
+    let paths = product_list
+        .iter()
+        .flat_map(|(codigo, _, downloaded)| {
+            let local = local_files(codigo).iter().map(|image| image.path.clone());
+            local.chain(downloaded.iter().map(|(_, path)| path.clone()))
+        })
+        .collect();
+    let mut checks = validate_all(paths).await?.into_iter();
+
+    for (codigo, product_name, downloaded) in product_list {
+        let mut files = Vec::new();
+        let mut invalid = Vec::new();
+        for (image, check) in local_files(codigo).iter().zip(checks.by_ref()) {
+            match check {
+                Ok(_) => files.push(image),
+                Err((problem, message)) => invalid.push(InvalidImage { path: image.relative.clone(), problem, message }),
             }
         }
-        
-        // Find missing folders
-        for (codigo, (_, product_name)) in &product_map {
-            if !found_folders.contains(codigo) {
-                missing_folders.push(codigo.clone());
-                products_without_images += 1;
-                details.push(json!({
-                    "codigo_interno": codigo,
-                    "productName": product_name,
-                    "imageCount": 0,
-                }));
+        let mut downloaded_count = 0;
+        for ((url, _), check) in downloaded.into_iter().zip(checks.by_ref()) {
+            match check {
+                Ok(_) => downloaded_count += 1,
+                Err((problem, message)) => invalid.push(InvalidImage { path: url.to_string(), problem, message }),
             }
         }
+        invalid_images += invalid.len();
+        
+        let image_count = files.len() + downloaded_count;
+        if image_count > 0 {
+            products_with_images += 1;
+            total_images += image_count;
+        } else {
+            missing_folders.push(codigo.to_string());
+            products_without_images += 1;
+        }
+        
+        details.push(json!({
+            "codigo_interno": codigo,
+            "productName": product_name,
+            "imageCount": image_count,
+            "downloadedCount": downloaded_count,
+            "files": files,
+            "invalid": invalid,
+        }));
     }
     
     Ok(json!({
+        "directory": directory,
+        "matching": options,
         "productsWithImages": products_with_images,
         "productsWithoutImages": products_without_images,
         "totalImages": total_images,
+        "invalidImages": invalid_images,
         "missingFolders": missing_folders,
-        "orphanFolders": orphan_folders,
+        "orphanFolders": matches.orphan_folders,
+        "unmatchedFiles": matches.unmatched,
+        "deepFolders": matches.deep_folders,
+        "duplicateCodes": duplicate_codes,
         "details": details,
     }))
 }
 
-fn count_images(path: &Path) -> Result<usize> {
-    let mut count = 0;
-    let image_extensions = ["jpg", "jpeg", "png", "webp", "gif"];
-    
-    if let Ok(entries) = std::fs::read_dir(path) {
-        for entry in entries.flatten() {
-            if let Ok(file_type) = entry.file_type() {
-                if file_type.is_file() {
-                    if let Some(extension) = entry.path().extension().and_then(|e| e.to_str()) {
-                        if image_extensions.contains(&extension.to_lowercase().as_str()) {
-                            count += 1;
-                        }
-                    }
-                }
-            }
-        }
+/// Validates `paths` on the image worker pool, away from the async runtime
+/// and within the default memory budget, returning each check in the order
+/// of `paths`.
+async fn validate_all(paths: Vec<PathBuf>) -> Result<Vec<Result<(), (ImageProblem, String)>>> {
+    let total = paths.len();
+    let workers = std::thread::available_parallelism().map_or(4, usize::from);
+    let memory_limit = OptimizeOptions::default().memory_mb.saturating_mul(1024 * 1024);
+    let mut finished = pool::run(paths, workers, memory_limit, |path, budget| {
+        let _reserved = budget.reserve(pool::decode_estimate(path));
+        validate(path)
+    })?;
+
+    let mut checks: Vec<Option<_>> = (0..total).map(|_| None).collect();
+    while let Some((index, check)) = finished.recv().await {
+        checks[index] = Some(check);
     }
-    
-    Ok(count)
+    // A job without a result stopped on a panic in a decoder
+    Ok(checks
+        .into_iter()
+        .map(|check| check.unwrap_or_else(|| Err((ImageProblem::Corrupt, "Image could not be decoded".to_string()))))
+        .collect())
 }
 
-pub async fn optimize_image(input_path: &Path, output_path: &Path) -> Result<()> {
-    // Load image
-    let img = image::open(input_path)?;
-    
-    // Resize if larger than 1200px width
-    let img = if img.width() > 1200 {
-        img.resize(1200, (1200.0 * img.height() as f32 / img.width() as f32) as u32, image::imageops::FilterType::Lanczos3)
-    } else {
-        img
-    };
-    
-    // Save optimized image
-    img.save(output_path)?;
-    
-    Ok(())
+/// Matching options saved with a scan result, the defaults for older ones.
+pub fn match_options(mapping: &Value) -> MatchOptions {
+    mapping
+        .get("matching")
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[tokio::test]
+    async fn scan_validates_every_image_in_product_order() {
+        let dir = std::env::temp_dir().join(format!("scan-{}", uuid::Uuid::new_v4()));
+        for codigo in ["A1", "B2"] {
+            std::fs::create_dir_all(dir.join(codigo)).unwrap();
+        }
+        RgbImage::from_pixel(300, 300, Rgb([200, 10, 10])).save(dir.join("A1/1.png")).unwrap();
+        std::fs::write(dir.join("A1/2.jpg"), b"<html>").unwrap();
+        RgbImage::from_pixel(50, 50, Rgb([10, 10, 200])).save(dir.join("B2/1.png")).unwrap();
+        RgbImage::from_pixel(400, 300, Rgb([10, 200, 10])).save(dir.join("B2/2.png")).unwrap();
+        let products = vec![
+            json!({"codigo_interno": "A1", "nome": "Camiseta"}),
+            json!({"codigo_interno": "B2", "nome": "Boné"}),
+        ];
+
+        let scan = scan_directory(dir.to_str().unwrap(), &products, None, &MatchOptions::default()).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(scan["totalImages"], 2);
+        assert_eq!(scan["invalidImages"], 2);
+        let details = scan["details"].as_array().unwrap();
+        assert_eq!(details[0]["imageCount"], 1);
+        assert_eq!(details[0]["invalid"][0]["problem"], "unsupported");
+        assert_eq!(details[1]["imageCount"], 1);
+        assert_eq!(details[1]["invalid"][0]["problem"], "too-small");
+    }
 }
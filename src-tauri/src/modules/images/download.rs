@@ -0,0 +1,448 @@
+//! Product images given as URLs in the `imagens` column. They are downloaded
+//! once into a local cache, keyed by URL, and from there go through the same
+//! processing as the images of the product folders.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use super::hash_key;
+use crate::modules::csv_parser::Product;
+
+/// Accepted content types and the extension their cached file gets
+const CONTENT_TYPES: [(&str, &str); 5] = [
+    ("image/jpeg", "jpg"),
+    ("image/jpg", "jpg"),
+    ("image/png", "png"),
+    ("image/webp", "webp"),
+    ("image/gif", "gif"),
+];
+const CACHED_EXTENSIONS: [&str; 4] = ["jpg", "png", "webp", "gif"];
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+const DOWNLOADS_DIR: &str = "downloads";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DownloadOptions {
+    /// Downloads running at the same time
+    pub concurrency: usize,
+    #[serde(rename = "maxBytes")]
+    pub max_bytes: u64,
+    /// Further attempts after a network error, a 429 or a 5xx response
+    pub retries: u32,
+    #[serde(rename = "timeoutSecs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            max_bytes: 20 * 1024 * 1024,
+            retries: 3,
+            timeout_secs: 30,
+        }
+    }
+}
+
+/// Emitted as each URL finishes, from the cache or the network.
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadProgress {
+    pub current: usize,
+    pub total: usize,
+    pub url: String,
+    pub cached: bool,
+    pub ok: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadFailure {
+    pub url: String,
+    /// Products using the URL
+    pub codigos: Vec<String>,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DownloadReport {
+    pub downloaded: usize,
+    pub cached: usize,
+    /// Bytes downloaded, cached files not included
+    pub bytes: u64,
+    pub failed: Vec<DownloadFailure>,
+    /// Local file of every URL available in the cache
+    #[serde(skip)]
+    pub files: HashMap<String, PathBuf>,
+}
+
+impl DownloadReport {
+    /// Local copies of the product's image URLs, in the order of the column.
+    pub fn files_of(&self, product: &Product) -> Vec<PathBuf> {
+        product.imagens.iter().filter_map(|url| self.files.get(url).cloned()).collect()
+    }
+
+    /// Image URLs of the product that could not be downloaded, which are
+    /// still linked as they are.
+    pub fn remote_of(&self, product: &Product) -> Vec<String> {
+        product.imagens.iter().filter(|url| !self.files.contains_key(*url)).cloned().collect()
+    }
+}
+
+/// Downloads the image URLs of `products` into the `downloads` folder of
+/// `cache_dir`, each URL once even when several products use it. URLs
+/// already in the cache are not requested again. A URL that fails is
+/// reported and the rest carry on.
+pub async fn download_images(
+    products: &[Product],
+    cache_dir: &Path,
+    options: &DownloadOptions,
+    mut on_progress: impl FnMut(&DownloadProgress),
+) -> Result<DownloadReport> {
+    let downloads_dir = &cache_dir.join(DOWNLOADS_DIR);
+    std::fs::create_dir_all(downloads_dir)
+        .with_context(|| format!("Failed to create {}", downloads_dir.display()))?;
+
+    let mut urls: Vec<(String, Vec<String>)> = Vec::new();
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for product in products {
+        for url in &product.imagens {
+            match seen.get(url.as_str()) {
+                Some(&index) => urls[index].1.push(product.codigo_interno.clone()),
+                None => {
+                    seen.insert(url, urls.len());
+                    urls.push((url.clone(), vec![product.codigo_interno.clone()]));
+                }
+            }
+        }
+    }
+
+    let http = reqwest::Client::builder()
+        .timeout(Duration::from_secs(options.timeout_secs))
+        .build()
+        .context("Failed to create HTTP client")?;
+    let limit = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let mut report = DownloadReport::default();
+    let mut tasks = JoinSet::new();
+    let total = urls.len();
+    let mut current = 0;
+
+    for (index, (url, _)) in urls.iter().enumerate() {
+        if let Some(path) = cached(cache_dir, url) {
+            report.cached += 1;
+            report.files.insert(url.clone(), path);
+            current += 1;
+            on_progress(&DownloadProgress { current, total, url: url.clone(), cached: true, ok: true });
+            continue;
+        }
+
+        let (http, limit, options) = (http.clone(), limit.clone(), options.clone());
+        let (url, stem) = (url.clone(), downloads_dir.join(url_key(url)));
+        tasks.spawn(async move {
+            let _permit = limit.acquire_owned().await;
+            (index, fetch(&http, &url, &stem, &options).await)
+        });
+    }
+
+    while let Some(joined) = tasks.join_next().await {
+        let (index, result) = joined.context("Image download task failed")?;
+        let (url, codigos) = &urls[index];
+        current += 1;
+        on_progress(&DownloadProgress { current, total, url: url.clone(), cached: false, ok: result.is_ok() });
+        match result {
+            Ok((path, bytes)) => {
+                report.downloaded += 1;
+                report.bytes += bytes;
+                report.files.insert(url.clone(), path);
+            }
+            Err(e) => report.failed.push(DownloadFailure {
+                url: url.clone(),
+                codigos: codigos.clone(),
+                error: format!("{:#}", e),
+            }),
+        }
+    }
+
+    report.failed.sort_by(|a, b| a.url.cmp(&b.url));
+    Ok(report)
+}
+
+/// The cached file of `url`, whatever image type it turned out to be.
+pub fn cached(cache_dir: &Path, url: &str) -> Option<PathBuf> {
+    let key = url_key(url);
+    CACHED_EXTENSIONS
+        .iter()
+        .map(|extension| cache_dir.join(DOWNLOADS_DIR).join(format!("{}.{}", key, extension)))
+        .find(|path| path.is_file())
+}
+
+enum Failure {
+    /// Network errors, 429 and 5xx responses, worth another attempt
+    Transient(anyhow::Error),
+    Permanent(anyhow::Error),
+}
+
+/// Downloads `url` to `stem` plus the extension of its content type,
+/// retrying transient failures with an increasing delay.
+async fn fetch(http: &reqwest::Client, url: &str, stem: &Path, options: &DownloadOptions) -> Result<(PathBuf, u64)> {
+    let mut attempt = 0;
+    loop {
+        match fetch_once(http, url, stem, options.max_bytes).await {
+            Ok(done) => return Ok(done),
+            Err(Failure::Transient(_)) if attempt < options.retries => {
+                tokio::time::sleep(RETRY_DELAY * 2u32.pow(attempt)).await;
+                attempt += 1;
+            }
+            Err(Failure::Transient(e)) if attempt > 0 => {
+                return Err(e.context(format!("Gave up after {} attempts", attempt + 1)));
+            }
+            Err(Failure::Transient(e)) | Err(Failure::Permanent(e)) => return Err(e),
+        }
+    }
+}
+
+async fn fetch_once(http: &reqwest::Client, url: &str, stem: &Path, max_bytes: u64) -> std::result::Result<(PathBuf, u64), Failure> {
+    let mut response = http
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| Failure::Transient(e.into()))?;
+
+    let status = response.status();
+    if status.as_u16() == 429 || status.is_server_error() {
+        return Err(Failure::Transient(anyhow::anyhow!("HTTP {}", status)));
+    }
+    if !status.is_success() {
+        return Err(Failure::Permanent(anyhow::anyhow!("HTTP {}", status)));
+    }
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    let extension = extension_for(&content_type, url).ok_or_else(|| {
+        Failure::Permanent(anyhow::anyhow!("Not a supported image (content type '{}')", content_type))
+    })?;
+    if let Some(length) = response.content_length().filter(|&length| length > max_bytes) {
+        return Err(Failure::Permanent(anyhow::anyhow!(
+            "Image has {} bytes, more than the {} allowed",
+            length,
+            max_bytes
+        )));
+    }
+
+    let partial = stem.with_extension("part");
+    let mut file = tokio::fs::File::create(&partial)
+        .await
+        .map_err(|e| Failure::Permanent(e.into()))?;
+    let mut bytes = 0;
+    let written = async {
+        while let Some(chunk) = response.chunk().await.map_err(|e| Failure::Transient(e.into()))? {
+            bytes += chunk.len() as u64;
+            if bytes > max_bytes {
+                return Err(Failure::Permanent(anyhow::anyhow!("Image is larger than the {} bytes allowed", max_bytes)));
+            }
+            file.write_all(&chunk).await.map_err(|e| Failure::Permanent(e.into()))?;
+        }
+        file.flush().await.map_err(|e| Failure::Permanent(e.into()))
+    }
+    .await;
+    drop(file);
+    if let Err(failure) = written {
+        let _ = std::fs::remove_file(&partial);
+        return Err(failure);
+    }
+
+    // Renamed only when complete, so an interrupted download is never cached
+    let path = stem.with_extension(extension);
+    std::fs::rename(&partial, &path).map_err(|e| Failure::Permanent(e.into()))?;
+    Ok((path, bytes))
+}
+
+/// Extension for the response's content type. Servers that send no type or
+/// a generic binary one are trusted when the URL ends in an image extension.
+fn extension_for(content_type: &str, url: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+    if let Some((_, extension)) = CONTENT_TYPES.iter().find(|(t, _)| *t == mime) {
+        return Some(extension);
+    }
+    if !matches!(mime.as_str(), "" | "application/octet-stream" | "binary/octet-stream") {
+        return None;
+    }
+
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let extension = path.rsplit_once('.')?.1.to_lowercase();
+    match extension.as_str() {
+        "jpg" | "jpeg" => Some("jpg"),
+        "png" => Some("png"),
+        "webp" => Some("webp"),
+        "gif" => Some("gif"),
+        _ => None,
+    }
+}
+
+fn url_key(url: &str) -> String {
+    hash_key(&[url.as_bytes()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    /// Status line, content type and body the stub sends for a path on its
+    /// `n`th request (from 1). Without `length` the body is sent without a
+    /// Content-Length, ending when the connection closes.
+    struct Reply {
+        status: &'static str,
+        content_type: &'static str,
+        body: Vec<u8>,
+        length: bool,
+    }
+
+    type Hits = Arc<Mutex<HashMap<String, usize>>>;
+
+    /// Serves `reply` on a local port, counting the requests of each path.
+    async fn stub(reply: fn(&str, usize) -> Reply) -> (String, Hits) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let hits: Hits = Arc::default();
+        let counter = hits.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else { return };
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match socket.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&request);
+                let path = request.split(' ').nth(1).unwrap_or("/").to_string();
+                let n = {
+                    let mut hits = counter.lock().unwrap();
+                    let n = hits.entry(path.clone()).or_default();
+                    *n += 1;
+                    *n
+                };
+
+                let reply = reply(&path, n);
+                let mut head = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: {}\r\nConnection: close\r\n",
+                    reply.status, reply.content_type
+                );
+                if reply.length {
+                    head.push_str(&format!("Content-Length: {}\r\n", reply.body.len()));
+                }
+                head.push_str("\r\n");
+                let _ = socket.write_all(head.as_bytes()).await;
+                let _ = socket.write_all(&reply.body).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+        (base, hits)
+    }
+
+    fn image(body: usize) -> Reply {
+        Reply { status: "200 OK", content_type: "image/jpeg", body: vec![0xFF; body], length: true }
+    }
+
+    fn unavailable() -> Reply {
+        Reply { status: "503 Service Unavailable", content_type: "text/plain", body: Vec::new(), length: true }
+    }
+
+    fn cache_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("downloads-{}", uuid::Uuid::new_v4()))
+    }
+
+    async fn download(urls: &[String], cache_dir: &Path, options: &DownloadOptions) -> DownloadReport {
+        let product = Product { imagens: urls.to_vec(), ..Product::sample("CAM-01") };
+        download_images(&[product], cache_dir, options, |_| {}).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn rejects_content_that_is_not_an_image() {
+        let (base, _) = stub(|path, _| match path {
+            "/page.jpg" => Reply { content_type: "text/html", body: b"<html>".to_vec(), ..image(0) },
+            _ => image(10),
+        })
+        .await;
+        let cache_dir = cache_dir();
+        let urls = [format!("{}/page.jpg", base), format!("{}/photo", base)];
+        let report = download(&urls, &cache_dir, &DownloadOptions::default()).await;
+
+        assert_eq!(report.downloaded, 1);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].url, urls[0]);
+        assert!(report.failed[0].error.contains("text/html"), "{}", report.failed[0].error);
+        assert_eq!(report.files[&urls[1]].extension().unwrap(), "jpg");
+        std::fs::remove_dir_all(cache_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stops_at_max_bytes() {
+        let (base, _) = stub(|path, _| match path {
+            "/declared" => image(2000),
+            _ => Reply { length: false, ..image(2000) },
+        })
+        .await;
+        let cache_dir = cache_dir();
+        let urls = [format!("{}/declared", base), format!("{}/streamed", base)];
+        let options = DownloadOptions { max_bytes: 1000, ..Default::default() };
+        let report = download(&urls, &cache_dir, &options).await;
+
+        assert_eq!(report.downloaded, 0);
+        assert_eq!(report.failed.len(), 2);
+        assert!(report.failed.iter().all(|f| f.error.contains("1000")));
+        // Nothing partial is left behind to be taken as cached
+        assert_eq!(std::fs::read_dir(cache_dir.join(DOWNLOADS_DIR)).unwrap().count(), 0);
+        std::fs::remove_dir_all(cache_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn retries_service_unavailable() {
+        let (base, hits) = stub(|path, n| match (path, n) {
+            ("/flaky", 1 | 2) => unavailable(),
+            ("/flaky", _) => image(10),
+            _ => unavailable(),
+        })
+        .await;
+        let cache_dir = cache_dir();
+        let urls = [format!("{}/flaky", base), format!("{}/down", base)];
+        let options = DownloadOptions { retries: 2, ..Default::default() };
+        let report = download(&urls, &cache_dir, &options).await;
+
+        assert_eq!(report.downloaded, 1);
+        assert_eq!(hits.lock().unwrap()["/flaky"], 3);
+        assert_eq!(hits.lock().unwrap()["/down"], 3);
+        assert_eq!(report.failed[0].url, urls[1]);
+        assert!(report.failed[0].error.contains("Gave up after 3 attempts"), "{}", report.failed[0].error);
+        std::fs::remove_dir_all(cache_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cached_urls_are_not_requested_again() {
+        let (base, hits) = stub(|_, _| image(10)).await;
+        let cache_dir = cache_dir();
+        let urls = [format!("{}/a.jpg", base), format!("{}/b.jpg", base)];
+        let first = download(&urls, &cache_dir, &DownloadOptions::default()).await;
+        assert_eq!((first.downloaded, first.cached, first.bytes), (2, 0, 20));
+
+        let second = download(&urls, &cache_dir, &DownloadOptions::default()).await;
+        assert_eq!((second.downloaded, second.cached, second.bytes), (0, 2, 0));
+        assert_eq!(second.files, first.files);
+        assert_eq!(hits.lock().unwrap().values().sum::<usize>(), 2);
+        std::fs::remove_dir_all(cache_dir).unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+//! The renditions staged for each product, saved as `manifest.json` next to
+//! the images. Paths are relative to the images bucket until `with_base`
+//! turns them into the URLs the store links.
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Characters escaped in a path segment, as the URL standard does
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestFile {
+    pub url: String,
+    /// The JPEG, when `url` is a WebP
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<String>,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestImage {
+    /// Full size, for zoom
+    pub full: ManifestFile,
+    /// Smaller widths, from the smallest
+    pub sizes: Vec<ManifestFile>,
+    /// Square, for listings
+    pub thumbnail: ManifestFile,
+}
+
+/// Images of each product code, in the order of its sources.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ImageManifest(pub BTreeMap<String, Vec<ManifestImage>>);
+
+impl ImageManifest {
+    pub fn images_of(&self, codigo: &str) -> &[ManifestImage] {
+        self.0.get(codigo).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// The manifest with every path prefixed by `base`, the URL of the
+    /// images bucket, and percent-encoded.
+    pub fn with_base(&self, base: &str) -> ImageManifest {
+        let base = base.trim_end_matches('/');
+        let url = |path: &str| {
+            let segments: Vec<String> =
+                path.split('/').map(|segment| utf8_percent_encode(segment, SEGMENT).to_string()).collect();
+            format!("{}/{}", base, segments.join("/"))
+        };
+        let resolve = |file: &ManifestFile| ManifestFile {
+            url: url(&file.url),
+            fallback: file.fallback.as_deref().map(url),
+            ..file.clone()
+        };
+        ImageManifest(
+            self.0
+                .iter()
+                .map(|(codigo, images)| {
+                    let images = images
+                        .iter()
+                        .map(|image| ManifestImage {
+                            full: resolve(&image.full),
+                            sizes: image.sizes.iter().map(resolve).collect(),
+                            thumbnail: resolve(&image.thumbnail),
+                        })
+                        .collect();
+                    (codigo.clone(), images)
+                })
+                .collect(),
+        )
+    }
+
+    pub fn image_count(&self) -> usize {
+        self.0.values().map(Vec::len).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(url: &str) -> ManifestFile {
+        ManifestFile { url: url.to_string(), fallback: Some(url.replace(".webp", ".jpg")), width: 400, height: 300 }
+    }
+
+    #[test]
+    fn urls_are_prefixed_and_percent_encoded() {
+        let image = ManifestImage { full: file("cam-01/1.webp"), sizes: Vec::new(), thumbnail: file("a b/1#2.webp") };
+        let manifest = ImageManifest([("CAM 01".to_string(), vec![image])].into_iter().collect());
+
+        let resolved = manifest.with_base("http://localhost:9002/products/");
+        let image = &resolved.images_of("CAM 01")[0];
+        assert_eq!(image.full.url, "http://localhost:9002/products/cam-01/1.webp");
+        assert_eq!(image.full.fallback.as_deref(), Some("http://localhost:9002/products/cam-01/1.jpg"));
+        assert_eq!(image.thumbnail.url, "http://localhost:9002/products/a%20b/1%232.webp");
+    }
+}
@@ -0,0 +1,415 @@
+//! Which product each image file belongs to. Files under the images root are
+//! matched by rules tried in order: a folder named after the product
+//! anywhere in the path, a file name starting with the code, or a glob
+//! pattern capturing the code. Codes are compared ignoring case, like the
+//! file systems of most photographers.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+const IMAGE_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "webp", "gif"];
+const CODE_PLACEHOLDER: &str = "{codigo}";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum MatchRule {
+    /// `123/foto.jpg`, or `Marca/123/foto.jpg` with a deeper search
+    Folder,
+    /// `123.jpg`, `123_1.jpg` or `123-frente.png`; the code is followed by
+    /// one of `separators` or is the whole name
+    Prefix {
+        #[serde(default = "default_separators")]
+        separators: String,
+    },
+    /// Glob over the path from the root, where `*` and `?` stay within a
+    /// folder, `**` crosses folders and `{codigo}` captures the code, e.g.
+    /// `**/{codigo}_*.jpg`
+    Pattern { pattern: String },
+}
+
+fn default_separators() -> String {
+    "_- .".to_string()
+}
+
+impl MatchRule {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MatchRule::Folder => "folder",
+            MatchRule::Prefix { .. } => "prefix",
+            MatchRule::Pattern { .. } => "pattern",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatchOptions {
+    pub rules: Vec<MatchRule>,
+    /// Folder levels searched below the root; 1 reaches `root/123/foto.jpg`
+    #[serde(rename = "maxDepth")]
+    pub max_depth: usize,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        Self {
+            rules: vec![MatchRule::Folder],
+            max_depth: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchedImage {
+    #[serde(skip)]
+    pub path: PathBuf,
+    /// Path from the root, with `/` separators
+    #[serde(rename = "path")]
+    pub relative: String,
+    pub rule: &'static str,
+}
+
+#[derive(Debug, Default)]
+pub struct ImageMatches {
+    /// Images of each product code as given, sorted by path
+    pub products: HashMap<String, Vec<MatchedImage>>,
+    /// Image files no rule matched
+    pub unmatched: Vec<String>,
+    /// First-level folders without any matched image
+    pub orphan_folders: Vec<String>,
+    /// Folders below `max_depth` that were not searched
+    pub deep_folders: Vec<String>,
+}
+
+impl ImageMatches {
+    pub fn files_of(&self, codigo: &str) -> Vec<PathBuf> {
+        self.products
+            .get(codigo)
+            .map(|images| images.iter().map(|image| image.path.clone()).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Walks `root` down to `options.max_depth` and assigns each image file to
+/// the product of the first rule that yields a known code.
+pub fn match_images(root: &Path, codes: &[&str], options: &MatchOptions) -> Result<ImageMatches> {
+    let patterns = options
+        .rules
+        .iter()
+        .map(|rule| match rule {
+            MatchRule::Pattern { pattern } => Glob::parse(pattern).map(Some),
+            _ => Ok(None),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let by_lowercase: HashMap<String, &str> = codes.iter().map(|code| (code.to_lowercase(), *code)).collect();
+
+    let mut files = Vec::new();
+    let mut matches = ImageMatches::default();
+    walk(root, &mut Vec::new(), options.max_depth, &mut files, &mut matches.deep_folders)
+        .with_context(|| format!("Failed to read {}", root.display()))?;
+    files.sort();
+
+    let mut first_levels = HashSet::new();
+    let mut matched_first_levels = HashSet::new();
+    for (components, path) in files {
+        let relative = components.join("/");
+        if components.len() > 1 {
+            first_levels.insert(components[0].clone());
+        }
+        let found = options.rules.iter().zip(&patterns).find_map(|(rule, glob)| {
+            let key = match (rule, glob) {
+                (MatchRule::Folder, _) => folder_code(&components, &by_lowercase),
+                (MatchRule::Prefix { separators }, _) => prefix_code(&components, separators, &by_lowercase),
+                (MatchRule::Pattern { .. }, Some(glob)) => glob.capture(&relative.to_lowercase()),
+                (MatchRule::Pattern { .. }, None) => None,
+            }?;
+            by_lowercase.get(&key).map(|code| (*code, rule.name()))
+        });
+
+        match found {
+            Some((code, rule)) => {
+                if components.len() > 1 {
+                    matched_first_levels.insert(components[0].clone());
+                }
+                matches.products.entry(code.to_string()).or_default().push(MatchedImage { path, relative, rule });
+            }
+            None => matches.unmatched.push(relative),
+        }
+    }
+
+    matches.orphan_folders = first_levels.difference(&matched_first_levels).cloned().collect();
+    matches.orphan_folders.sort();
+    matches.deep_folders.sort();
+    Ok(matches)
+}
+
+/// Image files below `dir` as their path components from the root. Hidden
+/// files and folders are skipped.
+fn walk(
+    dir: &Path,
+    components: &mut Vec<String>,
+    max_depth: usize,
+    files: &mut Vec<(Vec<String>, PathBuf)>,
+    deep_folders: &mut Vec<String>,
+) -> Result<()> {
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else { continue };
+        if name.starts_with('.') {
+            continue;
+        }
+        let Ok(file_type) = entry.file_type() else { continue };
+        components.push(name);
+        if file_type.is_dir() {
+            if components.len() > max_depth {
+                deep_folders.push(components.join("/"));
+            } else {
+                walk(&entry.path(), components, max_depth, files, deep_folders)?;
+            }
+        } else if file_type.is_file() && is_image(&entry.path()) {
+            files.push((components.clone(), entry.path()));
+        }
+        components.pop();
+    }
+    Ok(())
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+/// The nearest folder of the file named after a product.
+fn folder_code(components: &[String], codes: &HashMap<String, &str>) -> Option<String> {
+    components[..components.len() - 1]
+        .iter()
+        .rev()
+        .map(|folder| folder.to_lowercase())
+        .find(|folder| codes.contains_key(folder))
+}
+
+/// The longest product code the file name starts with, followed by a
+/// separator or the extension, so `12-3_1.jpg` goes to `12-3` over `12`.
+fn prefix_code(components: &[String], separators: &str, codes: &HashMap<String, &str>) -> Option<String> {
+    let name = components.last()?.to_lowercase();
+    let stem = Path::new(&name).file_stem()?.to_str()?;
+    stem.char_indices()
+        .filter(|(_, c)| separators.contains(*c))
+        .map(|(i, _)| &stem[..i])
+        .chain(std::iter::once(stem))
+        .filter(|candidate| codes.contains_key(*candidate))
+        .max_by_key(|candidate| candidate.len())
+        .map(str::to_string)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    /// `?`, one character within a folder
+    Any,
+    /// `*`, any characters within a folder
+    Star,
+    /// `**`, any characters across folders
+    Globstar,
+    /// `**/`, nothing or whole folders
+    Folders,
+    Capture,
+}
+
+/// A lowercase glob with exactly one `{codigo}` capture.
+#[derive(Debug)]
+struct Glob(Vec<Token>);
+
+impl Glob {
+    fn parse(pattern: &str) -> Result<Self> {
+        if pattern.matches(CODE_PLACEHOLDER).count() != 1 {
+            anyhow::bail!("Pattern '{}' must contain {} exactly once", pattern, CODE_PLACEHOLDER);
+        }
+        let pattern = pattern.trim_start_matches("./").replace('\\', "/").to_lowercase();
+        let mut tokens = Vec::new();
+        let mut rest = pattern.as_str();
+        while let Some(c) = rest.chars().next() {
+            let (token, length) = if rest.starts_with(CODE_PLACEHOLDER) {
+                (Token::Capture, CODE_PLACEHOLDER.len())
+            } else if rest.starts_with("**/") {
+                (Token::Folders, 3)
+            } else if rest.starts_with("**") {
+                (Token::Globstar, 2)
+            } else {
+                let token = match c {
+                    '*' => Token::Star,
+                    '?' => Token::Any,
+                    c => Token::Char(c),
+                };
+                (token, c.len_utf8())
+            };
+            tokens.push(token);
+            rest = &rest[length..];
+        }
+        Ok(Self(tokens))
+    }
+
+    /// The captured code when `path` matches the whole pattern.
+    fn capture(&self, path: &str) -> Option<String> {
+        let chars: Vec<char> = path.chars().collect();
+        let mut capture = None;
+        if matches(&self.0, &chars, 0, &mut capture) {
+            capture.map(|(start, end)| chars[start..end].iter().collect())
+        } else {
+            None
+        }
+    }
+}
+
+/// Backtracking match of `tokens` against `path[at..]`, recording the
+/// capture of the first successful match.
+fn matches(tokens: &[Token], path: &[char], at: usize, capture: &mut Option<(usize, usize)>) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return at == path.len();
+    };
+    // Characters left before the next `/`
+    let in_folder = path[at..].iter().take_while(|c| **c != '/').count();
+    match token {
+        Token::Char(c) => path.get(at) == Some(c) && matches(rest, path, at + 1, capture),
+        Token::Any => in_folder > 0 && matches(rest, path, at + 1, capture),
+        Token::Star => (0..=in_folder).any(|n| matches(rest, path, at + n, capture)),
+        Token::Globstar => (at..=path.len()).any(|next| matches(rest, path, next, capture)),
+        Token::Folders => (at..=path.len())
+            .filter(|&next| next == at || path[next - 1] == '/')
+            .any(|next| matches(rest, path, next, capture)),
+        Token::Capture => (1..=in_folder).any(|n| {
+            *capture = Some((at, at + n));
+            matches(rest, path, at + n, capture)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODES: [&str; 4] = ["123", "12", "12-3", "ABC"];
+
+    /// Code and rule a file matched
+    type Found = Option<(String, &'static str)>;
+
+    fn prefix() -> MatchRule {
+        MatchRule::Prefix { separators: default_separators() }
+    }
+
+    fn pattern(pattern: &str) -> MatchRule {
+        MatchRule::Pattern { pattern: pattern.to_string() }
+    }
+
+    /// Matches `files`, created empty under a fresh root, returning the code
+    /// and rule of each, or `None` when unmatched, plus the folders left
+    /// unsearched.
+    fn run(files: &[&str], rules: Vec<MatchRule>, max_depth: usize) -> (Vec<Found>, Vec<String>) {
+        let root = std::env::temp_dir().join(format!("matching-{}", uuid::Uuid::new_v4()));
+        for file in files {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"").unwrap();
+        }
+        let matches = match_images(&root, &CODES, &MatchOptions { rules, max_depth }).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let found = files
+            .iter()
+            .map(|file| {
+                matches.products.iter().find_map(|(code, images)| {
+                    let image = images.iter().find(|image| image.relative == *file)?;
+                    Some((code.clone(), image.rule))
+                })
+            })
+            .collect();
+        (found, matches.deep_folders)
+    }
+
+    fn found(code: &str, rule: &'static str) -> Found {
+        Some((code.to_string(), rule))
+    }
+
+    #[test]
+    fn prefix_takes_the_code_before_a_separator() {
+        let cases = [
+            ("123.jpg", found("123", "prefix")),
+            ("123_1.jpg", found("123", "prefix")),
+            ("123-frente.png", found("123", "prefix")),
+            ("123 costas.webp", found("123", "prefix")),
+            ("12-3_1.jpg", found("12-3", "prefix")),
+            ("12_1.jpg", found("12", "prefix")),
+            ("1234.jpg", None),
+            ("abc_1.JPG", found("ABC", "prefix")),
+            ("123.txt", None),
+        ];
+        let files: Vec<&str> = cases.iter().map(|(file, _)| *file).collect();
+        let (matched, _) = run(&files, vec![prefix()], 1);
+        for ((file, expected), matched) in cases.iter().zip(matched) {
+            assert_eq!(&matched, expected, "{}", file);
+        }
+    }
+
+    #[test]
+    fn folders_are_searched_down_to_max_depth() {
+        let files = ["123/x.jpg", "Marca/123/x.jpg", "Marca/abc/y.png"];
+
+        let (matched, deep) = run(&files, vec![MatchRule::Folder], 1);
+        assert_eq!(matched, vec![found("123", "folder"), None, None]);
+        assert_eq!(deep, vec!["Marca/123", "Marca/abc"]);
+
+        let (matched, deep) = run(&files, vec![MatchRule::Folder], 2);
+        assert_eq!(matched, vec![found("123", "folder"), found("123", "folder"), found("ABC", "folder")]);
+        assert!(deep.is_empty());
+    }
+
+    #[test]
+    fn patterns_capture_the_code() {
+        let cases = [
+            ("123_1.jpg", found("123", "pattern")),
+            ("fotos/123_2.jpg", found("123", "pattern")),
+            ("fotos/2024/ABC_frente.jpg", found("ABC", "pattern")),
+            ("fotos/123.jpg", None),
+            ("fotos/123_1.png", None),
+            ("fotos/999_1.jpg", None),
+        ];
+        let files: Vec<&str> = cases.iter().map(|(file, _)| *file).collect();
+        let (matched, _) = run(&files, vec![pattern("**/{codigo}_*.jpg")], 3);
+        for ((file, expected), matched) in cases.iter().zip(matched) {
+            assert_eq!(&matched, expected, "{}", file);
+        }
+    }
+
+    #[test]
+    fn single_star_stays_within_a_folder() {
+        let glob = Glob::parse("*/{codigo}.jpg").unwrap();
+        assert_eq!(glob.capture("fotos/123.jpg"), Some("123".to_string()));
+        assert_eq!(glob.capture("a/b/123.jpg"), None);
+        assert_eq!(Glob::parse("{codigo}-?.jpg").unwrap().capture("123-1.jpg"), Some("123".to_string()));
+        assert_eq!(Glob::parse("{codigo}-?.jpg").unwrap().capture("123-10.jpg"), None);
+    }
+
+    #[test]
+    fn rules_are_tried_in_order() {
+        // In folder 123 but named after ABC
+        let files = ["123/abc_1.jpg"];
+        let (matched, _) = run(&files, vec![MatchRule::Folder, prefix()], 1);
+        assert_eq!(matched, vec![found("123", "folder")]);
+        let (matched, _) = run(&files, vec![prefix(), MatchRule::Folder], 1);
+        assert_eq!(matched, vec![found("ABC", "prefix")]);
+        // A rule that finds no known code falls through to the next
+        let (matched, _) = run(&["outros/abc_1.jpg"], vec![MatchRule::Folder, prefix()], 1);
+        assert_eq!(matched, vec![found("ABC", "prefix")]);
+    }
+
+    #[test]
+    fn patterns_need_exactly_one_code() {
+        for bad in ["*.jpg", "{codigo}/{codigo}.jpg"] {
+            let options = MatchOptions { rules: vec![pattern(bad)], max_depth: 1 };
+            let error = match_images(&std::env::temp_dir(), &CODES, &options).unwrap_err();
+            assert!(error.to_string().contains("exactly once"), "{}", bad);
+        }
+    }
+}
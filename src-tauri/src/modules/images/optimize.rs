@@ -0,0 +1,314 @@
+//! Turns a product photo into what the store serves: upright, without
+//! metadata, in sRGB, as WebP with a JPEG fallback, in a full size for zoom,
+//! smaller widths for listings and product pages, and a square thumbnail.
+//! Results are cached by the content of the source and the options, so
+//! unchanged images are not processed again on the next deploy.
+
+use anyhow::{Context, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageReader, Rgba, RgbaImage};
+use moxcms::{ColorProfile, DataColorSpace, Layout, TransformOptions};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use super::hash_key;
+
+/// Bumped when the output of the pipeline changes, so cached files are
+/// processed again
+const PIPELINE_VERSION: &str = "3";
+const OPTIMIZED_DIR: &str = "optimized";
+/// Written last in a cache entry, listing its renditions
+const ENTRY_FILE: &str = "renditions.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OptimizeOptions {
+    /// Width of the full size image, for zoom. Wider images are scaled
+    /// down, keeping their proportions
+    #[serde(rename = "maxWidth")]
+    pub max_width: u32,
+    /// Smaller copies for listings and product pages. Widths from the full
+    /// size up are left out, images are never scaled up
+    pub widths: Vec<u32>,
+    /// Side of the square thumbnail, cropped from the center
+    pub thumbnail: u32,
+    /// JPEG and WebP quality, 1 to 100
+    pub quality: u8,
+    /// Images processed at the same time, 0 for one per CPU core
+    pub workers: usize,
+    /// Memory the images being decoded may take together, in megabytes
+    #[serde(rename = "memoryMb")]
+    pub memory_mb: u64,
+}
+
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        Self {
+            max_width: 1600,
+            widths: vec![200, 400, 800, 1600],
+            thumbnail: 300,
+            quality: 82,
+            workers: 0,
+            memory_mb: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RenditionKind {
+    Full,
+    Width,
+    Thumbnail,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rendition {
+    pub kind: RenditionKind,
+    pub width: u32,
+    pub height: u32,
+    /// Left out when it would be larger than the JPEG
+    pub webp: bool,
+    /// Size of the file served, the WebP when there is one
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct OptimizedImage {
+    /// Cache entry holding the files of every rendition
+    pub dir: PathBuf,
+    /// Full size first, then the widths from the smallest, then the thumbnail
+    pub renditions: Vec<Rendition>,
+    /// Taken from the cache of an earlier run
+    pub cached: bool,
+    pub source_bytes: u64,
+    /// Size of the full size file served
+    pub bytes: u64,
+}
+
+impl Rendition {
+    /// Ending of the file name, in the cache and in the bucket after the
+    /// image number, e.g. `1-400.webp`
+    pub fn suffix(&self) -> String {
+        match self.kind {
+            RenditionKind::Full => String::new(),
+            RenditionKind::Width => format!("-{}", self.width),
+            RenditionKind::Thumbnail => "-thumb".to_string(),
+        }
+    }
+}
+
+impl OptimizedImage {
+    pub fn jpeg(&self, rendition: &Rendition) -> PathBuf {
+        self.dir.join(format!("rendition{}.jpg", rendition.suffix()))
+    }
+
+    pub fn webp(&self, rendition: &Rendition) -> Option<PathBuf> {
+        rendition.webp.then(|| self.dir.join(format!("rendition{}.webp", rendition.suffix())))
+    }
+}
+
+/// The renditions of a source with `bytes` from an earlier run with the
+/// same content and options, without decoding it.
+pub fn cached_image(bytes: &[u8], cache_dir: &Path, options: &OptimizeOptions) -> Option<OptimizedImage> {
+    read_entry(entry_dir(bytes, cache_dir, options), bytes.len() as u64)
+}
+
+/// Optimizes `image`, decoded from the source `bytes`, into its renditions
+/// in the `optimized` folder of `cache_dir`. `cached_image` finds them on
+/// the next run with the same content and options.
+pub fn optimize_decoded(
+    mut image: DynamicImage,
+    bytes: &[u8],
+    cache_dir: &Path,
+    options: &OptimizeOptions,
+) -> Result<OptimizedImage> {
+    let dir = entry_dir(bytes, cache_dir, options);
+    let source_bytes = bytes.len() as u64;
+    // Sources with the same content share an entry and may be optimized at
+    // the same time, so each job builds its own copy and moves it in whole
+    let partial = dir.with_extension(format!("{}.part", uuid::Uuid::new_v4().simple()));
+    std::fs::create_dir_all(&partial).with_context(|| format!("Failed to create {}", partial.display()))?;
+
+    if image.width() > options.max_width {
+        image = image.resize(options.max_width, u32::MAX, FilterType::Lanczos3);
+    }
+
+    let mut widths: Vec<u32> = options.widths.iter().copied().filter(|width| *width < image.width()).collect();
+    widths.sort_unstable();
+    widths.dedup();
+    let mut sizes = vec![(RenditionKind::Full, image.clone())];
+    for width in widths {
+        sizes.push((RenditionKind::Width, image.resize(width, u32::MAX, FilterType::Lanczos3)));
+    }
+    let side = options.thumbnail.max(1);
+    sizes.push((RenditionKind::Thumbnail, image.resize_to_fill(side, side, FilterType::Lanczos3)));
+
+    let mut entry = OptimizedImage { dir: partial, renditions: Vec::new(), cached: false, source_bytes, bytes: 0 };
+    for (kind, pixels) in sizes {
+        let (jpeg, webp) = encode(&pixels, options.quality)?;
+        let rendition = Rendition {
+            kind,
+            width: pixels.width(),
+            height: pixels.height(),
+            webp: webp.len() < jpeg.len(),
+            bytes: jpeg.len().min(webp.len()) as u64,
+        };
+        write(&entry.jpeg(&rendition), &jpeg)?;
+        if let Some(path) = entry.webp(&rendition) {
+            write(&path, &webp)?;
+        }
+        entry.renditions.push(rendition);
+    }
+    write(&entry.dir.join(ENTRY_FILE), &serde_json::to_vec(&entry.renditions)?)?;
+    entry.bytes = entry.renditions[0].bytes;
+
+    let partial = std::mem::replace(&mut entry.dir, dir);
+    if std::fs::rename(&partial, &entry.dir).is_err() {
+        // Another job moved its copy in first, or an entry left incomplete
+        // by an older version is in the way
+        if let Some(finished) = read_entry(entry.dir.clone(), source_bytes) {
+            let _ = std::fs::remove_dir_all(&partial);
+            return Ok(OptimizedImage { cached: false, ..finished });
+        }
+        let _ = std::fs::remove_dir_all(&entry.dir);
+        std::fs::rename(&partial, &entry.dir).with_context(|| format!("Failed to write {}", entry.dir.display()))?;
+    }
+    Ok(entry)
+}
+
+/// Cache entry of a source with `bytes`. Only the options that change the
+/// output are part of the key.
+fn entry_dir(bytes: &[u8], cache_dir: &Path, options: &OptimizeOptions) -> PathBuf {
+    let widths: Vec<u8> = options.widths.iter().flat_map(|width| width.to_le_bytes()).collect();
+    let key = hash_key(&[
+        PIPELINE_VERSION.as_bytes(),
+        &options.max_width.to_le_bytes(),
+        &widths,
+        &options.thumbnail.to_le_bytes(),
+        &[options.quality],
+        bytes,
+    ]);
+    cache_dir.join(OPTIMIZED_DIR).join(key)
+}
+
+fn read_entry(dir: PathBuf, source_bytes: u64) -> Option<OptimizedImage> {
+    let renditions: Vec<Rendition> = serde_json::from_slice(&std::fs::read(dir.join(ENTRY_FILE)).ok()?).ok()?;
+    let bytes = renditions.first().map_or(0, |full| full.bytes);
+    Some(OptimizedImage { dir, renditions, cached: true, source_bytes, bytes })
+}
+
+/// The image at `quality` as a JPEG, flattened on white, and as a lossy
+/// WebP keeping its transparency.
+fn encode(image: &DynamicImage, quality: u8) -> Result<(Vec<u8>, Vec<u8>)> {
+    let quality = quality.clamp(1, 100);
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, quality)
+        .encode_image(&flatten(image))
+        .context("Failed to encode JPEG")?;
+
+    // The encoder takes 8-bit RGB or RGBA only
+    let webp = if image.color().has_alpha() {
+        let pixels = image.to_rgba8();
+        webp::Encoder::from_rgba(&pixels, pixels.width(), pixels.height()).encode_simple(false, f32::from(quality))
+    } else {
+        let pixels = image.to_rgb8();
+        webp::Encoder::from_rgb(&pixels, pixels.width(), pixels.height()).encode_simple(false, f32::from(quality))
+    }
+    .map_err(|e| anyhow::anyhow!("Failed to encode WebP: {:?}", e))?;
+    Ok((jpeg, webp.to_vec()))
+}
+
+/// Decodes `bytes` upright, following the EXIF orientation, and in sRGB.
+/// Re-encoding from the pixels leaves EXIF, GPS and other metadata behind.
+pub fn decode(bytes: &[u8]) -> Result<DynamicImage> {
+    let mut decoder = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()?
+        .into_decoder()
+        .context("Failed to read image")?;
+    let orientation = decoder.orientation()?;
+    let icc = decoder.icc_profile()?;
+    let mut image = DynamicImage::from_decoder(decoder).context("Failed to decode image")?;
+    image.apply_orientation(orientation);
+
+    match icc {
+        Some(icc) => to_srgb(image, &icc),
+        None => Ok(image),
+    }
+}
+
+/// Converts pixels described by the ICC profile `icc` to sRGB. Images whose
+/// profile cannot be read, or is not an RGB one, are taken as sRGB already.
+fn to_srgb(image: DynamicImage, icc: &[u8]) -> Result<DynamicImage> {
+    let Ok(profile) = ColorProfile::new_from_slice(icc) else {
+        return Ok(image);
+    };
+    if profile.color_space != DataColorSpace::Rgb {
+        return Ok(image);
+    }
+
+    let srgb = ColorProfile::new_srgb();
+    if image.color().has_alpha() {
+        let source = image.to_rgba8();
+        let mut target = source.clone();
+        profile
+            .create_transform_8bit(Layout::Rgba, &srgb, Layout::Rgba, TransformOptions::default())
+            .and_then(|transform| transform.transform(&source, &mut target))
+            .context("Failed to convert to sRGB")?;
+        Ok(DynamicImage::ImageRgba8(target))
+    } else {
+        let source = image.to_rgb8();
+        let mut target = source.clone();
+        profile
+            .create_transform_8bit(Layout::Rgb, &srgb, Layout::Rgb, TransformOptions::default())
+            .and_then(|transform| transform.transform(&source, &mut target))
+            .context("Failed to convert to sRGB")?;
+        Ok(DynamicImage::ImageRgb8(target))
+    }
+}
+
+/// JPEG has no transparency, transparent areas become white.
+fn flatten(image: &DynamicImage) -> DynamicImage {
+    if !image.color().has_alpha() {
+        return DynamicImage::ImageRgb8(image.to_rgb8());
+    }
+    let mut background = RgbaImage::from_pixel(image.width(), image.height(), Rgba([255, 255, 255, 255]));
+    image::imageops::overlay(&mut background, &image.to_rgba8(), 0, 0);
+    DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(background).to_rgb8())
+}
+
+fn write(path: &Path, bytes: &[u8]) -> Result<()> {
+    std::fs::write(path, bytes).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Smooth gradients with some detail, closer to a photo than a flat color
+    fn photo() -> DynamicImage {
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(320, 240, |x, y| {
+            image::Rgb([(x % 256) as u8, (y % 256) as u8, ((x * y) % 251) as u8])
+        }))
+    }
+
+    #[test]
+    fn webp_follows_the_quality() {
+        let (_, low) = encode(&photo(), 20).unwrap();
+        let (_, high) = encode(&photo(), 90).unwrap();
+        assert!(low.len() < high.len(), "{} bytes at 20, {} at 90", low.len(), high.len());
+        assert_eq!(image::guess_format(&low).unwrap(), image::ImageFormat::WebP);
+    }
+
+    #[test]
+    fn webp_keeps_transparency() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(64, 64, Rgba([10, 20, 30, 0])));
+        let (jpeg, webp) = encode(&image, 82).unwrap();
+        let decoded = image::load_from_memory(&webp).unwrap();
+        assert!(decoded.color().has_alpha());
+        assert_eq!(decoded.to_rgba8().get_pixel(0, 0)[3], 0);
+        assert_eq!(image::load_from_memory(&jpeg).unwrap().to_rgb8().get_pixel(0, 0).0, [255, 255, 255]);
+    }
+}
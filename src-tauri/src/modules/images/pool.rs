@@ -0,0 +1,98 @@
+//! Runs blocking image work on a fixed set of threads, away from the async
+//! runtime, with a memory budget: a job reserves the memory estimated for
+//! its decode and waits until it fits next to the jobs already running, so
+//! a batch of large photos cannot exhaust the RAM however many workers
+//! there are.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+/// Decoded pixels as RGBA, plus the color converted copy and the resized
+/// renditions alive at the same time while an image is optimized
+const BYTES_PER_PIXEL: u64 = 4 * 3;
+
+/// Starts `workers` threads running `work` on each of `jobs`, sharing a
+/// budget of `memory_limit` bytes. Results arrive as `(index, result)` in
+/// the order they finish; the channel closes once every job is done.
+pub fn run<T, R, F>(jobs: Vec<T>, workers: usize, memory_limit: u64, work: F) -> Result<UnboundedReceiver<(usize, R)>>
+where
+    T: Send + Sync + 'static,
+    R: Send + 'static,
+    F: Fn(&T, &MemoryBudget) -> R + Send + Sync + 'static,
+{
+    let (sender, receiver) = unbounded_channel();
+    let jobs = Arc::new(jobs);
+    let next = Arc::new(AtomicUsize::new(0));
+    let budget = Arc::new(MemoryBudget::new(memory_limit));
+    let work = Arc::new(work);
+
+    for worker in 0..workers.clamp(1, jobs.len().max(1)) {
+        let (jobs, next, budget, sender) = (jobs.clone(), next.clone(), budget.clone(), sender.clone());
+        let work = work.clone();
+        std::thread::Builder::new()
+            .name(format!("image-worker-{}", worker))
+            .spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(job) = jobs.get(index) else { break };
+                let result = work(job, &budget);
+                // The receiver is gone when the caller stopped waiting
+                if sender.send((index, result)).is_err() {
+                    break;
+                }
+            })
+            .context("Failed to start image worker")?;
+    }
+    Ok(receiver)
+}
+
+/// Memory needed to process the image at `path`, from the dimensions in its
+/// header. Files whose header cannot be read count as nothing, they fail
+/// before decoding.
+pub fn decode_estimate(path: &Path) -> u64 {
+    image::ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok())
+        .map_or(0, |(width, height)| u64::from(width) * u64::from(height) * BYTES_PER_PIXEL)
+}
+
+pub struct MemoryBudget {
+    limit: u64,
+    used: Mutex<u64>,
+    freed: Condvar,
+}
+
+impl MemoryBudget {
+    fn new(limit: u64) -> Self {
+        Self { limit: limit.max(1), used: Mutex::new(0), freed: Condvar::new() }
+    }
+
+    /// Blocks until `bytes` fit in the budget. Jobs larger than the whole
+    /// budget wait for every other job to finish and then run alone.
+    pub fn reserve(&self, bytes: u64) -> Reservation<'_> {
+        let bytes = bytes.min(self.limit);
+        let mut used = self.used.lock().unwrap_or_else(PoisonError::into_inner);
+        while *used + bytes > self.limit {
+            used = self.freed.wait(used).unwrap_or_else(PoisonError::into_inner);
+        }
+        *used += bytes;
+        Reservation { budget: self, bytes }
+    }
+}
+
+/// Memory taken from the budget until dropped.
+pub struct Reservation<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let mut used = self.budget.used.lock().unwrap_or_else(PoisonError::into_inner);
+        *used -= self.bytes;
+        self.budget.freed.notify_all();
+    }
+}
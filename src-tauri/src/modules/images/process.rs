@@ -0,0 +1,346 @@
+//! The images of a whole catalog on their way to the store: found in the
+//! product folders or downloaded, validated, optimized and staged with
+//! their manifest in the layout uploaded to the images bucket.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use super::optimize::{cached_image, decode, optimize_decoded};
+use super::validation::inspect;
+use super::{download_images, match_images, match_options};
+use super::{hash_key, pool};
+use super::{DownloadOptions, DownloadProgress, DownloadReport, ImageMatches, OptimizeOptions, OptimizedImage};
+use super::{ImageManifest, ManifestFile, ManifestImage, RenditionKind};
+use crate::modules::csv_parser::Product;
+use crate::modules::text::slugify;
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Local image files of the catalog.
+pub struct ImageSources {
+    pub matches: ImageMatches,
+    pub downloads: DownloadReport,
+}
+
+impl ImageSources {
+    /// Files matched in the product folders, then the downloaded URLs.
+    pub fn files_of(&self, product: &Product) -> Vec<PathBuf> {
+        let mut files = self.matches.files_of(&product.codigo_interno);
+        files.extend(self.downloads.files_of(product));
+        files
+    }
+}
+
+/// Matches the images folder of `mapping`, when there is one, and downloads
+/// the image URLs of `products`.
+pub async fn collect_sources(
+    products: &[Product],
+    mapping: &Value,
+    cache_dir: &Path,
+    on_download: impl FnMut(&DownloadProgress),
+) -> Result<ImageSources> {
+    let matches = match mapping.get("directory").and_then(|v| v.as_str()) {
+        Some(directory) => {
+            let codes: Vec<&str> = products.iter().map(|p| p.codigo_interno.as_str()).collect();
+            match_images(Path::new(directory), &codes, &match_options(mapping))?
+        }
+        None => ImageMatches::default(),
+    };
+    let downloads = download_images(products, cache_dir, &DownloadOptions::default(), on_download).await?;
+    Ok(ImageSources { matches, downloads })
+}
+
+/// What became of an image, emitted as each one finishes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImageStatus {
+    Optimized,
+    Cached,
+    /// Left out, as reported by the image scan
+    Invalid,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageProgress {
+    pub current: usize,
+    pub total: usize,
+    pub codigo: String,
+    pub path: String,
+    pub status: ImageStatus,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageFailure {
+    pub codigo: String,
+    pub path: String,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ProcessedImages {
+    /// Optimized images of each product code, in the order of its sources
+    #[serde(skip)]
+    pub products: HashMap<String, Vec<OptimizedImage>>,
+    pub optimized: usize,
+    pub cached: usize,
+    pub invalid: usize,
+    pub failed: Vec<ImageFailure>,
+    /// Size of the source files of the images processed
+    #[serde(rename = "sourceBytes")]
+    pub source_bytes: u64,
+    /// Size of their full size renditions
+    pub bytes: u64,
+    #[serde(rename = "savedBytes")]
+    pub saved_bytes: u64,
+    #[serde(rename = "elapsedMs")]
+    pub elapsed_ms: u64,
+}
+
+/// Validates and optimizes every image of `products` on a pool of worker
+/// threads, reporting each one to `on_image` as it finishes. An image that
+/// fails is reported and the rest carry on.
+pub async fn process_images(
+    products: &[Product],
+    sources: &ImageSources,
+    cache_dir: &Path,
+    options: &OptimizeOptions,
+    mut on_image: impl FnMut(&ImageProgress),
+) -> Result<ProcessedImages> {
+    let started = Instant::now();
+    let jobs: Vec<(String, PathBuf)> = products
+        .iter()
+        .flat_map(|product| {
+            sources.files_of(product).into_iter().map(|file| (product.codigo_interno.clone(), file))
+        })
+        .collect();
+    let total = jobs.len();
+
+    let workers = match options.workers {
+        0 => std::thread::available_parallelism().map_or(4, usize::from),
+        workers => workers,
+    };
+    let (cache_dir, job_options) = (cache_dir.to_path_buf(), options.clone());
+    let memory_limit = options.memory_mb.saturating_mul(1024 * 1024);
+    let mut finished = pool::run(jobs.clone(), workers, memory_limit, move |(_, path), budget| {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read {}", path.display()))
+            .map_err(Skipped::Failed)?;
+        // Cached images were valid when optimized, they are not decoded again
+        if let Some(cached) = cached_image(&bytes, &cache_dir, &job_options) {
+            return Ok(cached);
+        }
+        let _reserved = budget.reserve(pool::decode_estimate(path));
+        // Checked from the headers, then decoded once for both the last
+        // check and the renditions
+        inspect(&bytes).map_err(|_| Skipped::Invalid)?;
+        let image = decode(&bytes).map_err(|_| Skipped::Invalid)?;
+        optimize_decoded(image, &bytes, &cache_dir, &job_options).map_err(Skipped::Failed)
+    })?;
+
+    let mut results: Vec<Option<Result<OptimizedImage, Skipped>>> = (0..total).map(|_| None).collect();
+    let mut current = 0;
+    while let Some((index, result)) = finished.recv().await {
+        current += 1;
+        let status = match &result {
+            Ok(image) if image.cached => ImageStatus::Cached,
+            Ok(_) => ImageStatus::Optimized,
+            Err(Skipped::Invalid) => ImageStatus::Invalid,
+            Err(Skipped::Failed(_)) => ImageStatus::Failed,
+        };
+        let (codigo, path) = &jobs[index];
+        on_image(&ImageProgress { current, total, codigo: codigo.clone(), path: path.display().to_string(), status });
+        results[index] = Some(result);
+    }
+
+    // Collected in job order, so each product keeps the order of its sources
+    let mut processed = ProcessedImages::default();
+    for ((codigo, path), result) in jobs.into_iter().zip(results) {
+        let failure = |error: String| ImageFailure { codigo: codigo.clone(), path: path.display().to_string(), error };
+        match result {
+            Some(Ok(image)) => {
+                if image.cached {
+                    processed.cached += 1;
+                } else {
+                    processed.optimized += 1;
+                }
+                processed.source_bytes += image.source_bytes;
+                processed.bytes += image.bytes;
+                processed.products.entry(codigo).or_default().push(image);
+            }
+            Some(Err(Skipped::Invalid)) => processed.invalid += 1,
+            Some(Err(Skipped::Failed(e))) => processed.failed.push(failure(format!("{:#}", e))),
+            // The worker stopped on a panic in a decoder
+            None => processed.failed.push(failure("Image processing stopped unexpectedly".to_string())),
+        }
+    }
+    processed.saved_bytes = processed.source_bytes.saturating_sub(processed.bytes);
+    processed.elapsed_ms = started.elapsed().as_millis() as u64;
+    Ok(processed)
+}
+
+enum Skipped {
+    Invalid,
+    Failed(anyhow::Error),
+}
+
+/// Copies the renditions into `dir` as `<folder>/<n><suffix>.webp`, e.g.
+/// `cam-01/1-400.webp`, with the JPEG fallback next to each WebP, and writes
+/// the manifest listing them to `dir/manifest.json`.
+pub fn stage_images(processed: &ProcessedImages, dir: &Path) -> Result<ImageManifest> {
+    let mut manifest = ImageManifest::default();
+    for (codigo, folder) in folder_names(processed.products.keys()) {
+        let images = &processed.products[codigo];
+        let product_dir = dir.join(&folder);
+        std::fs::create_dir_all(&product_dir)
+            .with_context(|| format!("Failed to create {}", product_dir.display()))?;
+
+        let mut staged = Vec::new();
+        for (index, image) in images.iter().enumerate() {
+            let mut full = None;
+            let mut sizes = Vec::new();
+            let mut thumbnail = None;
+            for rendition in &image.renditions {
+                let stem = format!("{}/{}{}", folder, index + 1, rendition.suffix());
+                let jpeg = format!("{}.jpg", stem);
+                std::fs::copy(image.jpeg(rendition), dir.join(&jpeg))?;
+                let file = match image.webp(rendition) {
+                    Some(path) => {
+                        let webp = format!("{}.webp", stem);
+                        std::fs::copy(path, dir.join(&webp))?;
+                        ManifestFile { url: webp, fallback: Some(jpeg), width: rendition.width, height: rendition.height }
+                    }
+                    None => ManifestFile { url: jpeg, fallback: None, width: rendition.width, height: rendition.height },
+                };
+                match rendition.kind {
+                    RenditionKind::Full => full = Some(file),
+                    RenditionKind::Width => sizes.push(file),
+                    RenditionKind::Thumbnail => thumbnail = Some(file),
+                }
+            }
+            if let (Some(full), Some(thumbnail)) = (full, thumbnail) {
+                staged.push(ManifestImage { full, sizes, thumbnail });
+            }
+        }
+        manifest.0.insert(codigo.clone(), staged);
+    }
+
+    std::fs::write(dir.join(MANIFEST_FILE), serde_json::to_vec_pretty(&manifest)?)
+        .with_context(|| format!("Failed to write {}", dir.join(MANIFEST_FILE).display()))?;
+    Ok(manifest)
+}
+
+/// Folder of each product code in the bucket: the code as a slug, safe in
+/// paths and URLs whatever it holds ("CAM 01" becomes `cam-01`). Codes that
+/// would share a slug with an earlier code, in code order, or have none get
+/// a hash of the code appended.
+fn folder_names<'a>(codes: impl Iterator<Item = &'a String>) -> Vec<(&'a String, String)> {
+    let mut codes: Vec<&String> = codes.collect();
+    codes.sort();
+    let mut taken = HashSet::new();
+    codes
+        .into_iter()
+        .map(|codigo| {
+            let mut folder = slugify(codigo);
+            if folder.is_empty() || taken.contains(&folder) {
+                let hash = &hash_key(&[codigo.as_bytes()])[..8];
+                folder = if folder.is_empty() { hash.to_string() } else { format!("{}-{}", folder, hash) };
+            }
+            taken.insert(folder.clone());
+            (codigo, folder)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[tokio::test]
+    async fn invalid_images_are_skipped_and_optimized_ones_cached() {
+        let dir = std::env::temp_dir().join(format!("process-{}", uuid::Uuid::new_v4()));
+        let (folder, cache_dir) = (dir.join("fotos"), dir.join("cache"));
+        std::fs::create_dir_all(folder.join("CAM 01")).unwrap();
+        RgbImage::from_pixel(500, 400, Rgb([200, 10, 10])).save(folder.join("CAM 01/1.png")).unwrap();
+        RgbImage::from_pixel(50, 50, Rgb([10, 10, 200])).save(folder.join("CAM 01/2.png")).unwrap();
+        let products = [Product::sample("CAM 01")];
+        let sources = ImageSources {
+            matches: match_images(&folder, &["CAM 01"], &Default::default()).unwrap(),
+            downloads: DownloadReport::default(),
+        };
+        let options = OptimizeOptions { widths: vec![200], ..Default::default() };
+
+        let first = process_images(&products, &sources, &cache_dir, &options, |_| {}).await.unwrap();
+        let second = process_images(&products, &sources, &cache_dir, &options, |_| {}).await.unwrap();
+        let manifest = stage_images(&second, &dir.join("staged")).unwrap();
+        let staged = dir.join("staged/cam-01/1-200.jpg").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!((first.optimized, first.cached, first.invalid), (1, 0, 1));
+        assert_eq!((second.optimized, second.cached, second.invalid), (0, 1, 1));
+        assert!(first.failed.is_empty());
+        let image = &manifest.images_of("CAM 01")[0];
+        assert_eq!((image.full.width, image.full.height), (500, 400));
+        assert_eq!(image.sizes[0].width, 200);
+        assert!(staged);
+    }
+
+    #[tokio::test]
+    async fn identical_images_optimized_together_share_one_entry() {
+        let dir = std::env::temp_dir().join(format!("process-{}", uuid::Uuid::new_v4()));
+        let (folder, cache_dir) = (dir.join("fotos"), dir.join("cache"));
+        let codes: Vec<String> = (1..=4).map(|n| format!("P{}", n)).collect();
+        for codigo in &codes {
+            std::fs::create_dir_all(folder.join(codigo)).unwrap();
+            RgbImage::from_fn(300, 200, |x, y| Rgb([x as u8, y as u8, 90]))
+                .save(folder.join(codigo).join("1.png"))
+                .unwrap();
+        }
+        let products: Vec<Product> = codes.iter().map(|codigo| Product::sample(codigo)).collect();
+        let code_refs: Vec<&str> = codes.iter().map(String::as_str).collect();
+        let sources = ImageSources {
+            matches: match_images(&folder, &code_refs, &Default::default()).unwrap(),
+            downloads: DownloadReport::default(),
+        };
+        let options = OptimizeOptions { workers: 4, ..Default::default() };
+
+        let processed = process_images(&products, &sources, &cache_dir, &options, |_| {}).await.unwrap();
+        let entries: Vec<PathBuf> = std::fs::read_dir(cache_dir.join("optimized"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        let image = &processed.products["P1"][0];
+        let formats: Vec<_> = image
+            .renditions
+            .iter()
+            .map(|rendition| image::guess_format(&std::fs::read(image.jpeg(rendition)).unwrap()).unwrap())
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(processed.failed.is_empty(), "{:?}", processed.failed);
+        assert_eq!(processed.products.len(), 4);
+        assert_eq!(entries.len(), 1, "{:?}", entries);
+        assert!(formats.iter().all(|format| *format == image::ImageFormat::Jpeg));
+    }
+
+    #[test]
+    fn folders_are_slugs_safe_in_paths() {
+        let codes: Vec<String> = ["CAM 01", "A/B", "../x", "cam-01", "***"].iter().map(|c| c.to_string()).collect();
+        let folders: HashMap<&String, String> = folder_names(codes.iter()).into_iter().collect();
+
+        assert_eq!(folders[&codes[0]], "cam-01");
+        assert_eq!(folders[&codes[1]], "a-b");
+        assert_eq!(folders[&codes[2]], "x");
+        // Sorted first, "CAM 01" keeps the plain slug
+        assert!(folders[&codes[3]].starts_with("cam-01-"));
+        assert_eq!(folders[&codes[4]].len(), 8);
+        let distinct: HashSet<&String> = folders.values().collect();
+        assert_eq!(distinct.len(), codes.len());
+        assert!(folders.values().all(|folder| folder.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')));
+    }
+}
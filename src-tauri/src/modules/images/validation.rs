@@ -0,0 +1,220 @@
+//! Checks that an image file really is a usable product photo by decoding
+//! it, whatever its extension says.
+
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, ImageFormat, ImageReader};
+use serde::Serialize;
+use std::io::Cursor;
+use std::path::Path;
+
+/// Shortest side accepted, smaller images look blurry on product pages
+const MIN_SIDE: u32 = 200;
+/// Larger images are refused before decoding, they would take hundreds of
+/// megabytes of memory each
+const MAX_SIDE: u32 = 12_000;
+const MAX_PIXELS: u64 = 50_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImageProblem {
+    /// Truncated or damaged data
+    Corrupt,
+    /// Not an image, or a format the store does not serve
+    Unsupported,
+    /// CMYK JPEG, shown with wrong colors by browsers
+    Cmyk,
+    Animated,
+    TooSmall,
+    TooLarge,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InvalidImage {
+    pub path: String,
+    pub problem: ImageProblem,
+    pub message: String,
+}
+
+/// Decodes the image at `path`, returning what makes it unusable if anything.
+pub fn validate(path: &Path) -> Result<(), (ImageProblem, String)> {
+    let bytes = std::fs::read(path).map_err(|e| (ImageProblem::Corrupt, format!("Cannot read file: {}", e)))?;
+    let format = inspect(&bytes)?;
+    ImageReader::with_format(Cursor::new(bytes.as_slice()), format).decode().map_err(corrupt)?;
+    Ok(())
+}
+
+/// Everything `validate` checks short of decoding the pixels, from the
+/// headers and markers of `bytes`. Returns the format of the image, whose
+/// decode may still fail on damaged data.
+pub fn inspect(bytes: &[u8]) -> Result<ImageFormat, (ImageProblem, String)> {
+    let format = image::guess_format(bytes)
+        .map_err(|_| (ImageProblem::Unsupported, "Not a recognized image format".to_string()))?;
+    if !matches!(format, ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP | ImageFormat::Gif) {
+        return Err((ImageProblem::Unsupported, format!("{:?} images are not supported", format)));
+    }
+
+    if format == ImageFormat::Jpeg {
+        let markers = jpeg_markers(bytes);
+        let frame = markers.iter().find(|(marker, _)| is_frame(*marker));
+        if frame.and_then(|(_, at)| bytes.get(at + 9)) == Some(&4) {
+            return Err((ImageProblem::Cmyk, "CMYK JPEG, convert it to RGB".to_string()));
+        }
+        // The decoder fills a truncated scan with gray instead of failing,
+        // so the end of image marker has to be there
+        let scan = markers.iter().find(|(marker, _)| *marker == 0xDA).map(|(_, at)| *at);
+        if !scan.is_some_and(|at| bytes[at..].windows(2).any(|w| w == [0xFF, 0xD9])) {
+            return Err((ImageProblem::Corrupt, "Truncated JPEG, the end of image is missing".to_string()));
+        }
+    }
+
+    let (width, height) = ImageReader::with_format(Cursor::new(bytes), format).into_dimensions().map_err(corrupt)?;
+    if width.max(height) > MAX_SIDE || u64::from(width) * u64::from(height) > MAX_PIXELS {
+        return Err((ImageProblem::TooLarge, format!("{}x{} pixels is too large", width, height)));
+    }
+
+    let animated = match format {
+        ImageFormat::Gif => {
+            let frames = GifDecoder::new(Cursor::new(bytes)).map_err(corrupt)?.into_frames();
+            frames.take(2).collect::<Result<Vec<_>, _>>().map_err(corrupt)?.len() > 1
+        }
+        ImageFormat::Png => PngDecoder::new(Cursor::new(bytes))
+            .and_then(|decoder| decoder.is_apng())
+            .map_err(corrupt)?,
+        ImageFormat::WebP => WebPDecoder::new(Cursor::new(bytes)).map_err(corrupt)?.has_animation(),
+        _ => false,
+    };
+    if animated {
+        return Err((ImageProblem::Animated, "Animated images are not supported".to_string()));
+    }
+
+    if width.min(height) < MIN_SIDE {
+        return Err((
+            ImageProblem::TooSmall,
+            format!("{}x{} pixels, at least {} on each side", width, height, MIN_SIDE),
+        ));
+    }
+
+    Ok(format)
+}
+
+fn corrupt(e: image::ImageError) -> (ImageProblem, String) {
+    (ImageProblem::Corrupt, e.to_string())
+}
+
+/// Markers of a JPEG with their offsets, up to the first start of scan.
+/// The frame header has the color components at offset 9: 1 for gray, 3
+/// for YCbCr or RGB and 4 for CMYK or YCCK.
+fn jpeg_markers(bytes: &[u8]) -> Vec<(u8, usize)> {
+    let mut markers = Vec::new();
+    let mut at = 2;
+    while at + 4 <= bytes.len() && bytes[at] == 0xFF {
+        let marker = bytes[at + 1];
+        // Fill bytes before a marker
+        if marker == 0xFF {
+            at += 1;
+            continue;
+        }
+        markers.push((marker, at));
+        if marker == 0xDA {
+            break;
+        }
+        at += 2 + usize::from(u16::from_be_bytes([bytes[at + 2], bytes[at + 3]]));
+    }
+    markers
+}
+
+/// Start of frame markers, all but DHT, JPG and DAC in the C0-CF range.
+fn is_frame(marker: u8) -> bool {
+    matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifEncoder;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{DynamicImage, Frame, Rgb, RgbImage, Rgba, RgbaImage};
+
+    fn encoded(image: RgbImage, format: ImageFormat) -> Vec<u8> {
+        let mut bytes = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(image).write_to(&mut bytes, format).unwrap();
+        bytes.into_inner()
+    }
+
+    fn jpeg() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        JpegEncoder::new_with_quality(&mut bytes, 80)
+            .encode_image(&RgbImage::from_fn(300, 300, |x, y| Rgb([x as u8, y as u8, 40])))
+            .unwrap();
+        bytes
+    }
+
+    fn problem(bytes: &[u8]) -> Option<ImageProblem> {
+        inspect(bytes).err().map(|(problem, _)| problem)
+    }
+
+    #[test]
+    fn usable_images_pass() {
+        assert_eq!(inspect(&jpeg()), Ok(ImageFormat::Jpeg));
+        let png = encoded(RgbImage::from_pixel(200, 400, Rgb([1, 2, 3])), ImageFormat::Png);
+        assert_eq!(inspect(&png), Ok(ImageFormat::Png));
+    }
+
+    #[test]
+    fn jpeg_without_end_of_image_is_corrupt() {
+        let mut bytes = jpeg();
+        assert_eq!(bytes[bytes.len() - 2..], [0xFF, 0xD9]);
+        bytes.truncate(bytes.len() - 200);
+        assert_eq!(problem(&bytes), Some(ImageProblem::Corrupt));
+    }
+
+    #[test]
+    fn cmyk_jpeg_is_refused() {
+        let mut bytes = jpeg();
+        // Four components in the frame header
+        let frame = jpeg_markers(&bytes).into_iter().find(|(marker, _)| is_frame(*marker)).unwrap().1;
+        assert_eq!(bytes[frame + 9], 3);
+        bytes[frame + 9] = 4;
+        assert_eq!(problem(&bytes), Some(ImageProblem::Cmyk));
+    }
+
+    #[test]
+    fn animated_gif_is_refused() {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut bytes);
+            for color in [[255, 0, 0, 255], [0, 0, 255, 255]] {
+                encoder.encode_frame(Frame::new(RgbaImage::from_pixel(300, 300, Rgba(color)))).unwrap();
+            }
+        }
+        assert_eq!(problem(&bytes), Some(ImageProblem::Animated));
+
+        let mut still = Vec::new();
+        GifEncoder::new(&mut still).encode_frame(Frame::new(RgbaImage::from_pixel(300, 300, Rgba([0; 4])))).unwrap();
+        assert_eq!(inspect(&still), Ok(ImageFormat::Gif));
+    }
+
+    #[test]
+    fn images_over_the_limits_are_refused_before_decoding() {
+        let wide = encoded(RgbImage::new(MAX_SIDE + 1, 200), ImageFormat::Png);
+        assert_eq!(problem(&wide), Some(ImageProblem::TooLarge));
+        let small = encoded(RgbImage::new(MIN_SIDE - 1, 1000), ImageFormat::Png);
+        assert_eq!(problem(&small), Some(ImageProblem::TooSmall));
+    }
+
+    #[test]
+    fn damaged_pixel_data_is_caught_by_the_decode() {
+        let path = std::env::temp_dir().join(format!("validation-{}.png", uuid::Uuid::new_v4()));
+        let mut bytes = encoded(RgbImage::from_fn(300, 300, |x, y| Rgb([x as u8, y as u8, 7])), ImageFormat::Png);
+        // Cuts into the compressed pixels, leaving the header intact
+        bytes.truncate(bytes.len() / 2);
+        std::fs::write(&path, &bytes).unwrap();
+        let result = validate(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(inspect(&bytes), Ok(ImageFormat::Png));
+        assert_eq!(result.unwrap_err().0, ImageProblem::Corrupt);
+    }
+}
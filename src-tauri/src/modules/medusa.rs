@@ -0,0 +1,245 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::modules::csv_parser::{category_handle, CategoryNode, Money, Product, Variant};
+use crate::modules::images::ManifestImage;
+use crate::modules::text::slugify;
+
+mod client;
+mod export;
+mod seed;
+mod sync;
+
+pub use client::MedusaClient;
+pub use export::fetch_catalog;
+pub use seed::{seed_catalog, SeedSummary};
+pub use sync::{sync_catalog, CatalogSync};
+
+const DEFAULT_OPTION: &str = "Padrão";
+/// Stock location created on stores that have none yet
+pub const STOCK_LOCATION_NAME: &str = "Estoque principal";
+
+/// Where a store's admin API answers and the admin user to log in as.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StoreAccess {
+    pub url: String,
+    pub email: String,
+    pub password: String,
+}
+
+/// Body for `POST /admin/products` creating `product` with its options and
+/// variants. Products without options get a single default option so Medusa
+/// accepts the variant. `external_id` carries the product UUID (or the
+/// internal code) so later imports can find the product again. Image URLs
+/// given in the product file follow the uploaded images, whose thumbnail
+/// and smaller sizes go in `metadata.images` for the storefront.
+pub fn product_payload(product: &Product, images: &[ManifestImage]) -> Value {
+    let external_id = product
+        .uuid
+        .clone()
+        .unwrap_or_else(|| product.codigo_interno.clone());
+
+    let (options, variants) = if product.options.is_empty() {
+        let options = vec![json!({ "title": DEFAULT_OPTION, "values": [DEFAULT_OPTION] })];
+        let mut variant = variant_payload(product, product.variants.first());
+        variant["title"] = json!(DEFAULT_OPTION);
+        variant["options"] = json!({ DEFAULT_OPTION: DEFAULT_OPTION });
+        (options, vec![variant])
+    } else {
+        let options = product.options
+            .iter()
+            .map(|option| json!({ "title": option.title, "values": option.values }))
+            .collect();
+        let variants = product.variants
+            .iter()
+            .map(|variant| {
+                let mut payload = variant_payload(product, Some(variant));
+                payload["title"] = json!(variant.title(&product.options));
+                payload["options"] = json!(variant.options);
+                payload
+            })
+            .collect();
+        (options, variants)
+    };
+
+    let mut payload = json!({
+        "title": product.nome,
+        "handle": product_handle(product),
+        "description": product.descricao,
+        "status": "published",
+        "external_id": external_id,
+        "metadata": {
+            "codigo_interno": product.codigo_interno,
+        },
+        "images": images
+            .iter()
+            .map(|image| &image.full.url)
+            .chain(&product.imagens)
+            .map(|url| json!({ "url": url }))
+            .collect::<Vec<_>>(),
+        "options": options,
+        "variants": variants,
+    });
+    if let Some(first) = images.first() {
+        payload["thumbnail"] = json!(first.thumbnail.url);
+        payload["metadata"]["images"] = json!(images);
+    }
+    payload
+}
+
+/// Variant fields other than title and options. Weight is in grams and the
+/// dimensions in centimetres; the NCM goes in `hs_code`.
+fn variant_payload(product: &Product, variant: Option<&Variant>) -> Value {
+    let Some(variant) = variant else {
+        return json!({
+            "sku": product.codigo_interno,
+            "manage_inventory": false,
+            "prices": [price_payload(product.preco)],
+        });
+    };
+
+    json!({
+        "sku": variant.sku,
+        "ean": variant.ean,
+        "hs_code": variant.ncm,
+        "weight": variant.peso_g,
+        "height": variant.altura_cm,
+        "width": variant.largura_cm,
+        "length": variant.comprimento_cm,
+        "manage_inventory": variant.estoque.is_some(),
+        "prices": [price_payload(variant.preco.unwrap_or(product.preco))],
+    })
+}
+
+/// Shipping data for the inventory item Medusa creates for a variant with
+/// managed inventory, which is what shipping providers quote from.
+pub fn inventory_item_payload(variant: &Variant) -> Value {
+    json!({
+        "hs_code": variant.ncm,
+        "weight": variant.peso_g,
+        "height": variant.altura_cm,
+        "width": variant.largura_cm,
+        "length": variant.comprimento_cm,
+    })
+}
+
+/// Medusa v2 stores `amount` in major units, reais rather than centavos.
+fn price_payload(price: Money) -> Value {
+    json!({
+        "currency_code": price.currency.code().to_lowercase(),
+        "amount": price.to_major_f64(),
+    })
+}
+
+/// `prices`, a variant's price set in the store, with `price` taking the
+/// place of the one in its currency. Medusa replaces the whole set on an
+/// update, so the other currencies and the ids are sent back as they are.
+pub fn merge_price(prices: &[Value], mut price: Value) -> Vec<Value> {
+    let currency = price["currency_code"].as_str().unwrap_or_default().to_string();
+    let mut merged = Vec::new();
+    for live in prices {
+        if live["currency_code"].as_str().is_some_and(|code| code.eq_ignore_ascii_case(&currency)) {
+            if live["id"].is_string() {
+                price["id"] = live["id"].clone();
+            }
+        } else {
+            merged.push(json!({ "id": live["id"], "currency_code": live["currency_code"], "amount": live["amount"] }));
+        }
+    }
+    merged.push(price);
+    merged
+}
+
+/// Handles must be unique per store, the internal code keeps products with
+/// the same name apart.
+pub fn product_handle(product: &Product) -> String {
+    let name = slugify(&product.nome);
+    let code = slugify(&product.codigo_interno);
+    if name.is_empty() {
+        code
+    } else {
+        format!("{}-{}", name, code)
+    }
+}
+
+/// Medusa ids of the taxonomy a catalog links to: categories and
+/// collections by handle, tags by lowercase value.
+#[derive(Debug, Default)]
+pub struct TaxonomyIds {
+    pub categories: HashMap<String, String>,
+    pub collections: HashMap<String, String>,
+    pub tags: HashMap<String, String>,
+}
+
+/// Adds the product's category, collection and tags to a product payload.
+/// Entries missing from `ids` are left out.
+pub fn link_taxonomy(payload: &mut Value, product: &Product, ids: &TaxonomyIds) {
+    if !product.categoria.is_empty() {
+        if let Some(id) = ids.categories.get(&category_handle(&product.categoria)) {
+            payload["categories"] = json!([{ "id": id }]);
+        }
+    }
+
+    if let Some(id) = product.colecao.as_ref().and_then(|c| ids.collections.get(&slugify(c))) {
+        payload["collection_id"] = json!(id);
+    }
+
+    let tags: Vec<Value> = product.tags
+        .iter()
+        .filter_map(|tag| ids.tags.get(&tag.to_lowercase()))
+        .map(|id| json!({ "id": id }))
+        .collect();
+    if !tags.is_empty() {
+        payload["tags"] = json!(tags);
+    }
+}
+
+/// Walks the tree depth first, so every category comes after its parent.
+/// Each entry carries the parent's handle.
+pub fn flatten_categories(tree: &[CategoryNode]) -> Vec<(&CategoryNode, Option<&str>)> {
+    let mut flat = Vec::new();
+    let mut stack: Vec<(&CategoryNode, Option<&str>)> = tree.iter().rev().map(|node| (node, None)).collect();
+    while let Some((node, parent)) = stack.pop() {
+        flat.push((node, parent));
+        stack.extend(node.children.iter().rev().map(|child| (child, Some(node.handle.as_str()))));
+    }
+    flat
+}
+
+pub fn category_payload(node: &CategoryNode, parent_id: Option<&str>) -> Value {
+    json!({
+        "name": node.name,
+        "handle": node.handle,
+        "is_active": true,
+        "is_internal": false,
+        "parent_category_id": parent_id,
+    })
+}
+
+pub fn collection_payload(title: &str) -> Value {
+    json!({ "title": title, "handle": slugify(title) })
+}
+
+pub fn tag_payload(value: &str) -> Value {
+    json!({ "value": value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn price_amount_is_in_major_units() {
+        let payload = product_payload(&Product::sample("CAM-01"), &[]);
+        let price = &payload["variants"][0]["prices"][0];
+        assert_eq!(price["currency_code"], "brl");
+        assert_eq!(price["amount"], json!(49.9));
+    }
+
+    #[test]
+    fn price_amount_keeps_centavos() {
+        assert_eq!(price_payload(Money::brl(1)), json!({ "currency_code": "brl", "amount": 0.01 }));
+        assert_eq!(price_payload(Money::brl(129990))["amount"], json!(1299.9));
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::{Context, Result};
+use reqwest::RequestBuilder;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const PAGE_SIZE: usize = 100;
+
+/// Client for the Medusa admin API, authenticated with the bearer token of
+/// an admin user.
+#[derive(Debug, Clone)]
+pub struct MedusaClient {
+    http: reqwest::Client,
+    base_url: String,
+    token: Option<String>,
+}
+
+impl MedusaClient {
+    pub fn new(base_url: &str) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .context("Failed to create HTTP client")?;
+        Ok(Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: None,
+        })
+    }
+
+    /// Polls `/health` until the server answers or `timeout` passes.
+    pub async fn wait_healthy(&self, timeout: Duration) -> Result<()> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let healthy = self.http
+                .get(self.url("/health"))
+                .send()
+                .await
+                .is_ok_and(|r| r.status().is_success());
+            if healthy {
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                anyhow::bail!("Timed out waiting for Medusa at {}", self.base_url);
+            }
+            tokio::time::sleep(Duration::from_secs(3)).await;
+        }
+    }
+
+    pub async fn login(&mut self, email: &str, password: &str) -> Result<()> {
+        let request = self.http
+            .post(self.url("/auth/user/emailpass"))
+            .json(&json!({ "email": email, "password": password }));
+        let response = self.send("/auth/user/emailpass", request).await?;
+        let token = response["token"]
+            .as_str()
+            .context("Medusa login returned no token")?;
+        self.token = Some(token.to_string());
+        Ok(())
+    }
+
+    pub async fn get(&self, path: &str, query: &[(&str, &str)]) -> Result<Value> {
+        let request = self.http.get(self.url(path)).query(query);
+        self.send(path, request).await
+    }
+
+    /// Every entity of a paginated admin list such as `/admin/products`,
+    /// reading `key` from each page.
+    pub async fn list(&self, path: &str, key: &str, fields: &str) -> Result<Vec<Value>> {
+        let mut items = Vec::new();
+        loop {
+            let offset = items.len().to_string();
+            let limit = PAGE_SIZE.to_string();
+            let page = self
+                .get(path, &[("fields", fields), ("limit", &limit), ("offset", &offset)])
+                .await?;
+            let batch = page[key].as_array().cloned().unwrap_or_default();
+            let count = page["count"].as_u64().unwrap_or(0) as usize;
+            let done = batch.len() < PAGE_SIZE;
+            items.extend(batch);
+            if done || items.len() >= count {
+                return Ok(items);
+            }
+        }
+    }
+
+    pub async fn post(&self, path: &str, body: &Value) -> Result<Value> {
+        let request = self.http.post(self.url(path)).json(body);
+        self.send(path, request).await
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    async fn send(&self, path: &str, request: RequestBuilder) -> Result<Value> {
+        let request = match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        };
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Medusa API request {} failed", path))?;
+
+        let status = response.status();
+        let body: Value = response.json().await.unwrap_or(Value::Null);
+        if !status.is_success() {
+            let message = body["message"].as_str().unwrap_or_else(|| status.as_str());
+            anyhow::bail!("Medusa API {} returned {}: {}", path, status, message);
+        }
+        Ok(body)
+    }
+}
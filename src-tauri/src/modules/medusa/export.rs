@@ -0,0 +1,180 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+use super::{MedusaClient, DEFAULT_OPTION};
+use crate::modules::csv_parser::{Currency, Money, Product, ProductOption, Variant};
+
+const EXPORT_FIELDS: &str = "id,title,handle,description,status,external_id,metadata,\
+    *options,*variants,*variants.prices,*variants.options,+variants.inventory_quantity,\
+    *images,*categories,*collection,*tags";
+const ARCHIVED: &str = "draft";
+
+/// The store's catalog as products in the spreadsheet schema, with the
+/// stock and image URLs the store holds now. Archived products are left
+/// out, so importing the file again does not publish them.
+pub async fn fetch_catalog(client: &MedusaClient) -> Result<Vec<Product>> {
+    let live = client
+        .list("/admin/products", "products", EXPORT_FIELDS)
+        .await
+        .context("Failed to list the store's products")?;
+    let categories = client
+        .list("/admin/product-categories", "product_categories", "id,name,parent_category_id")
+        .await
+        .context("Failed to list the store's categories")?;
+    let categories: HashMap<&str, &Value> = categories
+        .iter()
+        .filter_map(|c| c["id"].as_str().map(|id| (id, c)))
+        .collect();
+
+    Ok(live
+        .iter()
+        .filter(|p| text(&p["status"]) != ARCHIVED)
+        .filter_map(|p| to_product(p, &categories))
+        .collect())
+}
+
+fn to_product(live: &Value, categories: &HashMap<&str, &Value>) -> Option<Product> {
+    let codigo_interno = [&live["metadata"]["codigo_interno"], &live["handle"], &live["id"]]
+        .into_iter()
+        .find_map(|v| v.as_str().filter(|s| !s.is_empty()))?
+        .to_string();
+
+    // Products created without options carry a single default one
+    let option_titles: HashMap<&str, &str> = items(&live["options"])
+        .iter()
+        .filter(|o| text(&o["title"]) != DEFAULT_OPTION)
+        .filter_map(|o| Some((o["id"].as_str()?, text(&o["title"]))))
+        .collect();
+
+    let mut options: Vec<ProductOption> = Vec::new();
+    let mut variants = Vec::new();
+    let mut prices = Vec::new();
+    for live_variant in items(&live["variants"]) {
+        let mut values = BTreeMap::new();
+        for value in items(&live_variant["options"]) {
+            let Some(title) = value["option_id"].as_str().and_then(|id| option_titles.get(id)) else {
+                continue;
+            };
+            let value = text(&value["value"]).to_string();
+            match options.iter_mut().find(|o| o.title == *title) {
+                Some(option) if !option.values.contains(&value) => option.values.push(value.clone()),
+                Some(_) => {}
+                None => options.push(ProductOption { title: title.to_string(), values: vec![value.clone()] }),
+            }
+            values.insert(title.to_string(), value);
+        }
+
+        let manage_inventory = live_variant["manage_inventory"].as_bool().unwrap_or(false);
+        prices.push(price(live_variant));
+        variants.push(Variant {
+            sku: text(&live_variant["sku"]).to_string(),
+            options: values,
+            preco: None,
+            estoque: live_variant["inventory_quantity"].as_i64().filter(|_| manage_inventory),
+            ean: non_empty(&live_variant["ean"]),
+            ncm: non_empty(&live_variant["hs_code"]),
+            peso_g: live_variant["weight"].as_f64().map(|g| g.round() as u32),
+            altura_cm: live_variant["height"].as_f64(),
+            largura_cm: live_variant["width"].as_f64(),
+            comprimento_cm: live_variant["length"].as_f64(),
+        });
+    }
+    if variants.is_empty() {
+        return None;
+    }
+
+    let preco = prices.iter().flatten().next().copied().unwrap_or_else(|| Money::brl(0));
+    for (variant, price) in variants.iter_mut().zip(prices) {
+        variant.preco = price.filter(|p| *p != preco);
+    }
+
+    Some(Product {
+        uuid: live["external_id"]
+            .as_str()
+            .filter(|id| Uuid::parse_str(id).is_ok())
+            .map(str::to_string),
+        codigo_interno,
+        nome: text(&live["title"]).to_string(),
+        preco,
+        descricao: text(&live["description"]).to_string(),
+        options,
+        variants,
+        categoria: items(&live["categories"])
+            .first()
+            .map(|c| category_path(c, categories))
+            .unwrap_or_default(),
+        colecao: non_empty(&live["collection"]["title"]),
+        tags: items(&live["tags"]).iter().filter_map(|t| non_empty(&t["value"])).collect(),
+        imagens: items(&live["images"]).iter().filter_map(|i| non_empty(&i["url"])).collect(),
+    })
+}
+
+/// The price in reais when the variant has one, otherwise its first price.
+fn price(variant: &Value) -> Option<Money> {
+    let prices = items(&variant["prices"]);
+    let chosen = prices
+        .iter()
+        .find(|p| text(&p["currency_code"]).eq_ignore_ascii_case(Currency::BRL.code()))
+        .or(prices.first())?;
+    let currency = Currency::new(text(&chosen["currency_code"])).ok()?;
+    // Medusa v2 amounts are in major units, reais rather than centavos
+    chosen["amount"].as_f64().and_then(|amount| Money::from_major_f64(amount, currency).ok())
+}
+
+/// Names from the root category down to `category`.
+fn category_path(category: &Value, categories: &HashMap<&str, &Value>) -> Vec<String> {
+    let mut path = vec![text(&category["name"]).to_string()];
+    let mut parent = category["parent_category_id"].as_str();
+    while let Some(node) = parent.and_then(|id| categories.get(id)) {
+        // Guards against a cycle in broken data
+        if path.len() > categories.len() {
+            break;
+        }
+        path.push(text(&node["name"]).to_string());
+        parent = node["parent_category_id"].as_str();
+    }
+    path.reverse();
+    path
+}
+
+fn items(value: &Value) -> &[Value] {
+    value.as_array().map(Vec::as_slice).unwrap_or(&[])
+}
+
+fn non_empty(value: &Value) -> Option<String> {
+    value.as_str().map(str::trim).filter(|s| !s.is_empty()).map(str::to_string)
+}
+
+fn text(value: &Value) -> &str {
+    value.as_str().unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::csv_parser::{stream_products_file, write_csv, ImportOptions};
+    use serde_json::json;
+
+    #[test]
+    fn exported_prices_round_trip_through_the_csv() {
+        let live = json!({
+            "id": "prod_1",
+            "title": "Camiseta",
+            "metadata": { "codigo_interno": "CAM-01" },
+            "variants": [{
+                "sku": "CAM-01",
+                "prices": [{ "currency_code": "usd", "amount": 9.5 }, { "currency_code": "brl", "amount": 49.9 }],
+            }],
+        });
+        let product = to_product(&live, &HashMap::new()).unwrap();
+        assert_eq!(product.preco, Money::brl(4990));
+
+        let path = std::env::temp_dir().join(format!("export-{}.csv", Uuid::new_v4()));
+        std::fs::write(&path, write_csv(&[product]).unwrap()).unwrap();
+        let report = stream_products_file(&path, &ImportOptions::default(), |_| {});
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(report.unwrap().products[0].preco, Money::brl(4990));
+    }
+}
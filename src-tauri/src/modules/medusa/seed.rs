@@ -0,0 +1,352 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use super::{
+    category_payload, collection_payload, flatten_categories, inventory_item_payload,
+    link_taxonomy, merge_price, product_payload, tag_payload, MedusaClient, TaxonomyIds,
+    STOCK_LOCATION_NAME,
+};
+use crate::modules::csv_parser::{self, Product};
+use crate::modules::images::ImageManifest;
+use crate::modules::text::slugify;
+
+/// What an update needs of a product already in the store
+const LIVE_FIELDS: &str = "id,*options,*variants,*variants.prices";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SeedFailure {
+    pub codigo_interno: String,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SeedSummary {
+    pub categories: usize,
+    pub collections: usize,
+    pub tags: usize,
+    pub created: usize,
+    /// Products already in the store, found by `external_id`
+    pub updated: usize,
+    /// Variants whose stock level was set
+    pub stocked: usize,
+    pub failed: Vec<SeedFailure>,
+}
+
+/// Creates the categories, collections and tags used by `products`, reusing
+/// the ones that already exist, then creates the products linked to them.
+/// Products already in the store with the same `external_id`, from an
+/// earlier deploy, are updated instead.
+/// Variants with `estoque` get their inventory item's shipping data and a
+/// stock level in the store's stock location. A product that fails is
+/// recorded and the rest carry on. `images` holds the uploaded images of
+/// each product with their public URLs.
+pub async fn seed_catalog(
+    client: &MedusaClient,
+    products: &[Product],
+    images: &ImageManifest,
+    mut on_product: impl FnMut(usize, usize),
+) -> Result<SeedSummary> {
+    let mut summary = SeedSummary::default();
+    let mut ids = TaxonomyIds::default();
+
+    let tree = csv_parser::category_tree(products);
+    for (node, parent) in flatten_categories(&tree) {
+        let parent_id = parent.and_then(|handle| ids.categories.get(handle)).map(String::as_str);
+        let id = find_or_create(
+            client,
+            "/admin/product-categories",
+            ("handle", &node.handle),
+            "product_categories",
+            "product_category",
+            &category_payload(node, parent_id),
+        )
+        .await
+        .with_context(|| format!("Failed to create category {}", node.name))?;
+        ids.categories.insert(node.handle.clone(), id);
+        summary.categories += 1;
+    }
+
+    for title in csv_parser::collections(products) {
+        let handle = slugify(&title);
+        let id = find_or_create(
+            client,
+            "/admin/collections",
+            ("handle", &handle),
+            "collections",
+            "collection",
+            &collection_payload(&title),
+        )
+        .await
+        .with_context(|| format!("Failed to create collection {}", title))?;
+        ids.collections.insert(handle, id);
+        summary.collections += 1;
+    }
+
+    for value in csv_parser::tags(products) {
+        let id = find_or_create(
+            client,
+            "/admin/product-tags",
+            ("value", &value),
+            "product_tags",
+            "product_tag",
+            &tag_payload(&value),
+        )
+        .await
+        .with_context(|| format!("Failed to create tag {}", value))?;
+        ids.tags.insert(value.to_lowercase(), id);
+        summary.tags += 1;
+    }
+
+    let has_stock = products.iter().flat_map(|p| &p.variants).any(|v| v.estoque.is_some());
+    let location_id = if has_stock {
+        Some(ensure_stock_location(client).await.context("Failed to set up the stock location")?)
+    } else {
+        None
+    };
+
+    for (index, product) in products.iter().enumerate() {
+        let mut payload = product_payload(product, images.images_of(&product.codigo_interno));
+        link_taxonomy(&mut payload, product, &ids);
+
+        match seed_product(client, product, payload, location_id.as_deref()).await {
+            Ok((created, stocked)) => {
+                if created {
+                    summary.created += 1;
+                } else {
+                    summary.updated += 1;
+                }
+                summary.stocked += stocked;
+            }
+            Err(e) => summary.failed.push(SeedFailure {
+                codigo_interno: product.codigo_interno.clone(),
+                error: e.to_string(),
+            }),
+        }
+        on_product(index + 1, products.len());
+    }
+
+    Ok(summary)
+}
+
+/// Creates or updates the product, then stocks its variants. Returns
+/// whether it was created and how many variants were stocked; a product
+/// whose stock could not be set fails as a whole, so it is not counted as
+/// seeded.
+async fn seed_product(
+    client: &MedusaClient,
+    product: &Product,
+    payload: Value,
+    location_id: Option<&str>,
+) -> Result<(bool, usize)> {
+    let created = upsert_product(client, payload).await?;
+    let stocked = match location_id {
+        Some(location_id) => stock_variants(client, product, location_id).await?,
+        None => 0,
+    };
+    Ok((created, stocked))
+}
+
+/// Creates the product of `payload`, or updates the one with its
+/// `external_id`. Returns whether it was created.
+async fn upsert_product(client: &MedusaClient, payload: Value) -> Result<bool> {
+    let external_id = payload["external_id"].as_str().unwrap_or_default().to_string();
+    let existing = client
+        .get("/admin/products", &[("external_id", &external_id), ("fields", LIVE_FIELDS)])
+        .await?;
+    match existing["products"].as_array().and_then(|products| products.first()) {
+        Some(live) => {
+            let id = live["id"].as_str().context("/admin/products returned no id")?;
+            client.post(&format!("/admin/products/{}", id), &update_payload(payload, live)).await?;
+            Ok(false)
+        }
+        None => {
+            client.post("/admin/products", &payload).await?;
+            Ok(true)
+        }
+    }
+}
+
+/// A creation payload turned into an update of the `live` product. Options
+/// and variants already in the store keep their ids, matched by title and
+/// SKU, so Medusa updates them rather than adding copies; prices keep the
+/// store's other currencies.
+fn update_payload(mut payload: Value, live: &Value) -> Value {
+    let by_key = |list: &Value, key: &str| -> HashMap<String, Value> {
+        list.as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|item| Some((item[key].as_str()?.to_string(), item.clone())))
+            .collect()
+    };
+    let options = by_key(&live["options"], "title");
+    let variants = by_key(&live["variants"], "sku");
+
+    for option in payload["options"].as_array_mut().into_iter().flatten() {
+        if let Some(id) = option["title"].as_str().and_then(|title| options.get(title)).map(|o| o["id"].clone()) {
+            option["id"] = id;
+        }
+    }
+    for variant in payload["variants"].as_array_mut().into_iter().flatten() {
+        let Some(current) = variant["sku"].as_str().and_then(|sku| variants.get(sku)) else { continue };
+        variant["id"] = current["id"].clone();
+        let prices = std::mem::take(&mut variant["prices"]);
+        let live_prices = current["prices"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        variant["prices"] = json!(prices
+            .as_array()
+            .into_iter()
+            .flatten()
+            .fold(live_prices.to_vec(), |prices, price| merge_price(&prices, price.clone())));
+    }
+    payload
+}
+
+/// Returns the first stock location, creating one linked to the default
+/// sales channel on a fresh store.
+async fn ensure_stock_location(client: &MedusaClient) -> Result<String> {
+    let existing = client.get("/admin/stock-locations", &[("fields", "id")]).await?;
+    if let Some(id) = existing["stock_locations"][0]["id"].as_str() {
+        return Ok(id.to_string());
+    }
+
+    let created = client.post("/admin/stock-locations", &json!({ "name": STOCK_LOCATION_NAME })).await?;
+    let id = created["stock_location"]["id"]
+        .as_str()
+        .context("/admin/stock-locations returned no id")?
+        .to_string();
+
+    let channels = client.get("/admin/sales-channels", &[("fields", "id")]).await?;
+    if let Some(channel) = channels["sales_channels"][0]["id"].as_str() {
+        client
+            .post(
+                &format!("/admin/stock-locations/{}/sales-channels", id),
+                &json!({ "add": [channel] }),
+            )
+            .await?;
+    }
+    Ok(id)
+}
+
+/// Fills in the inventory items Medusa created for the product's managed
+/// variants and sets their stock. Returns how many variants were stocked.
+async fn stock_variants(client: &MedusaClient, product: &Product, location_id: &str) -> Result<usize> {
+    let mut stocked = 0;
+    for variant in &product.variants {
+        let Some(estoque) = variant.estoque else { continue };
+
+        let items = client
+            .get("/admin/inventory-items", &[("sku", &variant.sku), ("fields", "id,*location_levels")])
+            .await?;
+        let item_id = items["inventory_items"][0]["id"]
+            .as_str()
+            .with_context(|| format!("No inventory item for sku {}", variant.sku))?;
+
+        client
+            .post(&format!("/admin/inventory-items/{}", item_id), &inventory_item_payload(variant))
+            .await?;
+        // A redeploy finds the level created by the first one
+        let has_level = items["inventory_items"][0]["location_levels"]
+            .as_array()
+            .is_some_and(|levels| levels.iter().any(|level| level["location_id"] == location_id));
+        if has_level {
+            client
+                .post(
+                    &format!("/admin/inventory-items/{}/location-levels/{}", item_id, location_id),
+                    &json!({ "stocked_quantity": estoque }),
+                )
+                .await?;
+        } else {
+            client
+                .post(
+                    &format!("/admin/inventory-items/{}/location-levels", item_id),
+                    &json!({ "location_id": location_id, "stocked_quantity": estoque }),
+                )
+                .await?;
+        }
+        stocked += 1;
+    }
+    Ok(stocked)
+}
+
+/// Looks an entity up by `filter` and creates it when missing, returning
+/// its id. `list_key` and `item_key` are the response fields holding the
+/// list and the created entity.
+async fn find_or_create(
+    client: &MedusaClient,
+    path: &str,
+    filter: (&str, &str),
+    list_key: &str,
+    item_key: &str,
+    payload: &serde_json::Value,
+) -> Result<String> {
+    let existing = client.get(path, &[filter, ("fields", "id")]).await?;
+    if let Some(id) = existing[list_key][0]["id"].as_str() {
+        return Ok(id.to_string());
+    }
+
+    let created = client.post(path, payload).await?;
+    created[item_key]["id"]
+        .as_str()
+        .map(str::to_string)
+        .with_context(|| format!("{} returned no {} id", path, item_key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::csv_parser::{Product, ProductOption, Variant};
+
+    fn product() -> Product {
+        let variant = |sku: &str, size: &str| Variant {
+            options: [("Tamanho".to_string(), size.to_string())].into_iter().collect(),
+            ..Variant::sample(sku)
+        };
+        Product {
+            options: vec![ProductOption { title: "Tamanho".to_string(), values: vec!["P".into(), "M".into()] }],
+            variants: vec![variant("CAM-01-P", "P"), variant("CAM-01-M", "M")],
+            ..Product::sample("CAM-01")
+        }
+    }
+
+    #[test]
+    fn update_keeps_the_store_ids_and_other_currencies() {
+        let live = json!({
+            "id": "prod_1",
+            "options": [{ "id": "opt_1", "title": "Tamanho" }],
+            "variants": [{
+                "id": "variant_p",
+                "sku": "CAM-01-P",
+                "prices": [
+                    { "id": "price_brl", "currency_code": "brl", "amount": 39.9 },
+                    { "id": "price_usd", "currency_code": "usd", "amount": 9.5 },
+                ],
+            }],
+        });
+
+        let payload = update_payload(product_payload(&product(), &[]), &live);
+        assert_eq!(payload["options"][0]["id"], "opt_1");
+        let small = &payload["variants"][0];
+        assert_eq!(small["id"], "variant_p");
+        assert_eq!(
+            small["prices"],
+            json!([
+                { "id": "price_usd", "currency_code": "usd", "amount": 9.5 },
+                { "id": "price_brl", "currency_code": "brl", "amount": 49.9 },
+            ])
+        );
+        // Not in the store yet, created by the update
+        let medium = &payload["variants"][1];
+        assert!(medium.get("id").is_none());
+        assert_eq!(medium["prices"], json!([{ "currency_code": "brl", "amount": 49.9 }]));
+        assert_eq!(payload["external_id"], "CAM-01");
+    }
+
+    #[test]
+    fn price_ids_are_only_set_when_the_store_has_one() {
+        let price = json!({ "currency_code": "brl", "amount": 10.0 });
+        assert_eq!(merge_price(&[], price.clone()), vec![price.clone()]);
+        let live = [json!({ "id": "price_1", "currency_code": "BRL", "amount": 5.0 })];
+        assert_eq!(merge_price(&live, price)[0]["id"], "price_1");
+    }
+}
@@ -0,0 +1,365 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+
+use super::seed::SeedFailure;
+use super::{merge_price, price_payload, seed_catalog, MedusaClient};
+use crate::modules::csv_parser::{Money, Product};
+use crate::modules::images::ImageManifest;
+
+const LIVE_FIELDS: &str = "id,title,description,status,external_id,metadata,*variants,*variants.prices";
+const PUBLISHED: &str = "published";
+/// Archived products are unpublished, not deleted, so orders keep them
+const ARCHIVED: &str = "draft";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Create,
+    Update,
+    Price,
+    Archive,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CatalogChange {
+    /// e.g. "price:CAM-01", sent back to approve the change
+    pub id: String,
+    pub kind: ChangeKind,
+    pub codigo_interno: String,
+    pub nome: String,
+    pub fields: Vec<FieldChange>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CatalogDiff {
+    pub changes: Vec<CatalogChange>,
+    /// Products in the file that match the store exactly
+    pub unchanged: usize,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SyncSummary {
+    pub created: usize,
+    pub updated: usize,
+    pub repriced: usize,
+    pub archived: usize,
+    pub failed: Vec<SeedFailure>,
+}
+
+/// The diff against the store and, when changes were approved, what
+/// applying them did.
+#[derive(Debug, Serialize)]
+pub struct CatalogSync {
+    pub diff: CatalogDiff,
+    pub applied: Option<SyncSummary>,
+}
+
+enum Action {
+    Create(usize),
+    Update { product_id: String, body: Value },
+    /// Each variant with its whole price set, as Medusa replaces it
+    Prices { product_id: String, variants: Vec<(String, Vec<Value>)> },
+    Archive { product_id: String },
+}
+
+struct Planned {
+    change: CatalogChange,
+    action: Action,
+}
+
+/// Compares `products` with the store's catalog. Without `approved` only
+/// the diff is returned; otherwise the changes whose ids are listed are
+/// applied. The diff is computed from a fresh read of the store either way,
+/// so changes made in the admin since the preview are not overwritten
+/// blindly.
+pub async fn sync_catalog(
+    client: &MedusaClient,
+    products: &[Product],
+    approved: Option<&[String]>,
+) -> Result<CatalogSync> {
+    let live = client
+        .list("/admin/products", "products", LIVE_FIELDS)
+        .await
+        .context("Failed to list the store's products")?;
+    let (planned, unchanged, warnings) = plan(products, &live);
+    let diff = CatalogDiff {
+        changes: planned.iter().map(|p| p.change.clone()).collect(),
+        unchanged,
+        warnings,
+    };
+
+    let Some(approved) = approved else {
+        return Ok(CatalogSync { diff, applied: None });
+    };
+    let approved: HashSet<&str> = approved.iter().map(String::as_str).collect();
+    let planned: Vec<Planned> = planned
+        .into_iter()
+        .filter(|p| approved.contains(p.change.id.as_str()))
+        .collect();
+
+    let applied = apply(client, products, planned).await?;
+    Ok(CatalogSync { diff, applied: Some(applied) })
+}
+
+/// Matches file products to store products by UUID (the store's
+/// `external_id`) or by `codigo_interno`, and lists what differs.
+fn plan(products: &[Product], live: &[Value]) -> (Vec<Planned>, usize, Vec<String>) {
+    let mut by_key: HashMap<&str, usize> = HashMap::new();
+    for (i, product) in live.iter().enumerate() {
+        for key in [&product["external_id"], &product["metadata"]["codigo_interno"]] {
+            if let Some(key) = key.as_str().filter(|k| !k.is_empty()) {
+                by_key.entry(key).or_insert(i);
+            }
+        }
+    }
+
+    let mut planned = Vec::new();
+    let mut matched = HashSet::new();
+    let mut unchanged = 0;
+    let mut warnings = Vec::new();
+
+    for (index, product) in products.iter().enumerate() {
+        let found = product
+            .uuid
+            .as_deref()
+            .and_then(|uuid| by_key.get(uuid))
+            .or_else(|| by_key.get(product.codigo_interno.as_str()));
+        let Some(&live_index) = found else {
+            planned.push(Planned {
+                change: change(ChangeKind::Create, product, Vec::new()),
+                action: Action::Create(index),
+            });
+            continue;
+        };
+        matched.insert(live_index);
+        let current = &live[live_index];
+        let product_id = current["id"].as_str().unwrap_or_default().to_string();
+        let mut changed = false;
+
+        let mut fields = Vec::new();
+        compare(&mut fields, "nome", text(&current["title"]), &product.nome);
+        compare(&mut fields, "descricao", text(&current["description"]), &product.descricao);
+        compare(&mut fields, "status", text(&current["status"]), PUBLISHED);
+        if !fields.is_empty() {
+            changed = true;
+            planned.push(Planned {
+                change: change(ChangeKind::Update, product, fields),
+                action: Action::Update {
+                    product_id: product_id.clone(),
+                    body: json!({
+                        "title": product.nome,
+                        "description": product.descricao,
+                        "status": PUBLISHED,
+                    }),
+                },
+            });
+        }
+
+        let mut fields = Vec::new();
+        let mut variants = Vec::new();
+        let live_variants = current["variants"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        for variant in &product.variants {
+            let Some(live_variant) = live_variants.iter().find(|v| text(&v["sku"]) == variant.sku) else {
+                warnings.push(format!(
+                    "Variant {} of {} is not in the store; new variants of existing products are not synced",
+                    variant.sku, product.codigo_interno
+                ));
+                continue;
+            };
+            let price = variant.preco.unwrap_or(product.preco);
+            let live_prices = live_variant["prices"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+            let current_price = live_prices
+                .iter()
+                .find(|p| text(&p["currency_code"]).eq_ignore_ascii_case(price.currency.code()))
+                // Medusa v2 amounts are in major units, reais rather than centavos
+                .and_then(|p| p["amount"].as_f64())
+                .and_then(|amount| Money::from_major_f64(amount, price.currency).ok());
+            if current_price != Some(price) {
+                fields.push(FieldChange {
+                    field: variant.sku.clone(),
+                    from: current_price.map(Money::to_ptbr_string).unwrap_or_default(),
+                    to: price.to_ptbr_string(),
+                });
+                let prices = merge_price(live_prices, price_payload(price));
+                variants.push((text(&live_variant["id"]).to_string(), prices));
+            }
+        }
+        if !variants.is_empty() {
+            changed = true;
+            planned.push(Planned {
+                change: change(ChangeKind::Price, product, fields),
+                action: Action::Prices { product_id, variants },
+            });
+        }
+
+        if !changed {
+            unchanged += 1;
+        }
+    }
+
+    for (i, current) in live.iter().enumerate() {
+        if matched.contains(&i) || text(&current["status"]) != PUBLISHED {
+            continue;
+        }
+        let codigo = [&current["metadata"]["codigo_interno"], &current["external_id"], &current["id"]]
+            .into_iter()
+            .find_map(|v| v.as_str().filter(|s| !s.is_empty()))
+            .unwrap_or_default();
+        planned.push(Planned {
+            change: CatalogChange {
+                id: format!("archive:{}", text(&current["id"])),
+                kind: ChangeKind::Archive,
+                codigo_interno: codigo.to_string(),
+                nome: text(&current["title"]).to_string(),
+                fields: vec![FieldChange {
+                    field: "status".to_string(),
+                    from: PUBLISHED.to_string(),
+                    to: ARCHIVED.to_string(),
+                }],
+            },
+            action: Action::Archive { product_id: text(&current["id"]).to_string() },
+        });
+    }
+
+    (planned, unchanged, warnings)
+}
+
+async fn apply(client: &MedusaClient, products: &[Product], planned: Vec<Planned>) -> Result<SyncSummary> {
+    let mut summary = SyncSummary::default();
+    let mut to_create = Vec::new();
+
+    for Planned { change, action } in planned {
+        let result = match action {
+            Action::Create(index) => {
+                to_create.push(products[index].clone());
+                continue;
+            }
+            Action::Update { product_id, body } => client
+                .post(&format!("/admin/products/{}", product_id), &body)
+                .await
+                .map(|_| summary.updated += 1),
+            Action::Prices { product_id, variants } => {
+                let mut result = Ok(());
+                for (variant_id, prices) in variants {
+                    let path = format!("/admin/products/{}/variants/{}", product_id, variant_id);
+                    result = client.post(&path, &json!({ "prices": prices })).await.map(|_| ());
+                    if result.is_err() {
+                        break;
+                    }
+                }
+                result.map(|_| summary.repriced += 1)
+            }
+            Action::Archive { product_id } => client
+                .post(&format!("/admin/products/{}", product_id), &json!({ "status": ARCHIVED }))
+                .await
+                .map(|_| summary.archived += 1),
+        };
+        if let Err(e) = result {
+            summary.failed.push(SeedFailure {
+                codigo_interno: change.codigo_interno,
+                error: e.to_string(),
+            });
+        }
+    }
+
+    if !to_create.is_empty() {
+        let seeded = seed_catalog(client, &to_create, &ImageManifest::default(), |_, _| {}).await?;
+        summary.created = seeded.created;
+        summary.updated += seeded.updated;
+        summary.failed.extend(seeded.failed);
+    }
+    Ok(summary)
+}
+
+fn change(kind: ChangeKind, product: &Product, fields: Vec<FieldChange>) -> CatalogChange {
+    let prefix = match kind {
+        ChangeKind::Create => "create",
+        ChangeKind::Update => "update",
+        ChangeKind::Price => "price",
+        ChangeKind::Archive => "archive",
+    };
+    CatalogChange {
+        id: format!("{}:{}", prefix, product.codigo_interno),
+        kind,
+        codigo_interno: product.codigo_interno.clone(),
+        nome: product.nome.clone(),
+        fields,
+    }
+}
+
+fn compare(fields: &mut Vec<FieldChange>, field: &str, from: &str, to: &str) {
+    if from.trim() != to.trim() {
+        fields.push(FieldChange {
+            field: field.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+    }
+}
+
+fn text(value: &Value) -> &str {
+    value.as_str().unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::csv_parser::Variant;
+
+    fn product(preco: Money) -> Product {
+        Product { preco, variants: vec![Variant::sample("CAM-01")], ..Product::sample("CAM-01") }
+    }
+
+    fn live(amount: f64) -> Value {
+        json!({
+            "id": "prod_1",
+            "title": "Camiseta",
+            "description": "",
+            "status": PUBLISHED,
+            "external_id": "CAM-01",
+            "variants": [{
+                "id": "variant_1",
+                "sku": "CAM-01",
+                "prices": [
+                    { "id": "price_brl", "currency_code": "brl", "amount": amount },
+                    { "id": "price_usd", "currency_code": "usd", "amount": 12.0 },
+                ],
+            }],
+        })
+    }
+
+    #[test]
+    fn live_amounts_are_read_in_major_units() {
+        let (planned, unchanged, _) = plan(&[product(Money::brl(4990))], &[live(49.9)]);
+        assert!(planned.is_empty());
+        assert_eq!(unchanged, 1);
+    }
+
+    #[test]
+    fn price_change_shows_reais() {
+        let (planned, _, _) = plan(&[product(Money::brl(5490))], &[live(49.9)]);
+        assert_eq!(planned.len(), 1);
+        let field = &planned[0].change.fields[0];
+        assert_eq!((field.from.as_str(), field.to.as_str()), ("49,90", "54,90"));
+        let Action::Prices { variants, .. } = &planned[0].action else { panic!("not a price change") };
+        assert_eq!(variants[0].0, "variant_1");
+        // The other currencies go back too, or Medusa would delete them
+        assert_eq!(
+            variants[0].1,
+            vec![
+                json!({ "id": "price_usd", "currency_code": "usd", "amount": 12.0 }),
+                json!({ "id": "price_brl", "currency_code": "brl", "amount": 54.9 }),
+            ]
+        );
+    }
+}
@@ -2,3 +2,5 @@ pub mod ssh;
 pub mod csv_parser;
 pub mod images;
 pub mod deployment;
+pub mod medusa;
+pub mod text;
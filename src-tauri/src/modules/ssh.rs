@@ -1,7 +1,8 @@
 use anyhow::{Context, Result};
 use ssh2::Session;
 use std::net::TcpStream;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 pub async fn connect(ip: &str) -> Result<String> {
     // For now, return a mock success
@@ -77,4 +78,20 @@ impl SshConnection {
         
         Ok(output)
     }
+    
+    pub fn upload(&self, remote_path: &str, contents: &[u8]) -> Result<()> {
+        let mut remote = self.session
+            .scp_send(Path::new(remote_path), 0o644, contents.len() as u64, None)
+            .with_context(|| format!("Failed to start upload of {}", remote_path))?;
+        
+        remote.write_all(contents)
+            .with_context(|| format!("Failed to upload {}", remote_path))?;
+        
+        remote.send_eof().context("Failed to finish upload")?;
+        remote.wait_eof().context("Failed to finish upload")?;
+        remote.close().context("Failed to close upload channel")?;
+        remote.wait_close().context("Failed to close upload channel")?;
+        
+        Ok(())
+    }
 }
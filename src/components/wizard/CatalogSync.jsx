@@ -0,0 +1,236 @@
+import React, { useState } from 'react';
+import {
+  Box,
+  Button,
+  Typography,
+  Paper,
+  Stack,
+  TextField,
+  Alert,
+  Checkbox,
+  Chip,
+  List,
+  ListItem,
+  ListItemIcon,
+  ListItemText,
+  CircularProgress,
+} from '@mui/material';
+import { Sync as SyncIcon, CheckCircle, Download } from '@mui/icons-material';
+import { invoke } from '@tauri-apps/api/core';
+import { save } from '@tauri-apps/plugin-dialog';
+import { useWizard } from '../../contexts/WizardContext';
+
+const KIND_LABELS = {
+  create: { label: 'Novo', color: 'success' },
+  update: { label: 'Alterado', color: 'info' },
+  price: { label: 'Preço', color: 'warning' },
+  archive: { label: 'Arquivar', color: 'error' },
+};
+
+const describe = (change) => {
+  if (change.kind === 'create') return 'Produto novo na planilha';
+  return change.fields
+    .map((f) => `${f.field}: ${f.from || '—'} → ${f.to || '—'}`)
+    .join(' · ');
+};
+
+// Compares the imported spreadsheet with the live store and applies only
+// the changes the user approves, without redeploying.
+export const CatalogSync = ({ isLocal, storeUrl, onReimport }) => {
+  const { wizardData } = useWizard();
+  const [access, setAccess] = useState({ url: isLocal ? '' : storeUrl, email: '', password: '' });
+  const [diff, setDiff] = useState(null);
+  const [selected, setSelected] = useState([]);
+  const [applied, setApplied] = useState(null);
+  const [exported, setExported] = useState(null);
+  const [loading, setLoading] = useState(false);
+  const [error, setError] = useState(null);
+
+  // Local stores are reached with the credentials saved at deploy time
+  const store = access.url && access.email ? access : null;
+
+  const run = async (approved) => {
+    setLoading(true);
+    setError(null);
+    try {
+      const result = await invoke('sync_catalog', {
+        identity: wizardData.identity,
+        store,
+        approved,
+      });
+      setDiff(result.diff);
+      setApplied(result.applied);
+      if (!approved) {
+        // Archiving is opt-in, everything else starts selected
+        setSelected(result.diff.changes.filter((c) => c.kind !== 'archive').map((c) => c.id));
+      }
+    } catch (err) {
+      setError(err.toString());
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  const exportCatalog = async () => {
+    const outputPath = await save({
+      defaultPath: 'catalogo.csv',
+      filters: [{ name: 'CSV', extensions: ['csv'] }],
+    });
+    if (!outputPath) return;
+
+    setLoading(true);
+    setError(null);
+    try {
+      const result = await invoke('export_catalog_csv', {
+        identity: wizardData.identity,
+        store,
+        outputPath,
+      });
+      setExported(result);
+    } catch (err) {
+      setError(err.toString());
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  const toggle = (id) => {
+    setSelected((prev) => (prev.includes(id) ? prev.filter((s) => s !== id) : [...prev, id]));
+  };
+
+  return (
+    <Paper
+      elevation={0}
+      sx={{
+        p: 3,
+        border: 1,
+        borderColor: 'divider',
+        borderRadius: 2,
+      }}
+    >
+      <Stack spacing={2}>
+        <Typography variant="subtitle1" fontWeight="bold">
+          🔄 Sincronizar Catálogo
+        </Typography>
+        <Typography variant="body2" color="text.secondary">
+          Recebeu uma planilha atualizada? Importe-a na etapa de produtos e compare com a loja no ar: apenas as
+          alterações aprovadas são aplicadas, sem reimplantar a infraestrutura.
+        </Typography>
+
+        <Stack direction={{ xs: 'column', md: 'row' }} spacing={2}>
+          <TextField
+            size="small"
+            label="URL da API da loja"
+            placeholder={isLocal ? 'Loja local' : 'https://...'}
+            value={access.url}
+            onChange={(e) => setAccess({ ...access, url: e.target.value })}
+            sx={{ flex: 2 }}
+          />
+          <TextField
+            size="small"
+            label="E-mail do admin"
+            value={access.email}
+            onChange={(e) => setAccess({ ...access, email: e.target.value })}
+            sx={{ flex: 1 }}
+          />
+          <TextField
+            size="small"
+            type="password"
+            label="Senha do admin"
+            value={access.password}
+            onChange={(e) => setAccess({ ...access, password: e.target.value })}
+            sx={{ flex: 1 }}
+          />
+        </Stack>
+        {isLocal && (
+          <Typography variant="caption" color="text.secondary">
+            Em lojas locais os campos podem ficar em branco: são usadas as credenciais geradas na implantação.
+          </Typography>
+        )}
+
+        <Box sx={{ display: 'flex', gap: 2 }}>
+          <Button variant="outlined" onClick={onReimport} disabled={loading}>
+            Importar planilha atualizada
+          </Button>
+          <Button
+            variant="contained"
+            onClick={() => run(null)}
+            disabled={loading || !wizardData.products.productCount}
+            startIcon={loading ? <CircularProgress size={20} color="inherit" /> : <SyncIcon />}
+          >
+            Comparar com a loja
+          </Button>
+          <Button variant="outlined" onClick={exportCatalog} disabled={loading} startIcon={<Download />}>
+            Exportar catálogo (CSV)
+          </Button>
+        </Box>
+
+        {exported && (
+          <Alert severity="success">
+            {exported.products} produto(s) em {exported.rows} linha(s) salvos em {exported.path}. Edite a planilha e
+            importe-a novamente para sincronizar.
+          </Alert>
+        )}
+
+        {error && <Alert severity="error">{error}</Alert>}
+
+        {diff && (
+          <Box>
+            <Typography variant="body2" sx={{ mb: 1 }}>
+              {diff.changes.length} alteração(ões) encontrada(s), {diff.unchanged} produto(s) sem mudanças.
+            </Typography>
+            {diff.warnings.map((warning, index) => (
+              <Alert key={index} severity="warning" sx={{ mb: 1 }}>{warning}</Alert>
+            ))}
+            {diff.changes.length > 0 && (
+              <List dense sx={{ maxHeight: 360, overflow: 'auto', border: 1, borderColor: 'divider', borderRadius: 1 }}>
+                {diff.changes.map((change) => (
+                  <ListItem key={change.id} button onClick={() => toggle(change.id)}>
+                    <ListItemIcon>
+                      <Checkbox edge="start" checked={selected.includes(change.id)} tabIndex={-1} disableRipple />
+                    </ListItemIcon>
+                    <ListItemText
+                      primary={
+                        <Box sx={{ display: 'flex', alignItems: 'center', gap: 1 }}>
+                          <Chip size="small" label={KIND_LABELS[change.kind].label} color={KIND_LABELS[change.kind].color} />
+                          <Typography variant="body2" fontFamily="monospace">{change.codigo_interno}</Typography>
+                          <Typography variant="body2">{change.nome}</Typography>
+                        </Box>
+                      }
+                      secondary={describe(change)}
+                      secondaryTypographyProps={{ noWrap: true }}
+                    />
+                  </ListItem>
+                ))}
+              </List>
+            )}
+            {diff.changes.length > 0 && (
+              <Button
+                variant="contained"
+                color="success"
+                sx={{ mt: 2 }}
+                onClick={() => run(selected)}
+                disabled={loading || selected.length === 0}
+                startIcon={<CheckCircle />}
+              >
+                Aplicar {selected.length} alteração(ões)
+              </Button>
+            )}
+          </Box>
+        )}
+
+        {applied && (
+          <Alert severity={applied.failed.length > 0 ? 'warning' : 'success'}>
+            {applied.created} criado(s), {applied.updated} atualizado(s), {applied.repriced} com preço novo,{' '}
+            {applied.archived} arquivado(s).
+            {applied.failed.map((f, index) => (
+              <Typography key={index} variant="body2">
+                Produto {f.codigo_interno}: {f.error}
+              </Typography>
+            ))}
+          </Alert>
+        )}
+      </Stack>
+    </Paper>
+  );
+};
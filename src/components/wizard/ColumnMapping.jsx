@@ -0,0 +1,205 @@
+import React, { useState } from 'react';
+import {
+  Box,
+  Button,
+  Typography,
+  Paper,
+  Stack,
+  Table,
+  TableBody,
+  TableCell,
+  TableContainer,
+  TableHead,
+  TableRow,
+  Alert,
+  TextField,
+  MenuItem,
+  Chip,
+} from '@mui/material';
+import { CheckCircle } from '@mui/icons-material';
+
+const IGNORE = '';
+const OPTION = '__option__';
+
+const FIELD_LABELS = {
+  codigo_interno: 'Código interno',
+  nome: 'Nome',
+  preco: 'Preço',
+  descricao: 'Descrição',
+  uuid: 'UUID',
+  codigo_pai: 'Código do produto pai',
+  sku: 'SKU da variante',
+  estoque: 'Estoque',
+  ean: 'EAN / código de barras',
+  ncm: 'NCM',
+  peso_g: 'Peso (g)',
+  altura_cm: 'Altura (cm)',
+  largura_cm: 'Largura (cm)',
+  comprimento_cm: 'Comprimento (cm)',
+  categoria: 'Categoria',
+  colecao: 'Coleção',
+  tags: 'Tags',
+  imagens: 'Imagens (URLs)',
+  opcao_tamanho: 'Opção: Tamanho',
+  opcao_cor: 'Opção: Cor',
+  opcao_voltagem: 'Opção: Voltagem',
+};
+
+// Same rules as the backend slugify, so "Cor Principal" becomes opcao_cor_principal
+const optionField = (header) =>
+  'opcao_' + header
+    .normalize('NFD')
+    .replace(/[\u0300-\u036f]/g, '')
+    .toLowerCase()
+    .replace(/[^a-z0-9]+/g, '_')
+    .replace(/^_+|_+$/g, '');
+
+// header -> field, from a saved mapping (field -> header) when it fits the
+// file, otherwise from the backend suggestions
+const initialFields = (preview, savedMapping) => {
+  const saved = savedMapping && Object.values(savedMapping).some((h) => preview.headers.includes(h))
+    ? savedMapping
+    : preview.mapping;
+  const fields = {};
+  preview.headers.forEach((header) => {
+    const entry = Object.entries(saved).find(([, source]) => source === header);
+    fields[header] = entry ? entry[0] : IGNORE;
+  });
+  return fields;
+};
+
+export const ColumnMapping = ({ preview, savedMapping, loading, onConfirm, onCancel, onSheetChange }) => {
+  const [fields, setFields] = useState(() => initialFields(preview, savedMapping));
+
+  const chosen = Object.values(fields).filter((f) => f !== IGNORE);
+  const missing = preview.fields
+    .filter((f) => f.required && !chosen.includes(f.name))
+    .map((f) => FIELD_LABELS[f.name] || f.name);
+  const duplicated = chosen.filter((f, i) => chosen.indexOf(f) !== i);
+
+  const handleChange = (header, value) => {
+    setFields((prev) => ({ ...prev, [header]: value === OPTION ? optionField(header) : value }));
+  };
+
+  const handleConfirm = () => {
+    const mapping = {};
+    Object.entries(fields).forEach(([header, field]) => {
+      if (field !== IGNORE) mapping[field] = header;
+    });
+    onConfirm(mapping);
+  };
+
+  const suggestionFor = (header) => preview.suggestions.find((s) => s.header === header);
+
+  return (
+    <Box>
+      <Stack spacing={2}>
+        <Typography variant="subtitle1" fontWeight="bold">
+          Mapeamento de colunas
+        </Typography>
+        <Typography variant="body2" color="text.secondary">
+          Indique o que cada coluna da planilha representa. As sugestões foram feitas a partir dos nomes das colunas;
+          colunas ignoradas não são importadas.
+        </Typography>
+
+        {preview.sheets?.length > 1 && (
+          <TextField
+            select
+            size="small"
+            label="Planilha"
+            value={preview.sheet || ''}
+            onChange={(e) => onSheetChange(e.target.value)}
+            disabled={loading}
+            sx={{ minWidth: 240 }}
+          >
+            {preview.sheets.map((name) => (
+              <MenuItem key={name} value={name}>{name}</MenuItem>
+            ))}
+          </TextField>
+        )}
+
+        <TableContainer component={Paper} variant="outlined">
+          <Table size="small">
+            <TableHead>
+              <TableRow sx={{ bgcolor: 'grey.50' }}>
+                <TableCell sx={{ fontWeight: 'bold' }}>Coluna do arquivo</TableCell>
+                <TableCell sx={{ fontWeight: 'bold' }}>Exemplos</TableCell>
+                <TableCell sx={{ fontWeight: 'bold', minWidth: 260 }}>Campo</TableCell>
+              </TableRow>
+            </TableHead>
+            <TableBody>
+              {preview.headers.map((header, index) => {
+                const field = fields[header];
+                const suggestion = suggestionFor(header);
+                const isOption = field.startsWith('opcao_') && !FIELD_LABELS[field];
+                return (
+                  <TableRow key={`${header}-${index}`}>
+                    <TableCell sx={{ fontFamily: 'monospace' }}>
+                      {header || <em>(sem nome)</em>}
+                      {suggestion?.field && suggestion.field === field && (
+                        <Chip label="sugerido" size="small" sx={{ ml: 1 }} />
+                      )}
+                    </TableCell>
+                    <TableCell sx={{
+                      maxWidth: 320,
+                      overflow: 'hidden',
+                      textOverflow: 'ellipsis',
+                      whiteSpace: 'nowrap',
+                      color: 'text.secondary',
+                    }}>
+                      {preview.samples.map((row) => row[index]).filter(Boolean).join(' | ')}
+                    </TableCell>
+                    <TableCell>
+                      <TextField
+                        select
+                        fullWidth
+                        size="small"
+                        value={isOption ? OPTION : field}
+                        onChange={(e) => handleChange(header, e.target.value)}
+                        disabled={loading}
+                      >
+                        <MenuItem value={IGNORE}><em>Ignorar</em></MenuItem>
+                        {preview.fields.map((f) => (
+                          <MenuItem key={f.name} value={f.name}>
+                            {FIELD_LABELS[f.name] || f.name}{f.required ? ' *' : ''}
+                          </MenuItem>
+                        ))}
+                        <MenuItem value={OPTION}>Opção de variante ({optionField(header)})</MenuItem>
+                      </TextField>
+                    </TableCell>
+                  </TableRow>
+                );
+              })}
+            </TableBody>
+          </Table>
+        </TableContainer>
+
+        {missing.length > 0 && (
+          <Alert severity="warning">
+            Campos obrigatórios sem coluna: {missing.join(', ')}
+          </Alert>
+        )}
+        {duplicated.length > 0 && (
+          <Alert severity="warning">
+            Cada campo só pode ser usado por uma coluna: {[...new Set(duplicated)].join(', ')}
+          </Alert>
+        )}
+
+        <Box sx={{ display: 'flex', gap: 2 }}>
+          <Button
+            variant="contained"
+            color="warning"
+            onClick={handleConfirm}
+            disabled={loading || missing.length > 0 || duplicated.length > 0}
+            startIcon={<CheckCircle />}
+          >
+            Importar com este mapeamento
+          </Button>
+          <Button variant="outlined" onClick={onCancel} disabled={loading}>
+            Cancelar
+          </Button>
+        </Box>
+      </Stack>
+    </Box>
+  );
+};
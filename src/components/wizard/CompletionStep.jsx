@@ -23,6 +23,7 @@ import {
 } from '@mui/icons-material';
 import { openUrl } from '@tauri-apps/plugin-opener';
 import { useWizard } from '../../contexts/WizardContext';
+import { CatalogSync } from './CatalogSync';
 
 export const CompletionStep = () => {
   const { wizardData, goToStep } = useWizard();
@@ -33,6 +34,8 @@ export const CompletionStep = () => {
   const webhookUrl = isLocal ? 'http://localhost:9000/api/webhooks/mercadopago' :
     (wizardData.deployment?.webhookUrl || `https://${wizardData.server.domain}/api/webhooks/mercadopago`);
   const adminUrl = `${storeUrl}/app`;
+  // The server step is skipped for local deployments
+  const productsStep = isLocal ? 5 : 6;
 
   const handleOpenStore = async () => {
     try {
@@ -297,7 +300,7 @@ export const CompletionStep = () => {
                     Produtos
                   </Typography>
                   <Typography variant="h4" fontWeight="bold">
-                    {wizardData.products.csvData.length}
+                    {wizardData.products.productCount}
                   </Typography>
                 </Grid>
                 <Grid item xs={6} md={3}>
@@ -327,6 +330,12 @@ export const CompletionStep = () => {
               </Grid>
             </Paper>
 
+            <CatalogSync
+              isLocal={isLocal}
+              storeUrl={storeUrl}
+              onReimport={() => goToStep(productsStep)}
+            />
+
             {/* Next Steps */}
             <Paper 
               elevation={0} 
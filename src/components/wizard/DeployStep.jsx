@@ -10,6 +10,7 @@ import {
   Grid,
   Alert,
   CircularProgress,
+  LinearProgress,
 } from '@mui/material';
 import {
   Rocket as RocketIcon,
@@ -26,6 +27,7 @@ export const DeployStep = () => {
   const [deploying, setDeploying] = useState(false);
   const [deploymentStatus, setDeploymentStatus] = useState('idle'); // idle, deploying, success, error
   const [logs, setLogs] = useState([]);
+  const [imageProgress, setImageProgress] = useState(null);
   const logsEndRef = useRef(null);
 
   const scrollToBottom = () => {
@@ -46,13 +48,18 @@ export const DeployStep = () => {
       setLogs(prev => [...prev, logEntry]);
     });
 
+    // One event per image as the workers finish them
+    const unlistenImages = listen('image-progress', (event) => setImageProgress(event.payload));
+
     return () => {
       unlisten.then(fn => fn());
+      unlistenImages.then(fn => fn());
     };
   }, []);
 
   const handleDeploy = async () => {
     setDeploying(true);
+    setImageProgress(null);
     setDeploymentStatus('deploying');
     setLogs([{ timestamp: new Date().toISOString(), message: '🚀 Iniciando implantação...' }]);
 
@@ -64,8 +71,8 @@ export const DeployStep = () => {
           identity: wizardData.identity,
           design: wizardData.design,
           payment: wizardData.payment,
-          products: wizardData.products.csvData,
           imagesMapping: wizardData.images.mapping,
+          imageOptions: wizardData.images.optimization,
         },
       });
 
@@ -168,7 +175,7 @@ export const DeployStep = () => {
                         Produtos:
                       </Typography>
                       <Typography variant="body1" fontWeight="bold">
-                        {wizardData.products.csvData.length} produtos
+                        {wizardData.products.productCount} produtos
                       </Typography>
                     </Grid>
                     <Grid item xs={12} md={6}>
@@ -253,6 +260,18 @@ export const DeployStep = () => {
                   </Alert>
                 )}
 
+                {deploying && imageProgress && imageProgress.current < imageProgress.total && (
+                  <Box sx={{ mb: 3 }}>
+                    <LinearProgress
+                      variant="determinate"
+                      value={(imageProgress.current / Math.max(imageProgress.total, 1)) * 100}
+                    />
+                    <Typography variant="caption" color="text.secondary">
+                      Otimizando imagens: {imageProgress.current} de {imageProgress.total} ({imageProgress.codigo})
+                    </Typography>
+                  </Box>
+                )}
+
                 {/* Terminal/Logs */}
                 <Box sx={{ mb: 3 }}>
                   <Typography variant="subtitle1" fontWeight="bold" sx={{ mb: 2 }}>
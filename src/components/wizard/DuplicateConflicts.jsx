@@ -0,0 +1,79 @@
+import React, { useState } from 'react';
+import {
+  Alert,
+  Box,
+  Button,
+  Checkbox,
+  Chip,
+  FormControlLabel,
+  MenuItem,
+  TextField,
+  Typography,
+} from '@mui/material';
+
+const KIND_LABELS = {
+  codigo: 'Código repetido',
+  sku: 'SKU repetido',
+  uuid: 'UUID repetido',
+  nome: 'Nome parecido',
+};
+
+const STRATEGIES = [
+  { value: 'merge', label: 'Mesclar (primeira linha, completada pelas outras)' },
+  { value: 'keep-first', label: 'Manter a primeira ocorrência' },
+  { value: 'keep-last', label: 'Manter a última ocorrência' },
+];
+
+// Lists the duplicates found in the import and re-imports the file with the
+// strategy the user picks, before anything is sent to the store
+export const DuplicateConflicts = ({ conflicts, count, resolution, loading, onResolve }) => {
+  const [strategy, setStrategy] = useState(resolution?.strategy || 'keep-first');
+  const [names, setNames] = useState(resolution?.names || false);
+  const pending = conflicts.filter((c) => !c.resolved).length;
+
+  return (
+    <Alert severity={pending > 0 ? 'warning' : 'info'} sx={{ mb: 3 }}>
+      <Typography variant="body2" fontWeight="bold">
+        {count} duplicidade(s) encontrada(s){pending < conflicts.length && `, ${conflicts.length - pending} resolvida(s)`}:
+      </Typography>
+      {conflicts.slice(0, 10).map((conflict, index) => (
+        <Box key={index} sx={{ display: 'flex', alignItems: 'center', gap: 1, mt: 0.5 }}>
+          <Chip size="small" label={KIND_LABELS[conflict.kind]} variant={conflict.resolved ? 'outlined' : 'filled'} />
+          <Typography variant="body2" fontFamily="monospace">
+            {conflict.value}
+          </Typography>
+          <Typography variant="body2" color="text.secondary">
+            linhas {conflict.lines.join(', ')}
+          </Typography>
+        </Box>
+      ))}
+      {count > 10 && (
+        <Typography variant="body2" sx={{ mt: 0.5 }}>
+          ... e mais {count - 10} duplicidade(s)
+        </Typography>
+      )}
+
+      <Box sx={{ display: 'flex', alignItems: 'center', gap: 2, mt: 2, flexWrap: 'wrap' }}>
+        <TextField
+          select
+          size="small"
+          label="Como resolver"
+          value={strategy}
+          onChange={(e) => setStrategy(e.target.value)}
+          sx={{ minWidth: 320 }}
+        >
+          {STRATEGIES.map((s) => (
+            <MenuItem key={s.value} value={s.value}>{s.label}</MenuItem>
+          ))}
+        </TextField>
+        <FormControlLabel
+          control={<Checkbox size="small" checked={names} onChange={(e) => setNames(e.target.checked)} />}
+          label="Incluir nomes parecidos"
+        />
+        <Button variant="outlined" color="inherit" size="small" onClick={() => onResolve({ strategy, names })} disabled={loading}>
+          Resolver e reimportar
+        </Button>
+      </Box>
+    </Alert>
+  );
+};
@@ -0,0 +1,95 @@
+import React, { useEffect, useState } from 'react';
+import {
+  Alert,
+  Box,
+  Button,
+  CircularProgress,
+  LinearProgress,
+  Typography,
+} from '@mui/material';
+import { CloudDownload } from '@mui/icons-material';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+
+// Downloads the image URLs of the products file into the local cache, where
+// they are handled like the images of the product folders
+export const ImageDownloads = ({ urlCount, report, onDownloaded }) => {
+  const [loading, setLoading] = useState(false);
+  const [progress, setProgress] = useState(null);
+  const [error, setError] = useState(null);
+
+  useEffect(() => {
+    const unlisten = listen('image-download-progress', (event) => setProgress(event.payload));
+    return () => {
+      unlisten.then(fn => fn());
+    };
+  }, []);
+
+  const handleDownload = async () => {
+    setLoading(true);
+    setError(null);
+    setProgress(null);
+    try {
+      onDownloaded(await invoke('download_product_images'));
+    } catch (err) {
+      setError(`Erro ao baixar imagens: ${err.toString()}`);
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  return (
+    <Alert severity={report?.failed?.length > 0 ? 'warning' : 'info'} icon={<CloudDownload />}>
+      <Typography variant="body2" fontWeight="bold">
+        O arquivo de produtos tem {urlCount} imagem(ns) por URL
+      </Typography>
+      <Typography variant="body2">
+        As imagens são baixadas para um cache local e otimizadas junto com as das pastas.
+        URLs que não puderem ser baixadas continuam apontando para o endereço original.
+      </Typography>
+
+      {loading && progress && (
+        <Box sx={{ mt: 2 }}>
+          <LinearProgress variant="determinate" value={(progress.current / Math.max(progress.total, 1)) * 100} />
+          <Typography variant="caption" color="text.secondary">
+            {progress.current} de {progress.total}
+          </Typography>
+        </Box>
+      )}
+
+      {report && !loading && (
+        <Typography variant="body2" sx={{ mt: 1 }}>
+          {report.downloaded} baixada(s), {report.cached} já em cache
+          {report.failed.length > 0 && `, ${report.failed.length} com erro`}
+        </Typography>
+      )}
+      {report?.failed?.slice(0, 10).map((failure, index) => (
+        <Typography key={index} variant="body2" sx={{ fontFamily: 'monospace', wordBreak: 'break-all' }}>
+          {failure.codigos.join(', ')}: {failure.url} ({failure.error})
+        </Typography>
+      ))}
+      {report?.failed?.length > 10 && (
+        <Typography variant="body2">
+          ... e mais {report.failed.length - 10} erro(s)
+        </Typography>
+      )}
+      {error && (
+        <Typography variant="body2" color="error" sx={{ mt: 1 }}>
+          {error}
+        </Typography>
+      )}
+
+      <Button
+        variant="outlined"
+        color="inherit"
+        size="small"
+        sx={{ mt: 2 }}
+        onClick={handleDownload}
+        disabled={loading}
+        startIcon={loading ? <CircularProgress size={16} color="inherit" /> : <CloudDownload />}
+      >
+        {report ? 'Baixar novamente' : 'Baixar imagens das URLs'}
+      </Button>
+    </Alert>
+  );
+};
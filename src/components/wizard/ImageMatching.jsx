@@ -0,0 +1,101 @@
+import React from 'react';
+import {
+  Box,
+  Checkbox,
+  FormControlLabel,
+  MenuItem,
+  Stack,
+  TextField,
+  Typography,
+} from '@mui/material';
+
+export const DEFAULT_MATCHING = {
+  rules: [{ kind: 'folder' }],
+  maxDepth: 1,
+};
+
+const RULES = [
+  { kind: 'folder', label: 'Pasta com o código do produto', example: '123/foto.jpg ou Marca/123/foto.jpg' },
+  { kind: 'prefix', label: 'Nome do arquivo começa com o código', example: '123.jpg, 123_1.jpg, 123-frente.png' },
+  { kind: 'pattern', label: 'Padrão personalizado', example: 'fotos/**/SKU-{codigo}_*.jpg' },
+];
+
+// How image files are matched to products: the rules are tried in order and
+// the first one that finds a product code wins
+export const ImageMatching = ({ value, onChange, disabled }) => {
+  const matching = value || DEFAULT_MATCHING;
+  const enabled = (kind) => matching.rules.find((rule) => rule.kind === kind);
+  const pattern = enabled('pattern')?.pattern ?? '**/{codigo}_*.*';
+
+  const toggle = (kind, checked) => {
+    const rules = RULES
+      .filter((rule) => (rule.kind === kind ? checked : enabled(rule.kind)))
+      .map((rule) => (rule.kind === 'pattern' ? { kind: 'pattern', pattern } : enabled(rule.kind) || { kind: rule.kind }));
+    onChange({ ...matching, rules });
+  };
+
+  const setPattern = (newPattern) => {
+    onChange({
+      ...matching,
+      rules: matching.rules.map((rule) => (rule.kind === 'pattern' ? { ...rule, pattern: newPattern } : rule)),
+    });
+  };
+
+  return (
+    <Box>
+      <Typography variant="subtitle2" fontWeight="bold" gutterBottom>
+        Como associar as imagens aos produtos
+      </Typography>
+      <Stack spacing={0.5}>
+        {RULES.map((rule) => (
+          <Box key={rule.kind}>
+            <FormControlLabel
+              control={
+                <Checkbox
+                  size="small"
+                  checked={Boolean(enabled(rule.kind))}
+                  onChange={(e) => toggle(rule.kind, e.target.checked)}
+                  disabled={disabled}
+                />
+              }
+              label={
+                <Typography variant="body2">
+                  {rule.label}{' '}
+                  <Typography component="span" variant="caption" color="text.secondary" sx={{ fontFamily: 'monospace' }}>
+                    ({rule.example})
+                  </Typography>
+                </Typography>
+              }
+            />
+            {rule.kind === 'pattern' && enabled('pattern') && (
+              <TextField
+                size="small"
+                fullWidth
+                value={pattern}
+                onChange={(e) => setPattern(e.target.value)}
+                disabled={disabled}
+                helperText="* e ? dentro de uma pasta, ** em qualquer subpasta, {codigo} marca o código do produto"
+                sx={{ ml: 4, maxWidth: 520, '& input': { fontFamily: 'monospace' } }}
+              />
+            )}
+          </Box>
+        ))}
+      </Stack>
+      <TextField
+        select
+        size="small"
+        label="Profundidade das subpastas"
+        value={matching.maxDepth}
+        onChange={(e) => onChange({ ...matching, maxDepth: e.target.value })}
+        disabled={disabled}
+        sx={{ mt: 2, minWidth: 240 }}
+      >
+        {[0, 1, 2, 3, 4, 5].map((depth) => (
+          <MenuItem key={depth} value={depth}>
+            {depth === 0 ? 'Somente a pasta raiz' : `Até ${depth} nível(is)`}
+          </MenuItem>
+        ))}
+      </TextField>
+    </Box>
+  );
+};
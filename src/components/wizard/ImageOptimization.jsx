@@ -0,0 +1,111 @@
+import React from 'react';
+import {
+  Box,
+  Chip,
+  MenuItem,
+  Slider,
+  Stack,
+  TextField,
+  Typography,
+} from '@mui/material';
+
+export const DEFAULT_OPTIMIZATION = {
+  maxWidth: 1600,
+  widths: [200, 400, 800, 1600],
+  thumbnail: 300,
+  quality: 82,
+};
+
+const WIDTHS = [800, 1200, 1600, 2000, 2400];
+const RENDITION_WIDTHS = [200, 400, 600, 800, 1200, 1600];
+const THUMBNAILS = [150, 200, 300, 400];
+
+// How the images are prepared for the store during the deploy: a full size
+// for zoom up to the maximum width, smaller copies for listings and product
+// pages and a square thumbnail, each saved as WebP with a JPEG copy
+export const ImageOptimization = ({ value, onChange, disabled }) => {
+  const optimization = { ...DEFAULT_OPTIMIZATION, ...value };
+
+  const toggleWidth = (width) => {
+    const widths = optimization.widths.includes(width)
+      ? optimization.widths.filter((w) => w !== width)
+      : [...optimization.widths, width].sort((a, b) => a - b);
+    onChange({ ...optimization, widths });
+  };
+
+  return (
+    <Box>
+      <Typography variant="subtitle2" fontWeight="bold" gutterBottom>
+        Otimização das imagens
+      </Typography>
+      <Typography variant="body2" color="text.secondary" gutterBottom>
+        Na implantação as imagens são giradas conforme a câmera, convertidas para sRGB, têm os metadados (incluindo
+        localização GPS) removidos e são salvas em WebP com uma cópia JPEG. Imagens já otimizadas não são processadas
+        de novo.
+      </Typography>
+      <Stack direction="row" spacing={4} alignItems="center" sx={{ mt: 2 }}>
+        <TextField
+          select
+          size="small"
+          label="Tamanho ampliado (zoom)"
+          value={optimization.maxWidth}
+          onChange={(e) => onChange({ ...optimization, maxWidth: e.target.value })}
+          disabled={disabled}
+          sx={{ minWidth: 180 }}
+        >
+          {WIDTHS.map((width) => (
+            <MenuItem key={width} value={width}>
+              {width} px
+            </MenuItem>
+          ))}
+        </TextField>
+        <TextField
+          select
+          size="small"
+          label="Miniatura quadrada"
+          value={optimization.thumbnail}
+          onChange={(e) => onChange({ ...optimization, thumbnail: e.target.value })}
+          disabled={disabled}
+          sx={{ minWidth: 180 }}
+        >
+          {THUMBNAILS.map((side) => (
+            <MenuItem key={side} value={side}>
+              {side} x {side} px
+            </MenuItem>
+          ))}
+        </TextField>
+        <Box sx={{ flex: 1, maxWidth: 320 }}>
+          <Typography variant="caption" color="text.secondary">
+            Qualidade (JPEG e WebP): {optimization.quality}
+          </Typography>
+          <Slider
+            size="small"
+            min={50}
+            max={100}
+            value={optimization.quality}
+            onChange={(_, quality) => onChange({ ...optimization, quality })}
+            disabled={disabled}
+          />
+        </Box>
+      </Stack>
+      <Box sx={{ mt: 2 }}>
+        <Typography variant="caption" color="text.secondary" display="block" gutterBottom>
+          Tamanhos menores gerados para listagens e páginas de produto (maiores que a imagem original são ignorados)
+        </Typography>
+        <Stack direction="row" spacing={1}>
+          {RENDITION_WIDTHS.filter((width) => width <= optimization.maxWidth).map((width) => (
+            <Chip
+              key={width}
+              label={`${width} px`}
+              size="small"
+              color={optimization.widths.includes(width) ? 'primary' : 'default'}
+              variant={optimization.widths.includes(width) ? 'filled' : 'outlined'}
+              onClick={() => toggleWidth(width)}
+              disabled={disabled}
+            />
+          ))}
+        </Stack>
+      </Box>
+    </Box>
+  );
+};
@@ -30,12 +30,53 @@ import {
 import { open } from '@tauri-apps/plugin-dialog';
 import { invoke } from '@tauri-apps/api/core';
 import { useWizard } from '../../contexts/WizardContext';
+import { ImageDownloads } from './ImageDownloads';
+import { ImageMatching, DEFAULT_MATCHING } from './ImageMatching';
+import { ImageOptimization } from './ImageOptimization';
+
+const PROBLEM_LABELS = {
+  corrupt: 'arquivo corrompido',
+  unsupported: 'formato não suportado',
+  cmyk: 'cores CMYK',
+  animated: 'imagem animada',
+  'too-small': 'muito pequena',
+  'too-large': 'grande demais',
+};
+
+const RULE_LABELS = {
+  folder: 'Pasta',
+  prefix: 'Nome',
+  pattern: 'Padrão',
+};
 
 export const ImagesStep = () => {
   const { wizardData, updateWizardData, nextStep, prevStep } = useWizard();
   const [loading, setLoading] = useState(false);
   const [error, setError] = useState(null);
   const [mapping, setMapping] = useState(null);
+  const matching = wizardData.images.matching || DEFAULT_MATCHING;
+
+  const scan = async (directory) => {
+    const result = await invoke('scan_images_directory', { directory, matching });
+    setMapping(result);
+    updateWizardData('images', {
+      directory,
+      matching,
+      mapping: result,
+    });
+  };
+
+  const handleDownloaded = async (report) => {
+    updateWizardData('images', { downloads: report });
+    // Downloaded images count as the products' images in the scan
+    if (wizardData.images.directory) {
+      try {
+        await scan(wizardData.images.directory);
+      } catch (err) {
+        setError(`Erro ao escanear diretório: ${err.toString()}`);
+      }
+    }
+  };
 
   const handleDirectorySelect = async () => {
     try {
@@ -49,16 +90,7 @@ export const ImagesStep = () => {
         setError(null);
         
         // Scan directory and map images to products
-        const result = await invoke('scan_images_directory', {
-          directory: selected,
-          products: wizardData.products.csvData,
-        });
-        
-        setMapping(result);
-        updateWizardData('images', {
-          directory: selected,
-          mapping: result,
-        });
+        await scan(selected);
         
         setLoading(false);
       }
@@ -68,7 +100,20 @@ export const ImagesStep = () => {
     }
   };
 
+  const handleRescan = async () => {
+    setLoading(true);
+    setError(null);
+    try {
+      await scan(wizardData.images.directory);
+    } catch (err) {
+      setError(`Erro ao escanear diretório: ${err.toString()}`);
+    } finally {
+      setLoading(false);
+    }
+  };
+
   const hasImages = wizardData.images.directory && wizardData.images.mapping;
+  const hasDownloads = Boolean(wizardData.images.downloads);
   const mappingData = wizardData.images.mapping || mapping;
 
   return (
@@ -84,8 +129,8 @@ export const ImagesStep = () => {
             </Box>
             
             <Typography variant="body1" color="text.secondary">
-              Selecione o diretório raiz contendo as imagens dos produtos. Cada subpasta deve ter o nome
-              igual ao <strong>codigo_interno</strong> do produto e conter as imagens correspondentes.
+              Selecione o diretório raiz contendo as imagens dos produtos. Por padrão, cada subpasta deve ter o nome
+              igual ao <strong>codigo_interno</strong> do produto; abaixo é possível usar o nome dos arquivos ou um padrão.
             </Typography>
 
             <Paper 
@@ -138,6 +183,26 @@ export const ImagesStep = () => {
               </Typography>
             </Paper>
 
+            <ImageMatching
+              value={matching}
+              onChange={(value) => updateWizardData('images', { matching: value })}
+              disabled={loading}
+            />
+
+            <ImageOptimization
+              value={wizardData.images.optimization}
+              onChange={(value) => updateWizardData('images', { optimization: value })}
+              disabled={loading}
+            />
+
+            {wizardData.products.imageUrls > 0 && (
+              <ImageDownloads
+                urlCount={wizardData.products.imageUrls}
+                report={wizardData.images.downloads}
+                onDownloaded={handleDownloaded}
+              />
+            )}
+
             {!hasImages ? (
               <Box sx={{ textAlign: 'center', py: 8 }}>
                 <FolderOpen sx={{ fontSize: 80, color: 'text.disabled', mb: 2 }} />
@@ -247,7 +312,7 @@ export const ImagesStep = () => {
                     {mappingData.missingFolders && mappingData.missingFolders.length > 0 && (
                       <Box sx={{ mt: 2 }}>
                         <Typography variant="caption" fontWeight="bold">
-                          Pastas não encontradas:
+                          Códigos sem imagens:
                         </Typography>
                         <Box sx={{ display: 'flex', flexWrap: 'wrap', gap: 1, mt: 1 }}>
                           {mappingData.missingFolders.slice(0, 10).map((folder, i) => (
@@ -287,6 +352,77 @@ export const ImagesStep = () => {
                   </Alert>
                 )}
 
+                {mappingData?.invalidImages > 0 && (
+                  <Alert 
+                    severity="warning"
+                    icon={<AlertCircleIcon />}
+                    sx={{ mb: 3 }}
+                  >
+                    <Typography variant="body2" fontWeight="bold">
+                      {mappingData.invalidImages} imagem(ns) inválida(s) não serão usadas
+                    </Typography>
+                    {mappingData.details
+                      .flatMap((item) => (item.invalid || []).map((invalid) => ({ ...invalid, codigo: item.codigo_interno })))
+                      .slice(0, 10)
+                      .map((invalid, i) => (
+                        <Typography key={i} variant="body2" sx={{ wordBreak: 'break-all' }}>
+                          <strong>{invalid.codigo}</strong>: <span style={{ fontFamily: 'monospace' }}>{invalid.path}</span> ({PROBLEM_LABELS[invalid.problem]})
+                        </Typography>
+                      ))}
+                    {mappingData.invalidImages > 10 && (
+                      <Typography variant="caption" color="text.secondary">
+                        +{mappingData.invalidImages - 10} mais
+                      </Typography>
+                    )}
+                  </Alert>
+                )}
+
+                {mappingData?.unmatchedFiles?.length > 0 && (
+                  <Alert 
+                    severity="info"
+                    icon={<AlertCircleIcon />}
+                    sx={{ mb: 3 }}
+                  >
+                    <Typography variant="body2" fontWeight="bold">
+                      {mappingData.unmatchedFiles.length} imagem(ns) sem produto correspondente
+                    </Typography>
+                    <Typography variant="body2" sx={{ fontFamily: 'monospace', wordBreak: 'break-all' }}>
+                      {mappingData.unmatchedFiles.slice(0, 10).join(', ')}
+                      {mappingData.unmatchedFiles.length > 10 && ` +${mappingData.unmatchedFiles.length - 10} mais`}
+                    </Typography>
+                  </Alert>
+                )}
+
+                {mappingData?.deepFolders?.length > 0 && (
+                  <Alert 
+                    severity="info"
+                    icon={<AlertCircleIcon />}
+                    sx={{ mb: 3 }}
+                  >
+                    <Typography variant="body2" fontWeight="bold">
+                      {mappingData.deepFolders.length} subpasta(s) além da profundidade configurada não foram lidas
+                    </Typography>
+                    <Typography variant="body2" sx={{ fontFamily: 'monospace', wordBreak: 'break-all' }}>
+                      {mappingData.deepFolders.slice(0, 10).join(', ')}
+                    </Typography>
+                  </Alert>
+                )}
+
+                {mappingData?.duplicateCodes?.length > 0 && (
+                  <Alert 
+                    severity="warning"
+                    icon={<AlertCircleIcon />}
+                    sx={{ mb: 3 }}
+                  >
+                    <Typography variant="body2" fontWeight="bold">
+                      {mappingData.duplicateCodes.length} código(s) repetido(s): {mappingData.duplicateCodes.slice(0, 10).join(', ')}
+                    </Typography>
+                    <Typography variant="body2">
+                      Produtos com o mesmo código dividem a mesma pasta de imagens. Resolva as duplicidades na etapa de produtos.
+                    </Typography>
+                  </Alert>
+                )}
+
                 {/* Details Table */}
                 {mappingData?.details && mappingData.details.length > 0 && (
                   <Box sx={{ mb: 3 }}>
@@ -313,6 +449,9 @@ export const ImagesStep = () => {
                             <TableCell sx={{ fontWeight: 'bold', textTransform: 'uppercase', fontSize: '0.75rem', bgcolor: 'grey.50' }}>
                               Imagens
                             </TableCell>
+                            <TableCell sx={{ fontWeight: 'bold', textTransform: 'uppercase', fontSize: '0.75rem', bgcolor: 'grey.50' }}>
+                              Regra
+                            </TableCell>
                             <TableCell sx={{ fontWeight: 'bold', textTransform: 'uppercase', fontSize: '0.75rem', bgcolor: 'grey.50' }}>
                               Status
                             </TableCell>
@@ -334,6 +473,24 @@ export const ImagesStep = () => {
                               </TableCell>
                               <TableCell>{item.imageCount}</TableCell>
                               <TableCell>
+                                {[...new Set((item.files || []).map((file) => file.rule))].map((rule) => (
+                                  <Chip key={rule} label={RULE_LABELS[rule]} size="small" variant="outlined" sx={{ mr: 0.5 }} />
+                                ))}
+                                {item.downloadedCount > 0 && (
+                                  <Chip label="URL" size="small" variant="outlined" />
+                                )}
+                              </TableCell>
+                              <TableCell>
+                                {item.invalid?.length > 0 && (
+                                  <Chip
+                                    label={`${item.invalid.length} inválida(s)`}
+                                    size="small"
+                                    color="error"
+                                    variant="outlined"
+                                    title={item.invalid.map((invalid) => `${invalid.path}: ${invalid.message}`).join('\n')}
+                                    sx={{ mr: 0.5 }}
+                                  />
+                                )}
                                 {item.imageCount > 0 ? (
                                   <Chip
                                     icon={<CheckCircle sx={{ fontSize: 16 }} />}
@@ -360,14 +517,25 @@ export const ImagesStep = () => {
                   </Box>
                 )}
 
-                <Button
-                  variant="outlined"
-                  size="small"
-                  onClick={handleDirectorySelect}
-                  startIcon={<FolderOpen />}
-                >
-                  Selecionar outro diretório
-                </Button>
+                <Stack direction="row" spacing={2}>
+                  <Button
+                    variant="outlined"
+                    size="small"
+                    onClick={handleDirectorySelect}
+                    startIcon={<FolderOpen />}
+                  >
+                    Selecionar outro diretório
+                  </Button>
+                  <Button
+                    variant="outlined"
+                    size="small"
+                    onClick={handleRescan}
+                    disabled={loading}
+                    startIcon={loading ? <CircularProgress size={16} color="inherit" /> : <ImageIcon />}
+                  >
+                    Escanear novamente com estas regras
+                  </Button>
+                </Stack>
               </Box>
             )}
 
@@ -400,7 +568,7 @@ export const ImagesStep = () => {
                 size="large"
                 color="info"
                 onClick={nextStep}
-                disabled={!hasImages}
+                disabled={!hasImages && !hasDownloads}
                 endIcon={<ArrowForward />}
               >
                 Continuar
@@ -1,4 +1,4 @@
-import React, { useState } from 'react';
+import React, { useState, useEffect } from 'react';
 import {
   Box,
   Card,
@@ -16,6 +16,10 @@ import {
   TableRow,
   Alert,
   CircularProgress,
+  LinearProgress,
+  TablePagination,
+  TextField,
+  MenuItem,
 } from '@mui/material';
 import {
   Inventory as PackageIcon,
@@ -28,77 +32,172 @@ import {
 } from '@mui/icons-material';
 import { open } from '@tauri-apps/plugin-dialog';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { useWizard } from '../../contexts/WizardContext';
-import Papa from 'papaparse';
+import { ColumnMapping } from './ColumnMapping';
+import { DuplicateConflicts } from './DuplicateConflicts';
+
+const PLATFORM_NAMES = {
+  woocommerce: 'WooCommerce',
+  shopify: 'Shopify',
+  nuvemshop: 'Nuvemshop',
+  tray: 'Tray',
+};
 
 export const ProductsStep = () => {
   const { wizardData, updateWizardData, nextStep, prevStep } = useWizard();
   const [loading, setLoading] = useState(false);
   const [error, setError] = useState(null);
-  const [preview, setPreview] = useState([]);
+  // One page of the imported products; the full catalog stays in the backend
+  const [preview, setPreview] = useState({ products: [], page: 0, pageSize: 20, total: 0 });
+  const [progress, setProgress] = useState(null);
+  // Headers, samples and suggested mapping of the file being imported
+  const [mappingPreview, setMappingPreview] = useState(null);
+  const [pendingFile, setPendingFile] = useState(null);
+  const [uuidFile, setUuidFile] = useState(null);
+
+  useEffect(() => {
+    const unlisten = listen('import-progress', (event) => setProgress(event.payload));
+    return () => {
+      unlisten.then(fn => fn());
+    };
+  }, []);
+
+  useEffect(() => {
+    if (wizardData.products.productCount > 0) {
+      loadPage(0, preview.pageSize);
+    }
+  }, []);
+
+  const loadPage = async (page, pageSize) => {
+    try {
+      setPreview(await invoke('imported_products_page', { page, pageSize }));
+    } catch (err) {
+      setError(`Erro ao carregar produtos: ${err.toString()}`);
+    }
+  };
+
+  const previewFile = async (path, sheet = null) => {
+    setLoading(true);
+    setError(null);
+    try {
+      const headerPreview = await invoke('preview_products_file', { path, sheet });
+      setPendingFile(path);
+      if (headerPreview.platform) {
+        // Exports of other platforms have known columns, no mapping needed
+        await loadFile(path, headerPreview.sheet, null, headerPreview.platform);
+        return;
+      }
+      setMappingPreview(headerPreview);
+    } catch (err) {
+      setError(`Erro ao ler arquivo: ${err.toString()}`);
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  const loadFile = async (
+    path,
+    sheet = null,
+    mapping = wizardData.products.columnMapping,
+    platform = null,
+    duplicates = wizardData.products.duplicates || null,
+  ) => {
+    setLoading(true);
+    setError(null);
+    setProgress(null);
+    try {
+      // Stream and validate the file (CSV or spreadsheet) in the backend,
+      // which keeps the products and returns a summary
+      const summary = await invoke('import_products_file', {
+        path,
+        options: { sheet, mapping: platform ? null : mapping || null, platform, duplicates },
+      });
+
+      if (summary.products === 0) {
+        setError(`Nenhum produto válido encontrado (${summary.rejectedRows} linha(s) com erro)`);
+        return;
+      }
+
+      updateWizardData('products', {
+        productCount: summary.products,
+        variantCount: summary.variants,
+        csvFile: path,
+        sheet: summary.sheet,
+        sheets: summary.sheets,
+        columnMapping: platform ? wizardData.products.columnMapping || null : mapping || null,
+        platform: summary.platform,
+        format: summary.format,
+        generatedUuids: summary.generatedUuids,
+        imageUrls: summary.imageUrls,
+        diagnostics: summary.diagnostics,
+        diagnosticCount: summary.diagnosticCount,
+        conflicts: summary.conflicts,
+        conflictCount: summary.conflictCount,
+        duplicates,
+        taxonomy: {
+          categories: summary.categories,
+          collections: summary.collections,
+          tags: summary.tags,
+        },
+      });
+
+      setPreview(summary.preview);
+      setMappingPreview(null);
+      setUuidFile(null);
+    } catch (err) {
+      setError(`Erro ao processar arquivo: ${err.toString()}`);
+    } finally {
+      setLoading(false);
+      setProgress(null);
+    }
+  };
+
+  // Saves a copy of the CSV with the generated UUIDs, so the next import
+  // (and redeploy) keeps the same identifiers
+  const handleWriteUuids = async () => {
+    setLoading(true);
+    setError(null);
+    try {
+      const { csvFile, sheet, columnMapping } = wizardData.products;
+      const result = await invoke('write_products_uuids', {
+        path: csvFile,
+        options: { sheet, mapping: columnMapping || null },
+      });
+      updateWizardData('products', {
+        csvFile: result.path,
+        generatedUuids: 0,
+        columnMapping: columnMapping && !columnMapping.uuid
+          ? { ...columnMapping, uuid: result.uuidHeader }
+          : columnMapping,
+      });
+      setUuidFile(result.path);
+    } catch (err) {
+      setError(`Erro ao salvar UUIDs: ${err.toString()}`);
+    } finally {
+      setLoading(false);
+    }
+  };
 
   const handleFileSelect = async () => {
     try {
       const selected = await open({
         multiple: false,
         filters: [{
-          name: 'CSV',
-          extensions: ['csv']
+          name: 'Planilhas',
+          extensions: ['csv', 'xlsx', 'xls', 'ods']
         }]
       });
 
       if (selected) {
-        setLoading(true);
-        setError(null);
-        
-        // Read and parse CSV file
-        const result = await invoke('read_csv_file', { path: selected });
-        
-        Papa.parse(result, {
-          header: true,
-          skipEmptyLines: true,
-          complete: (results) => {
-            // Validate required columns
-            const requiredColumns = ['nome', 'preco', 'descricao'];
-            const headers = results.meta.fields || [];
-            const missingColumns = requiredColumns.filter(col => !headers.includes(col));
-            
-            if (missingColumns.length > 0) {
-              setError(`Colunas obrigatórias faltando: ${missingColumns.join(', ')}`);
-              setLoading(false);
-              return;
-            }
-            
-            // Process data
-            const products = results.data.map((row, index) => ({
-              uuid: row.uuid || `product-${Date.now()}-${index}`,
-              codigo_interno: row.codigo_interno || `${index + 1}`,
-              nome: row.nome,
-              preco: parseFloat(row.preco) || 0,
-              descricao: row.descricao,
-            }));
-            
-            updateWizardData('products', {
-              csvData: products,
-              csvFile: selected,
-            });
-            
-            setPreview(products.slice(0, 5));
-            setLoading(false);
-          },
-          error: (err) => {
-            setError(`Erro ao processar CSV: ${err.message}`);
-            setLoading(false);
-          }
-        });
+        await previewFile(selected);
       }
     } catch (err) {
       setError(`Erro ao selecionar arquivo: ${err.toString()}`);
-      setLoading(false);
     }
   };
 
-  const canContinue = wizardData.products.csvData && wizardData.products.csvData.length > 0;
+  const canContinue = wizardData.products.productCount > 0;
 
   return (
     <Box sx={{ maxWidth: 1200, mx: 'auto', py: 4 }}>
@@ -113,7 +212,9 @@ export const ProductsStep = () => {
             </Box>
             
             <Typography variant="body1" color="text.secondary">
-              Importe seus produtos através de um arquivo CSV. O arquivo deve conter os seguintes campos:
+              Importe seus produtos através de um arquivo CSV ou de uma planilha (XLSX, XLS ou ODS). As colunas podem ter
+              qualquer nome: depois de escolher o arquivo você indica qual coluna corresponde a cada campo. Exportações de
+              produtos do WooCommerce, Shopify, Nuvemshop e Tray são reconhecidas automaticamente. Campos:
             </Typography>
 
             <Paper 
@@ -148,10 +249,10 @@ export const ProductsStep = () => {
                 </Grid>
                 <Grid item xs={12} md={4}>
                   <Typography variant="body2" fontFamily="monospace" fontWeight="bold" color="warning.dark">
-                    descricao
+                    codigo_interno
                   </Typography>
                   <Typography variant="body2" color="text.secondary">
-                    Descrição do produto
+                    Código para associar imagens
                   </Typography>
                 </Grid>
               </Grid>
@@ -170,16 +271,26 @@ export const ProductsStep = () => {
                 </Grid>
                 <Grid item xs={12} md={6}>
                   <Typography variant="body2" fontFamily="monospace" fontWeight="bold" color="text.secondary">
-                    codigo_interno
+                    descricao
                   </Typography>
                   <Typography variant="body2" color="text.secondary">
-                    Código para associar imagens (sequencial se vazio)
+                    Descrição do produto (até 5000 caracteres)
                   </Typography>
                 </Grid>
               </Grid>
             </Paper>
 
-            {!canContinue ? (
+            {mappingPreview ? (
+              <ColumnMapping
+                key={`${pendingFile}-${mappingPreview.sheet || ''}`}
+                preview={mappingPreview}
+                savedMapping={wizardData.products.columnMapping}
+                loading={loading}
+                onConfirm={(mapping) => loadFile(pendingFile, mappingPreview.sheet, mapping)}
+                onCancel={() => setMappingPreview(null)}
+                onSheetChange={(sheet) => previewFile(pendingFile, sheet)}
+              />
+            ) : !canContinue ? (
               <Box sx={{ textAlign: 'center', py: 8 }}>
                 <FileTextIcon sx={{ fontSize: 80, color: 'text.disabled', mb: 2 }} />
                 <Typography variant="body1" color="text.secondary" sx={{ mb: 3 }}>
@@ -193,7 +304,7 @@ export const ProductsStep = () => {
                   disabled={loading}
                   startIcon={loading ? <CircularProgress size={20} color="inherit" /> : <Upload />}
                 >
-                  {loading ? 'Processando...' : 'Selecionar Arquivo CSV'}
+                  {loading ? 'Processando...' : 'Selecionar Arquivo (CSV, XLSX ou ODS)'}
                 </Button>
               </Box>
             ) : (
@@ -204,17 +315,102 @@ export const ProductsStep = () => {
                   sx={{ mb: 3 }}
                 >
                   <Typography variant="body2" fontWeight="bold">
-                    {wizardData.products.csvData.length} produto(s) carregado(s) com sucesso!
+                    {wizardData.products.productCount.toLocaleString('pt-BR')} produto(s) carregado(s) com sucesso!
                   </Typography>
                   <Typography variant="body2">
                     Arquivo: {wizardData.products.csvFile?.split('/').pop() || 'arquivo.csv'}
                   </Typography>
+                  {wizardData.products.platform && (
+                    <Typography variant="body2">
+                      Exportação reconhecida: {PLATFORM_NAMES[wizardData.products.platform] || wizardData.products.platform}
+                    </Typography>
+                  )}
                 </Alert>
 
+                {wizardData.products.generatedUuids > 0 && wizardData.products.format === 'csv' && !wizardData.products.platform && (
+                  <Alert
+                    severity="info"
+                    sx={{ mb: 3 }}
+                    action={
+                      <Button color="inherit" size="small" onClick={handleWriteUuids} disabled={loading}>
+                        Salvar cópia com UUIDs
+                      </Button>
+                    }
+                  >
+                    {wizardData.products.generatedUuids} produto(s) receberam um UUID novo. Salve uma cópia do arquivo com
+                    os UUIDs para que uma nova implantação atualize esses produtos em vez de duplicá-los.
+                  </Alert>
+                )}
+
+                {uuidFile && (
+                  <Alert severity="success" sx={{ mb: 3 }}>
+                    Arquivo com UUIDs salvo em {uuidFile}
+                  </Alert>
+                )}
+
+                {wizardData.products.sheets?.length > 1 && (
+                  <TextField
+                    select
+                    size="small"
+                    label="Planilha"
+                    value={wizardData.products.sheet || ''}
+                    onChange={(e) => previewFile(wizardData.products.csvFile, e.target.value)}
+                    disabled={loading}
+                    sx={{ mb: 3, minWidth: 240 }}
+                  >
+                    {wizardData.products.sheets.map((name) => (
+                      <MenuItem key={name} value={name}>{name}</MenuItem>
+                    ))}
+                  </TextField>
+                )}
+
+                {wizardData.products.conflictCount > 0 && (
+                  <DuplicateConflicts
+                    conflicts={wizardData.products.conflicts}
+                    count={wizardData.products.conflictCount}
+                    resolution={wizardData.products.duplicates}
+                    loading={loading}
+                    onResolve={(duplicates) =>
+                      loadFile(
+                        wizardData.products.csvFile,
+                        wizardData.products.sheet,
+                        wizardData.products.columnMapping,
+                        wizardData.products.platform,
+                        duplicates,
+                      )
+                    }
+                  />
+                )}
+
+                {wizardData.products.diagnosticCount > 0 && (
+                  <Alert severity="warning" sx={{ mb: 3 }}>
+                    <Typography variant="body2" fontWeight="bold">
+                      {wizardData.products.diagnosticCount} problema(s) encontrado(s); as linhas afetadas foram ignoradas:
+                    </Typography>
+                    {wizardData.products.diagnostics.slice(0, 10).map((d, index) => (
+                      <Typography key={index} variant="body2" fontFamily="monospace">
+                        Linha {d.line}, {d.column} = "{d.value}": {d.reason}
+                      </Typography>
+                    ))}
+                    {wizardData.products.diagnosticCount > 10 && (
+                      <Typography variant="body2">
+                        ... e mais {wizardData.products.diagnosticCount - 10} problema(s)
+                      </Typography>
+                    )}
+                  </Alert>
+                )}
+
                 <Box sx={{ mb: 3 }}>
                   <Typography variant="subtitle1" fontWeight="bold" sx={{ mb: 2 }}>
-                    Prévia (primeiros 5 produtos):
+                    Produtos importados:
                   </Typography>
+                  {wizardData.products.taxonomy && (
+                    <Typography variant="body2" color="text.secondary" sx={{ mb: 1 }}>
+                      {wizardData.products.taxonomy.categories.length} categoria(s) principal(is),{' '}
+                      {wizardData.products.taxonomy.collections.length} coleção(ões),{' '}
+                      {wizardData.products.taxonomy.tags.length} tag(s)
+                    </Typography>
+                  )}
                   <TableContainer component={Paper} variant="outlined">
                     <Table>
                       <TableHead>
@@ -234,13 +430,20 @@ export const ProductsStep = () => {
                         </TableRow>
                       </TableHead>
                       <TableBody>
-                        {preview.map((product, index) => (
+                        {preview.products.map((product, index) => (
                           <TableRow key={index}>
                             <TableCell sx={{ fontFamily: 'monospace' }}>
                               {product.codigo_interno}
                             </TableCell>
-                            <TableCell>{product.nome}</TableCell>
-                            <TableCell>R$ {product.preco.toFixed(2)}</TableCell>
+                            <TableCell>
+                              {product.nome}
+                              {product.options?.length > 0 && (
+                                <Typography variant="caption" color="text.secondary" display="block">
+                                  {product.variants.length} variante(s): {product.options.map((o) => o.title).join(', ')}
+                                </Typography>
+                              )}
+                            </TableCell>
+                            <TableCell>R$ {(product.preco.centavos / 100).toFixed(2)}</TableCell>
                             <TableCell sx={{ 
                               maxWidth: 300, 
                               overflow: 'hidden',
@@ -254,21 +457,49 @@ export const ProductsStep = () => {
                       </TableBody>
                     </Table>
                   </TableContainer>
-                  {wizardData.products.csvData.length > 5 && (
-                    <Typography variant="body2" color="text.secondary" sx={{ mt: 1 }}>
-                      ... e mais {wizardData.products.csvData.length - 5} produto(s)
-                    </Typography>
-                  )}
+                  <TablePagination
+                    component="div"
+                    count={preview.total}
+                    page={preview.page}
+                    rowsPerPage={preview.pageSize}
+                    rowsPerPageOptions={[20, 50, 100]}
+                    onPageChange={(e, page) => loadPage(page, preview.pageSize)}
+                    onRowsPerPageChange={(e) => loadPage(0, parseInt(e.target.value, 10))}
+                    labelRowsPerPage="Produtos por página"
+                    labelDisplayedRows={({ from, to, count }) => `${from}–${to} de ${count}`}
+                  />
                 </Box>
 
-                <Button
-                  variant="outlined"
-                  size="small"
-                  onClick={handleFileSelect}
-                  startIcon={<Upload />}
-                >
-                  Selecionar outro arquivo
-                </Button>
+                <Stack direction="row" spacing={2}>
+                  <Button
+                    variant="outlined"
+                    size="small"
+                    onClick={handleFileSelect}
+                    startIcon={<Upload />}
+                  >
+                    Selecionar outro arquivo
+                  </Button>
+                  <Button
+                    variant="outlined"
+                    size="small"
+                    onClick={() => previewFile(wizardData.products.csvFile, wizardData.products.sheet)}
+                    disabled={loading}
+                  >
+                    Ajustar colunas
+                  </Button>
+                </Stack>
+              </Box>
+            )}
+
+            {loading && progress && (
+              <Box>
+                <LinearProgress
+                  variant="determinate"
+                  value={progress.totalBytes ? (progress.bytesRead / progress.totalBytes) * 100 : 0}
+                />
+                <Typography variant="caption" color="text.secondary">
+                  {progress.rows.toLocaleString('pt-BR')} linha(s) lida(s)
+                </Typography>
               </Box>
             )}
 
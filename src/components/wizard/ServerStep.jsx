@@ -9,6 +9,8 @@ import {
   Alert,
   CircularProgress,
   Stack,
+  FormControlLabel,
+  Switch,
 } from '@mui/material';
 import { 
   Storage as StorageIcon, 
@@ -77,6 +79,16 @@ export const ServerStep = () => {
               variant="outlined"
             />
 
+            <FormControlLabel
+              control={
+                <Switch
+                  checked={!!wizardData.server.multiTenant}
+                  onChange={(e) => updateWizardData('server', { multiTenant: e.target.checked })}
+                />
+              }
+              label="Servidor compartilhado (várias lojas atrás de um Caddy único)"
+            />
+
             {error && (
               <Alert 
                 severity="error" 
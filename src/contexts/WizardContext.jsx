@@ -1,8 +1,28 @@
 import React, { createContext, useContext, useState, useEffect } from 'react';
+import { invoke } from '@tauri-apps/api/core';
 import { YamlWizardStorage } from '../utils/yamlStorage';
 
 const yamlStorage = new YamlWizardStorage();
 
+// Imported products are kept by the backend, not in the saved state: read
+// the file again when a saved wizard is restored
+const restoreProducts = async (products) => {
+  if (!products?.csvFile || !products.productCount) return;
+  try {
+    await invoke('import_products_file', {
+      path: products.csvFile,
+      options: {
+        sheet: products.sheet || null,
+        mapping: products.platform ? null : products.columnMapping || null,
+        platform: products.platform || null,
+        duplicates: products.duplicates || null,
+      },
+    });
+  } catch (error) {
+    console.error('Error restoring products:', error);
+  }
+};
+
 const WizardContext = createContext();
 
 export const useWizard = () => {
@@ -20,6 +40,7 @@ export const WizardProvider = ({ children }) => {
     server: {
       ip: '',
       domain: '',
+      multiTenant: false,
       connected: false,
     },
     identity: {
@@ -39,7 +60,7 @@ export const WizardProvider = ({ children }) => {
       testMode: true,
     },
     products: {
-      csvData: [],
+      productCount: 0,
       csvFile: null,
     },
     images: {
@@ -68,6 +89,7 @@ export const WizardProvider = ({ children }) => {
           setCurrentStep(state.currentStep);
           setWizardData(state.wizardData);
           setYamlSteps(state.steps);
+          restoreProducts(state.wizardData.products);
         }
       }
     };
@@ -123,7 +145,7 @@ export const WizardProvider = ({ children }) => {
         fontPair: 'Inter + Roboto',
       },
       payment: { mercadoPagoToken: '', webhookSecret: '', testMode: true },
-      products: { csvData: [], csvFile: null },
+      products: { productCount: 0, csvFile: null },
       images: { directory: null, mapping: {} },
       deployment: { status: 'idle', logs: [], url: null },
     });
@@ -138,6 +160,7 @@ export const WizardProvider = ({ children }) => {
       setWizardData(state.wizardData);
       setYamlSteps(state.steps);
       setHasSavedState(true);
+      restoreProducts(state.wizardData.products);
       return true;
     }
     return false;
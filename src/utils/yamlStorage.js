@@ -118,7 +118,7 @@ export class YamlWizardStorage {
             testMode: true,
           },
           products: data.wizardData.products || {
-            csvData: [],
+            productCount: 0,
             csvFile: null,
           },
           images: data.wizardData.images || {